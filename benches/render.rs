@@ -0,0 +1,64 @@
+// Times `Buffer::diff` on a 300x80 terminal against the 16.6 ms a frame gets at
+// 60 fps. Run with `cargo bench`
+use std::{
+    hint::black_box,
+    mem,
+    time::{Duration, Instant},
+};
+
+use crossterm::style::Color;
+use rustmpc::modules::{
+    buffer::{Buffer, Symbol},
+    ui::Rect,
+};
+
+const SCREEN: Rect = Rect {
+    x: 0,
+    y: 0,
+    width: 300,
+    height: 80,
+};
+const FRAMES: u32 = 2000;
+
+// Runs `draw` and a diff for each frame, swapping the buffers like the renderer does
+fn bench(name: &str, mut draw: impl FnMut(&mut Buffer, u32)) {
+    let (mut back, mut front) = (Buffer::new(SCREEN), Buffer::new(SCREEN));
+    let mut patches = vec![];
+    let mut diffing = Duration::ZERO;
+    for frame in 0..FRAMES {
+        draw(&mut back, frame);
+        let start = Instant::now();
+        back.diff(&front, &mut patches);
+        diffing += start.elapsed();
+        black_box(&patches);
+        mem::swap(&mut back, &mut front);
+        back.sync(&mut front);
+    }
+    let per_frame = diffing / FRAMES;
+    println!(
+        "{name:<32} {per_frame:>10.2?} a frame, {:.3}% of a 60 fps frame",
+        per_frame.as_secs_f64() * 60.0 * 100.0
+    );
+}
+
+fn main() {
+    bench("idle", |_, _| {});
+    // The progress bar creeping along the top row
+    bench("one row", |buffer, frame| {
+        let x = (frame % SCREEN.width) as usize;
+        buffer.update(x, 0, |cell| cell.bg = Color::Magenta);
+    });
+    // Every cell drawn again as it was, which is what most frames do
+    bench("every cell unchanged", |buffer, _| {
+        buffer.set_text(SCREEN, &"a".repeat((SCREEN.width * SCREEN.height) as usize));
+    });
+    // Every cell written, like scrolling a full list
+    bench("every cell", |buffer, frame| {
+        let symbol = Symbol::from(char::from(b'a' + (frame % 26) as u8));
+        for y in 0..SCREEN.height as usize {
+            for x in 0..SCREEN.width as usize {
+                buffer.set_symbol(x, y, symbol);
+            }
+        }
+    });
+}
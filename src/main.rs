@@ -1,17 +1,20 @@
-mod modules;
+use std::time::{Duration, Instant};
 
 use anyhow::Result;
-use crossterm::{
-    event::{Event, KeyCode, KeyEvent, KeyModifiers},
-    style::Color,
-};
-use modules::{
+use crossterm::event::Event;
+use rustmpc::modules::{
+    app::App,
     config::Config,
     crossterm::Crossterm,
+    events::{Events, Message},
     mpd::Mpd,
-    ui::{Overflow, Render, UI},
+    signals::{self, Signals},
+    ui::{Render, UI},
 };
 
+// Key timeouts and toasts are checked this often while nothing else happens
+const TICK: Duration = Duration::from_millis(100);
+
 fn main() -> Result<()> {
     stderrlog::new()
         .module(module_path!())
@@ -20,142 +23,64 @@ fn main() -> Result<()> {
         .init()
         .unwrap();
 
-    let conf = Config::default();
-    conf.generate_config()?;
-    let mut mpd = Mpd::new(conf.mpd.get_addr()?);
+    let conf = Config::load()?;
+    let mpd: Mpd = Mpd::new(conf.mpd.get_addr()?);
+    let mut app = App::new(conf, mpd);
+    let signals = Signals::register()?;
+    let frame = Duration::from_secs(1) / app.conf.ui.fps.clamp(1, 1000);
     if let Ok(mut ct) = Crossterm::init() {
+        let mut events = Events::new(TICK);
+        events.watch_input();
+        let addr = app.conf.mpd.get_addr()?;
+        events.watch_mpd(addr);
+        if app.art.is_enabled() {
+            app.art.fetch_with(events.watch_art(addr));
+        }
+        app.update();
+        let mut dirty = true;
+        let mut last_frame: Option<Instant> = None;
         loop {
-            mpd.update_loop();
-            if ct.is_event_ready() {
-                if let Ok(event) = ct.read_event() {
-                    match event {
-                        Event::Key(KeyEvent {
-                            code: KeyCode::Char('q'),
-                            ..
-                        })
-                        | Event::Key(KeyEvent {
-                            code: KeyCode::Char('c'),
-                            modifiers: KeyModifiers::CONTROL,
-                            ..
-                        })
-                        | Event::Key(KeyEvent {
-                            code: KeyCode::Esc, ..
-                        }) => break,
-                        play if conf.keybinds.play_pause.matches(&play) => mpd.toggle_play(),
-                        next if conf.keybinds.next.matches(&next) => mpd.next_song(),
-                        prev if conf.keybinds.prev.matches(&prev) => mpd.prev_song(),
-                        stop if conf.keybinds.stop.matches(&stop) => mpd.stop_playback(),
-                        repeat if conf.keybinds.repeat.matches(&repeat) => mpd.toggle_repeat(),
-                        shuffle if conf.keybinds.shuffle.matches(&shuffle) => mpd.toggle_shuffle(),
-                        vol_up if conf.keybinds.vol_up.matches(&vol_up) => mpd.increase_volume(),
-                        vol_down if conf.keybinds.vol_down.matches(&vol_down) => {
-                            mpd.decrease_volume()
-                        }
-                        clear_queue if conf.keybinds.clear_queue.matches(&clear_queue) => {
-                            mpd.clear_queue()
-                        }
-                        _ => {}
+            if signals.terminated() {
+                break;
+            }
+            // Ctrl-Z arrives as a key in raw mode, and SIGTSTP from elsewhere
+            if app.suspend || signals.take_suspend() {
+                app.suspend = false;
+                ct.destroy()?;
+                signals::stop()?;
+            }
+            if signals.take_resume() {
+                ct.resume()?;
+                dirty = true;
+            }
+            if app.quit {
+                break;
+            }
+            // Drawn at most once a frame, however much came in since the last one
+            if dirty && last_frame.is_none_or(|at| at.elapsed() >= frame) {
+                let screen = ct.screen;
+                app.draw(&mut ct, screen);
+                ct.render_frame()?;
+                dirty = false;
+                last_frame = Some(Instant::now());
+            }
+            let frame_due = last_frame.filter(|_| dirty).map(|at| at + frame);
+            match events.next(frame_due) {
+                Some(Message::Input(event)) => {
+                    if let Event::Resize(width, height) = event {
+                        ct.resize(width.into(), height.into());
                     }
+                    app.handle_event(&event);
+                    dirty = true;
                 }
-            }
-            if let Some(song) = mpd.get_current_playing() {
-                ct.set_background(
-                    modules::ui::Rect {
-                        x: 0,
-                        y: 0,
-                        width: ct.screen.width,
-                        height: ct.screen.height,
-                    },
-                    Color::Reset,
-                );
-                ct.set_foreground(
-                    modules::ui::Rect {
-                        x: 0,
-                        y: 0,
-                        width: ct.screen.width,
-                        height: ct.screen.height,
-                    },
-                    Color::Reset,
-                );
-                ct.set_attributes(
-                    modules::ui::Rect {
-                        x: 0,
-                        y: 0,
-                        width: ct.screen.width,
-                        height: ct.screen.height,
-                    },
-                    crossterm::style::Attribute::NormalIntensity,
-                );
-
-                if let Some((current_time, total_time)) = mpd.get_time() {
-                    ct.set_background(
-                        modules::ui::Rect {
-                            x: 0,
-                            y: 0,
-                            width: (ct.screen.width as f32
-                                * (current_time.as_secs_f32() / total_time.as_secs_f32()))
-                                as u32,
-                            height: ct.screen.height,
-                        },
-                        Color::Rgb {
-                            r: 127,
-                            g: 0,
-                            b: 185,
-                        },
-                    );
-                    ct.set_foreground(
-                        modules::ui::Rect {
-                            x: 0,
-                            y: 0,
-                            width: (ct.screen.width as f32
-                                * (current_time.as_secs_f32() / total_time.as_secs_f32()))
-                                as u32,
-                            height: ct.screen.height,
-                        },
-                        Color::Black,
-                    );
-                    ct.set_attributes(
-                        modules::ui::Rect {
-                            x: 0,
-                            y: 0,
-                            width: (ct.screen.width as f32
-                                * (current_time.as_secs_f32() / total_time.as_secs_f32()))
-                                as u32,
-                            height: ct.screen.height,
-                        },
-                        crossterm::style::Attribute::Bold,
-                    );
-                };
-                ct.set_text(
-                    modules::ui::Rect {
-                        x: 1,
-                        y: 0,
-                        width: ct.screen.width,
-                        height: 1,
-                    },
-                    format!(
-                        "{} - {}",
-                        &song.title.unwrap_or_default(),
-                        &song.artist.unwrap_or_default()
-                    )
-                    .as_str(),
-                    modules::ui::Overflow::Char,
-                );
-                if let Some((current_time, total_time)) = mpd.get_time() {
-                    let dur = format!("{}:{:02}/{}:{:02}", current_time.as_secs()/60, current_time.as_secs()%60, total_time.as_secs()/60, total_time.as_secs()%60);
-                    ct.set_text(
-                        modules::ui::Rect {
-                            x: ct.screen.width - (dur.len() as u32) - 1,
-                            y: 0,
-                            width: dur.len() as u32,
-                            height: 1,
-                        },
-                        dur.as_str(),
-                        Overflow::Char,
-                    );
+                Some(Message::Changed(subsystems)) => {
+                    app.changed(&subsystems);
+                    dirty = true;
                 }
+                Some(Message::Tick) => dirty |= app.tick(),
+                Some(Message::Art(file, image)) => dirty |= app.art.receive(file, image),
+                None => {}
             }
-            ct.render_frame()?;
         }
         let _ = ct.destroy();
     }
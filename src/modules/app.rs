@@ -0,0 +1,1447 @@
+use std::{
+    fs,
+    path::Path,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use crossterm::{
+    event::{Event, KeyCode, KeyEvent, KeyModifiers, MouseButton, MouseEvent, MouseEventKind},
+    style::{Attribute, Color},
+};
+use mpd::{idle::Subsystem, Song, State};
+use serde::{Deserialize, Serialize};
+use unicode_width::UnicodeWidthStr;
+
+use super::{
+    art::ArtPanel,
+    command::{adjust_time, parse, Action, Adjust, CommandLine, ExCommand, Setting},
+    config::{Config, Keymaps},
+    keymap::{KeyState, Keymap, Mode, Step},
+    library::Library,
+    list::{album, ListState},
+    lyrics::{CommandProvider, LyricsPanel, Provider},
+    modes::ModesMenu,
+    mpd::{Backend, Connection, Mpd, MpdError, MpdResult, OneshotMode},
+    overlay::Overlay,
+    paste,
+    playlists::Playlists,
+    popup::{Popup, PopupKind},
+    progress,
+    queue::Queue,
+    tabs,
+    tags::{self, Change, Field, TagEditor},
+    toast::{Severity, Toasts},
+    ui::{Overflow, Rect, Render},
+};
+
+// Seconds the seek actions jump by
+const SEEK_STEP: u32 = 5;
+// Changes come from MPD's idle, so the status is only fetched this often to
+// retry the connection
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+// Otherwise just often enough that MPD's connection_timeout, 60s by default,
+// doesn't close the connection
+const KEEPALIVE: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum View {
+    Queue,
+    Library,
+    Playlists,
+    Lyrics,
+}
+
+impl View {
+    pub fn name(self) -> &'static str {
+        match self {
+            View::Queue => "Queue",
+            View::Library => "Library",
+            View::Playlists => "Playlists",
+            View::Lyrics => "Lyrics",
+        }
+    }
+}
+
+// Actions waiting on an answer from a popup
+pub enum PopupAction {
+    Dismiss,
+    ClearQueue,
+    DeleteSongs,
+    CropQueue,
+    SaveQueue,
+    AddSongs(Vec<String>),
+    RenamePlaylist(String),
+    SetTag(Field),
+    WriteTags(Vec<Change>),
+    DeletePlaylist(String),
+    DeletePlaylistSongs,
+    ReplaceQueue(String),
+    QueueUris(Vec<String>),
+}
+
+// State of the whole client, shared by key handling, the command line and scripts
+pub struct App<B: Backend = Connection> {
+    pub mpd: Mpd<B>,
+    pub conf: Config,
+    pub view: View,
+    pub queue: Queue,
+    pub library: Library,
+    pub playlists: Playlists,
+    pub lyrics: LyricsPanel,
+    pub art: ArtPanel,
+    pub popup: Option<Popup<PopupAction>>,
+    pub overlay: Option<Overlay>,
+    pub tags: Option<TagEditor>,
+    pub modes: Option<ModesMenu>,
+    pub command_line: CommandLine,
+    pub search: CommandLine,
+    // What actions did or why they failed, shown for a few seconds each
+    pub toasts: Toasts,
+    // Whether MPD was reachable at the last update
+    connected: bool,
+    last_poll: Instant,
+    pub volume_slider: bool,
+    last_search: Option<String>,
+    keys: KeyState,
+    pub quit: bool,
+    // Set until the main loop has handed the terminal back and stopped the process
+    pub suspend: bool,
+}
+
+// Sheets over the views that bring a keymap of their own
+#[derive(Clone, Copy)]
+enum Sheet {
+    Tags,
+    Modes,
+}
+
+fn keymaps(keymaps: &Keymaps, view: View, mode: Mode, sheet: Option<Sheet>) -> Vec<&Keymap> {
+    match mode {
+        Mode::Normal if matches!(sheet, Some(Sheet::Modes)) => {
+            vec![&keymaps.modes, &keymaps.normal]
+        }
+        Mode::Normal if matches!(sheet, Some(Sheet::Tags)) => vec![&keymaps.tags, &keymaps.normal],
+        Mode::Normal => {
+            let view = match view {
+                View::Queue => &keymaps.queue,
+                View::Library => &keymaps.library,
+                View::Playlists => &keymaps.playlists,
+                View::Lyrics => &keymaps.lyrics,
+            };
+            vec![view, &keymaps.normal]
+        }
+        Mode::Search => vec![&keymaps.search],
+        Mode::Command => vec![&keymaps.command],
+        Mode::Popup => vec![&keymaps.popup],
+    }
+}
+
+// Lowercase text that searches match against
+fn song_label(song: &Song) -> String {
+    [
+        song.title.as_deref(),
+        song.artist.as_deref(),
+        album(song),
+        Some(song.file.as_str()),
+    ]
+    .into_iter()
+    .flatten()
+    .collect::<Vec<_>>()
+    .join(" ")
+    .to_lowercase()
+}
+
+fn selected_songs(list: &ListState, songs: &[Song]) -> Vec<Song> {
+    list.targets(songs.len())
+        .into_iter()
+        .map(|idx| songs[idx].clone())
+        .collect()
+}
+
+fn tag_value<'a>(song: &'a Song, tag: &str) -> Option<&'a str> {
+    match tag {
+        "file" => Some(song.file.as_str()),
+        "title" => song.title.as_deref(),
+        "artist" => song.artist.as_deref(),
+        _ => song
+            .tags
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(tag))
+            .map(|(_, value)| value.as_str()),
+    }
+}
+
+impl<B: Backend> App<B> {
+    pub fn new(mut conf: Config, mut mpd: Mpd<B>) -> Self {
+        conf.ui.tabs = tabs::normalize(&conf.ui.tabs);
+        let view = conf.ui.tabs[0];
+        mpd.set_history_depth(conf.queue.history_depth);
+        let provider = (!conf.lyrics.fetch_command.is_empty()).then(|| {
+            Arc::new(CommandProvider(conf.lyrics.fetch_command.clone())) as Arc<dyn Provider>
+        });
+        let lyrics = LyricsPanel::new(conf.mpd.music_dir(), conf.lyrics.cache_dir(), provider);
+        let art = ArtPanel::new(conf.art.protocol);
+        let conflicts = conf.keymaps.conflicts();
+        let connected = mpd.is_connected();
+        let popup = if !connected {
+            Some(Popup::message(
+                "Could not connect to MPD",
+                "Retrying in the background",
+                PopupAction::Dismiss,
+            ))
+        } else if let Some(conflict) = conflicts.first() {
+            let title = match conflicts.len() {
+                1 => "Key conflict".to_owned(),
+                len => format!("{len} key conflicts"),
+            };
+            Some(Popup::message(title, conflict, PopupAction::Dismiss))
+        } else {
+            None
+        };
+        Self {
+            mpd,
+            conf,
+            view,
+            queue: Queue::default(),
+            library: Library::default(),
+            playlists: Playlists::default(),
+            lyrics,
+            art,
+            popup,
+            overlay: None,
+            tags: None,
+            modes: None,
+            command_line: CommandLine::new(':'),
+            search: CommandLine::new('/'),
+            toasts: Toasts::default(),
+            connected,
+            last_poll: Instant::now(),
+            volume_slider: false,
+            last_search: None,
+            keys: KeyState::default(),
+            quit: false,
+            suspend: false,
+        }
+    }
+
+    // Runs the timers between events: unfinished key sequences, toasts running out
+    // and the periodic poll. Returns whether the screen needs drawing again
+    pub fn tick(&mut self) -> bool {
+        let mode = self.mode();
+        let maps = keymaps(&self.conf.keymaps, self.view, mode, self.sheet());
+        let timeout = Duration::from_millis(self.conf.keymaps.timeout);
+        // Unfinished keys are shown, so dropping them counts as a change as well
+        let pending = self.keys.pending();
+        let mut changed = false;
+        if let Some(step) =
+            self.keys
+                .tick(&maps, &self.conf.keymaps.leader, timeout, Instant::now())
+        {
+            self.apply(step, mode, None);
+            changed = true;
+        }
+        changed |= self.keys.pending() != pending;
+        changed |= self.toasts.expire();
+        let interval = if self.connected {
+            KEEPALIVE
+        } else {
+            POLL_INTERVAL
+        };
+        if self.last_poll.elapsed() >= interval {
+            changed |= self.update();
+        }
+        // The elapsed time moves along between status replies
+        let playing = self
+            .mpd
+            .get_status()
+            .is_some_and(|status| status.state == State::Play);
+        // A lyrics fetch may finish whatever the player is doing
+        let fetching = self.lyrics.is_fetching();
+        if fetching || playing && self.view == View::Lyrics {
+            self.sync_lyrics();
+        }
+        changed | playing | (fetching && !self.lyrics.is_fetching())
+    }
+
+    // Brings in what MPD's idle reported as changed. Nothing reported comes with
+    // each (re)connect of the idle connection, when any change may have been missed
+    pub fn changed(&mut self, subsystems: &[Subsystem]) {
+        let all = subsystems.is_empty();
+        if all || subsystems.contains(&Subsystem::Playlist) {
+            self.playlists.reload();
+        }
+        // The queue version and database update time come with the status too
+        let status = all
+            || subsystems.iter().any(|subsystem| {
+                matches!(
+                    subsystem,
+                    Subsystem::Queue
+                        | Subsystem::Player
+                        | Subsystem::Mixer
+                        | Subsystem::Options
+                        | Subsystem::Database
+                        | Subsystem::Update
+                )
+            });
+        if status {
+            self.update();
+        } else {
+            self.playlists.sync(&mut self.mpd);
+        }
+    }
+
+    // Fetches the state of MPD and brings the views up to date with it. Returns
+    // whether anything shown may have changed
+    pub fn update(&mut self) -> bool {
+        self.last_poll = Instant::now();
+        let before = (
+            self.mpd.get_status().cloned(),
+            self.mpd.get_single(),
+            self.mpd.get_consume(),
+        );
+        self.mpd.update_loop();
+        let connected = self.mpd.is_connected();
+        if connected != self.connected {
+            self.connected = connected;
+            if connected {
+                self.notify("Connected to MPD");
+            } else {
+                self.toasts
+                    .push(Severity::Error, "Lost connection to MPD, reconnecting");
+            }
+        }
+        self.queue.sync(&mut self.mpd);
+        self.library.sync(&mut self.mpd);
+        self.playlists.sync(&mut self.mpd);
+        if let Some(menu) = &mut self.modes {
+            menu.refresh(&self.mpd);
+        }
+        match self.view {
+            View::Lyrics => self.sync_lyrics(),
+            View::Queue => {
+                let pos = self.mpd.get_status().and_then(|status| status.song);
+                let song = pos.and_then(|place| self.queue.songs.get(place.pos as usize));
+                self.art.sync(song);
+            }
+            _ => {}
+        }
+        let after = (
+            self.mpd.get_status().cloned(),
+            self.mpd.get_single(),
+            self.mpd.get_consume(),
+        );
+        // Lyrics arrive from their fetch and scroll along on their own
+        before != after || self.view == View::Lyrics
+    }
+
+    fn sync_lyrics(&mut self) {
+        let pos = self.mpd.get_status().and_then(|status| status.song);
+        let song = pos.and_then(|place| self.queue.songs.get(place.pos as usize));
+        let elapsed = self.mpd.get_time().map(|(elapsed, _)| elapsed);
+        self.lyrics.sync(song, elapsed);
+    }
+
+    fn show_error(&mut self, title: &str, error: anyhow::Error) {
+        self.popup = Some(Popup::message(
+            title,
+            format!("{error:#}"),
+            PopupAction::Dismiss,
+        ));
+    }
+
+    fn notify(&mut self, text: impl Into<String>) {
+        self.toasts.push(Severity::Info, text);
+    }
+
+    // Shows why a command did not go through
+    fn report<T>(&mut self, result: MpdResult<T>) -> Option<T> {
+        result
+            .map_err(|err| {
+                let severity = match err {
+                    MpdError::Server { .. } | MpdError::Connection { .. } => Severity::Error,
+                    _ => Severity::Warning,
+                };
+                self.toasts.push(severity, err.to_string());
+            })
+            .ok()
+    }
+
+    // The menu can only be opened outside the tag editor, so it is on top
+    fn sheet(&self) -> Option<Sheet> {
+        if self.modes.is_some() {
+            Some(Sheet::Modes)
+        } else if self.tags.is_some() {
+            Some(Sheet::Tags)
+        } else {
+            None
+        }
+    }
+
+    pub fn mode(&self) -> Mode {
+        if self.popup.is_some() {
+            Mode::Popup
+        } else if self.command_line.is_open() {
+            Mode::Command
+        } else if self.search.is_open() {
+            Mode::Search
+        } else {
+            Mode::Normal
+        }
+    }
+
+    pub fn handle_event(&mut self, event: &Event) {
+        let mode = self.mode();
+        if let Event::Paste(text) = event {
+            self.paste(text, mode);
+            return;
+        }
+        // A click on the tab bar switches to that tab
+        if let Event::Mouse(MouseEvent {
+            kind: MouseEventKind::Down(MouseButton::Left),
+            column,
+            row: 0,
+            ..
+        }) = event
+        {
+            if mode == Mode::Normal {
+                if let Some(view) = tabs::at(&self.conf.ui.tabs, 0, u32::from(*column)) {
+                    self.view = view;
+                }
+            }
+            return;
+        }
+        let maps = keymaps(&self.conf.keymaps, self.view, mode, self.sheet());
+        let step = self.keys.feed(
+            event,
+            &maps,
+            &self.conf.keymaps.leader,
+            mode == Mode::Normal,
+            Instant::now(),
+        );
+        self.apply(step, mode, Some(event));
+    }
+
+    fn apply(&mut self, step: Step, mode: Mode, event: Option<&Event>) {
+        let handled = match step {
+            Step::Pending => true,
+            Step::Unbound(_) => false,
+            // A key bound to several actions runs the first one that fits
+            Step::Run(actions, count) => actions.into_iter().any(|action| match mode {
+                Mode::Normal => self.run_counted(action, count),
+                Mode::Search | Mode::Command => self.run_line(action, mode),
+                Mode::Popup => self.run_popup(action),
+            }),
+        };
+        // Keys that did nothing are typed into popups and prompts
+        let Some(Event::Key(KeyEvent {
+            code: KeyCode::Char(char),
+            modifiers,
+            ..
+        })) = event
+        else {
+            return;
+        };
+        if handled || !(KeyModifiers::SHIFT).contains(*modifiers) {
+            return;
+        }
+        match mode {
+            Mode::Normal => {}
+            Mode::Search => self.search.insert(*char),
+            Mode::Command => self.command_line.insert(*char),
+            Mode::Popup => {
+                if let Some(popup) = &mut self.popup {
+                    popup.insert(*char);
+                }
+            }
+        }
+    }
+
+    // Pasted text is typed into prompts and popups. Otherwise each line is taken as
+    // something to queue, shown for confirmation first
+    fn paste(&mut self, text: &str, mode: Mode) {
+        // Prompts hold a single line, so line breaks become spaces
+        let chars = text
+            .trim_end()
+            .chars()
+            .map(|char| if char == '\n' { ' ' } else { char })
+            .filter(|char| !char.is_control());
+        match mode {
+            Mode::Search => chars.for_each(|char| self.search.insert(char)),
+            Mode::Command => chars.for_each(|char| self.command_line.insert(char)),
+            Mode::Popup => {
+                if let Some(popup) = &mut self.popup {
+                    for char in chars {
+                        popup.insert(char);
+                    }
+                }
+            }
+            Mode::Normal => {
+                let music_dir = self.conf.mpd.music_dir();
+                let (uris, rejected) = paste::uris(text, music_dir.as_deref());
+                let title = match uris.len() {
+                    0 => {
+                        let text = match music_dir {
+                            _ if rejected.is_empty() => return,
+                            Some(_) => "Nothing pasted is a URL or a file in the music directory",
+                            None => "Only URLs can be pasted while mpd.music_dir is not set",
+                        };
+                        self.toasts.push(Severity::Warning, text);
+                        return;
+                    }
+                    1 => "Add the pasted item to the queue?".to_owned(),
+                    len => format!("Add {len} pasted items to the queue?"),
+                };
+                self.overlay = Some(paste::preview(&uris, &rejected));
+                self.popup = Some(Popup::confirm(title, PopupAction::QueueUris(uris)));
+            }
+        }
+    }
+
+    // Applies the count typed before an action
+    fn run_counted(&mut self, action: Action, count: Option<usize>) -> bool {
+        match (action, count) {
+            (Action::Top | Action::Bottom, Some(count)) => {
+                let (list, len) = self.active_list();
+                list.jump(count.saturating_sub(1), len);
+                true
+            }
+            // The count picks a playback mode's value by its number
+            (Action::ChooseValue, Some(count)) if self.overlay.is_none() => {
+                let Some(menu) = &mut self.modes else {
+                    return false;
+                };
+                let result = menu.choose(&mut self.mpd, Some(count));
+                self.report(result);
+                true
+            }
+            (action, Some(count)) if action.repeats() => {
+                (0..count).fold(false, |ran, _| self.run(action) || ran)
+            }
+            (action, _) => self.run(action),
+        }
+    }
+
+    fn run_line(&mut self, action: Action, mode: Mode) -> bool {
+        let line = match mode {
+            Mode::Search => &mut self.search,
+            _ => &mut self.command_line,
+        };
+        match action {
+            Action::Cancel => line.close(),
+            Action::DeleteChar => line.delete_char(),
+            Action::HistoryPrev => line.recall(true),
+            Action::HistoryNext => line.recall(false),
+            Action::Complete if mode == Mode::Command => line.complete(),
+            Action::Submit if mode == Mode::Command => {
+                if let Some(line) = line.submit() {
+                    if let Err(error) = self.execute(&line) {
+                        self.show_error("Command failed", error);
+                    }
+                }
+            }
+            // An empty search repeats the last one
+            Action::Submit => {
+                if let Some(query) = line.submit().or_else(|| self.last_search.take()) {
+                    self.find(&query);
+                    self.last_search = Some(query);
+                }
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    fn run_popup(&mut self, action: Action) -> bool {
+        let Some(popup) = &mut self.popup else {
+            return false;
+        };
+        match (action, &popup.kind) {
+            (Action::Cancel, _)
+            | (Action::Deny | Action::Submit, PopupKind::Message(_))
+            | (Action::Deny, PopupKind::Confirm) => {
+                // The preview of a paste goes along with it
+                if matches!(popup.action, PopupAction::QueueUris(_)) {
+                    self.overlay = None;
+                }
+                self.popup = None;
+            }
+            (Action::Submit, _) | (Action::Confirm, PopupKind::Confirm) => {
+                if let Some(popup) = self.popup.take() {
+                    let text = popup.text().to_owned();
+                    self.run_popup_action(popup.action, &text);
+                    self.playlists.reload();
+                }
+            }
+            (Action::DeleteChar, _) => return popup.delete_char(),
+            _ => return false,
+        }
+        true
+    }
+
+    // Moves the cursor to the next item containing `query`, wrapping around
+    fn find(&mut self, query: &str) {
+        let query = query.to_lowercase();
+        let labels = match (self.view, &self.playlists.tracks) {
+            (View::Queue, _) => self.queue.songs.iter().map(song_label).collect(),
+            (View::Library, _) => self.library.songs.iter().map(song_label).collect(),
+            (View::Playlists, Some(tracks)) => tracks.songs.iter().map(song_label).collect(),
+            (View::Lyrics, _) => self.lyrics.lyrics().map_or(vec![], |lyrics| {
+                lyrics
+                    .lines
+                    .iter()
+                    .map(|line| line.text.to_lowercase())
+                    .collect()
+            }),
+            (View::Playlists, None) => self
+                .playlists
+                .playlists
+                .iter()
+                .map(|(playlist, _)| playlist.name.to_lowercase())
+                .collect::<Vec<_>>(),
+        };
+        let (list, len) = self.active_list();
+        let found = (1..=len)
+            .map(|offset| (list.cursor + offset) % len)
+            .find(|idx| labels.get(*idx).is_some_and(|label| label.contains(&query)));
+        match found {
+            Some(idx) => list.jump(idx, len),
+            None => self.show_error("Search", anyhow!("Pattern not found: {query}")),
+        }
+    }
+
+    fn active_list(&mut self) -> (&mut ListState, usize) {
+        if let Some(overlay) = &mut self.overlay {
+            return (&mut overlay.list, overlay.lines.len());
+        }
+        if let Some(menu) = &mut self.modes {
+            return (&mut menu.sheet.list, menu.sheet.lines.len());
+        }
+        if let Some(editor) = &mut self.tags {
+            return (&mut editor.sheet.list, editor.sheet.lines.len());
+        }
+        match self.view {
+            View::Queue => (&mut self.queue.list, self.queue.songs.len()),
+            View::Library => (&mut self.library.list, self.library.songs.len()),
+            View::Playlists => self.playlists.active_list(),
+            View::Lyrics => self.lyrics.active_list(),
+        }
+    }
+
+    // Runs `action` and returns false if it does not apply to the current view
+    pub fn run(&mut self, action: Action) -> bool {
+        if self.volume_slider {
+            return self.run_slider(action);
+        }
+        if self.overlay.is_some() {
+            return self.run_overlay(action);
+        }
+        if self.modes.is_some() {
+            return self.run_modes(action);
+        }
+        if self.tags.is_some() {
+            return self.run_tags(action);
+        }
+        if let Some(result) = self.run_mpd(action) {
+            self.report(result);
+            return true;
+        }
+        match (action, self.view) {
+            (Action::Quit, _) => self.quit = true,
+            (Action::Suspend, _) => self.suspend = true,
+            (Action::Help, _) => self.overlay = Some(Overlay::help(&self.conf.keymaps)),
+            (Action::Errors, _) => self.overlay = Some(self.toasts.history_sheet()),
+            (Action::SongInfo, _) => return self.show_song_info(),
+            (Action::EditTags, view) => return self.edit_tags(view),
+            (Action::CommandLine, _) => self.command_line.open(),
+            (Action::Search, _) => self.search.open(),
+            (Action::PlaybackModes, _) => self.modes = Some(ModesMenu::new(&self.mpd)),
+            (
+                Action::VolUp
+                | Action::VolDown
+                | Action::VolUpFine
+                | Action::VolDownFine
+                | Action::Mute,
+                _,
+            ) => return self.run_volume(action),
+            (Action::VolumeSlider, _) => self.volume_slider = true,
+            (Action::Up, View::Queue) => self.queue.cursor_up(),
+            (Action::Up, View::Library) => self.library.cursor_up(),
+            (Action::Up, View::Playlists) => self.playlists.cursor_up(),
+            (Action::Up, View::Lyrics) => self.lyrics.cursor_up(),
+            (Action::Down, View::Queue) => self.queue.cursor_down(),
+            (Action::Down, View::Library) => self.library.cursor_down(),
+            (Action::Down, View::Playlists) => self.playlists.cursor_down(),
+            (Action::Down, View::Lyrics) => self.lyrics.cursor_down(),
+            (Action::Top, _) => self.active_list().0.jump(0, 1),
+            (Action::Bottom, _) => {
+                let (list, len) = self.active_list();
+                list.jump(len.saturating_sub(1), len)
+            }
+            (Action::SwitchView | Action::PrevTab, view) => {
+                let step = if action == Action::PrevTab { -1 } else { 1 };
+                if let Some(view) = tabs::cycle(&self.conf.ui.tabs, view, step) {
+                    self.view = view;
+                }
+            }
+            (
+                Action::Tab1
+                | Action::Tab2
+                | Action::Tab3
+                | Action::Tab4
+                | Action::Tab5
+                | Action::Tab6
+                | Action::Tab7
+                | Action::Tab8
+                | Action::Tab9,
+                _,
+            ) => match action.tab().and_then(|idx| self.conf.ui.tabs.get(idx)) {
+                Some(view) => self.view = *view,
+                None => return false,
+            },
+            (Action::Lyrics, View::Lyrics) => self.view = View::Queue,
+            (Action::Lyrics, _) => self.view = View::Lyrics,
+            (Action::ToggleMark, _) => {
+                let (list, len) = self.active_list();
+                list.toggle_mark(len)
+            }
+            (Action::VisualMode, _) => self.active_list().0.toggle_visual(),
+            (Action::SelectAll, _) => {
+                let (list, len) = self.active_list();
+                list.select_all(len)
+            }
+            (Action::InvertSelection, _) => {
+                let (list, len) = self.active_list();
+                list.invert_selection(len)
+            }
+            (Action::SelectAlbum, View::Queue) => self.queue.select_album(),
+            (Action::SelectAlbum, View::Library) => self.library.select_album(),
+            (Action::SelectAlbum, View::Playlists) => {}
+            (Action::ClearSelection, _) => self.active_list().0.clear_selection(),
+            (Action::SavePlaylist, _) => {
+                self.popup = Some(Popup::input(
+                    "Save queue as playlist",
+                    "",
+                    PopupAction::SaveQueue,
+                ))
+            }
+            (Action::AddToPlaylist, view @ (View::Queue | View::Library)) => {
+                let files = match view {
+                    View::Library => self.library.selected_files(),
+                    _ => self.queue.selected_files(),
+                };
+                if !files.is_empty() {
+                    self.popup = Some(Popup::input(
+                        format!("Add {} songs to playlist", files.len()),
+                        "",
+                        PopupAction::AddSongs(files),
+                    ));
+                }
+            }
+            (Action::PlaySelected, View::Playlists) => self.playlists.open_selected(&mut self.mpd),
+            (Action::Back, View::Playlists) => self.playlists.close(),
+            (Action::RenamePlaylist, View::Playlists) => {
+                if let Some(name) = self.playlists.selected_name() {
+                    self.popup = Some(Popup::input(
+                        format!("Rename \"{name}\" to"),
+                        name,
+                        PopupAction::RenamePlaylist(name.to_owned()),
+                    ))
+                }
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    // Actions that send commands to MPD, with the outcome for `report`
+    fn run_mpd(&mut self, action: Action) -> Option<MpdResult<()>> {
+        let mpd = &mut self.mpd;
+        let confirm = &self.conf.confirm;
+        let seek_step = Duration::from_secs(SEEK_STEP.into());
+        let result = match (action, self.view) {
+            (Action::PlayPause, _) => mpd.toggle_play(),
+            (Action::Next, _) => mpd.next_song(),
+            (Action::Prev, _) => mpd.prev_song(),
+            (Action::Stop, _) => mpd.stop_playback(),
+            (Action::Repeat, _) => mpd.toggle_repeat(),
+            (Action::Random, _) => mpd.toggle_random(),
+            (Action::Single, _) => mpd.cycle_single(),
+            (Action::Consume, _) => mpd.cycle_consume(),
+            (Action::Shuffle, _) => mpd
+                .shuffle_queue(None)
+                .map(|_| self.notify("Shuffled the queue")),
+            (Action::SeekForward, _) => mpd.forward(seek_step),
+            (Action::SeekBack, _) => mpd.rewind(seek_step),
+            (Action::ClearQueue, _) => {
+                let len = mpd.get_status().map_or(0, |status| status.queue_len);
+                if confirm.clear_queue && len > 0 {
+                    self.popup = Some(Popup::confirm(
+                        format!("Clear {len} songs from queue?"),
+                        PopupAction::ClearQueue,
+                    ));
+                    Ok(())
+                } else {
+                    mpd.clear_queue()
+                }
+            }
+            (Action::Undo, _) => mpd.undo().map(|done| {
+                if !done {
+                    self.notify("Nothing to undo")
+                }
+            }),
+            (Action::Redo, _) => mpd.redo().map(|done| {
+                if !done {
+                    self.notify("Nothing to redo")
+                }
+            }),
+            (Action::ShuffleSelected, View::Queue) => {
+                self.queue.shuffle_selected(mpd).map(|count| match count {
+                    0 => self.notify("Select songs to shuffle"),
+                    count => self.notify(format!("Shuffled {count} songs")),
+                })
+            }
+            (Action::ShuffleRest, View::Queue) => {
+                self.queue.shuffle_rest(mpd).map(|count| match count {
+                    0 => self.notify("Nothing to shuffle after the current song"),
+                    count => self.notify(format!("Shuffled {count} upcoming songs")),
+                })
+            }
+            (Action::AddToQueue, View::Library) => self.library.add_selected(mpd),
+            (Action::PlaySelected, View::Queue) => self.queue.play_selected(mpd),
+            (Action::DeleteSong, View::Queue) => {
+                let count = self.queue.list.targets(self.queue.songs.len()).len();
+                if confirm.delete_songs && count > 0 {
+                    self.popup = Some(Popup::confirm(
+                        format!("Delete {count} songs from queue?"),
+                        PopupAction::DeleteSongs,
+                    ));
+                    Ok(())
+                } else {
+                    self.queue.delete_selected(mpd)
+                }
+            }
+            (Action::MoveUp, View::Queue) => self.queue.move_up(mpd),
+            (Action::MoveDown, View::Queue) => self.queue.move_down(mpd),
+            (Action::MoveToTop, View::Queue) => self.queue.move_selected(mpd, 0),
+            (Action::MoveAfterCurrent, View::Queue) => self.queue.move_after_current(mpd),
+            (Action::CropQueue, View::Queue) => {
+                let count = self.queue.list.targets(self.queue.songs.len()).len();
+                if confirm.crop_queue && count > 0 {
+                    self.popup = Some(Popup::confirm(
+                        format!(
+                            "Remove {} songs from queue?",
+                            self.queue.songs.len() - count
+                        ),
+                        PopupAction::CropQueue,
+                    ));
+                    Ok(())
+                } else {
+                    self.queue.crop_to_selected(mpd)
+                }
+            }
+            (Action::Priority, View::Queue) => self.queue.toggle_priority(mpd),
+            // Seeks to the line under the cursor and follows along from there
+            (Action::PlaySelected, View::Lyrics) => match self.lyrics.selected_time() {
+                Some(time) => mpd.seek(time).map(|_| self.lyrics.follow = true),
+                None => Ok(()),
+            },
+            (Action::AddToQueue, View::Playlists) => match self.playlists.selected_name() {
+                Some(name) => mpd.load_playlist(name),
+                None => Ok(()),
+            },
+            (Action::LoadPlaylist, View::Playlists) => match self.playlists.selected_name() {
+                Some(name) if confirm.replace_queue => {
+                    self.popup = Some(Popup::confirm(
+                        format!("Replace the queue with \"{name}\"?"),
+                        PopupAction::ReplaceQueue(name.to_owned()),
+                    ));
+                    Ok(())
+                }
+                Some(name) => mpd.replace_with_playlist(name),
+                None => Ok(()),
+            },
+            (Action::DeleteSong, View::Playlists) => {
+                match (&self.playlists.tracks, self.playlists.selected_name()) {
+                    (Some(tracks), _) if confirm.delete_playlist_songs => {
+                        let count = tracks.list.targets(tracks.songs.len()).len();
+                        if count > 0 {
+                            self.popup = Some(Popup::confirm(
+                                format!("Delete {count} songs from \"{}\"?", tracks.name),
+                                PopupAction::DeletePlaylistSongs,
+                            ));
+                        }
+                        Ok(())
+                    }
+                    (Some(_), _) => self.playlists.delete_tracks(mpd),
+                    (None, Some(name)) if confirm.delete_playlist => {
+                        self.popup = Some(Popup::confirm(
+                            format!("Delete playlist \"{name}\"?"),
+                            PopupAction::DeletePlaylist(name.to_owned()),
+                        ));
+                        Ok(())
+                    }
+                    (None, Some(name)) => {
+                        let name = name.to_owned();
+                        let result = mpd.delete_playlist(&name);
+                        self.playlists.reload();
+                        result
+                    }
+                    (None, None) => Ok(()),
+                }
+            }
+            (Action::MoveUp, View::Playlists) => self.playlists.shift_tracks(mpd, true),
+            (Action::MoveDown, View::Playlists) => self.playlists.shift_tracks(mpd, false),
+            _ => return None,
+        };
+        Some(result)
+    }
+
+    // Details of the song under the cursor, or of the playing one in views without songs
+    fn show_song_info(&mut self) -> bool {
+        let status = self.mpd.get_status();
+        let playing = status
+            .and_then(|status| status.song)
+            .and_then(|place| self.queue.songs.get(place.pos as usize));
+        let selected = match (self.view, &self.playlists.tracks) {
+            (View::Queue, _) => self.queue.selected(),
+            (View::Library, _) => self.library.selected(),
+            (View::Playlists, Some(tracks)) => tracks.songs.get(tracks.list.cursor),
+            _ => None,
+        };
+        let Some(song) = selected.or(playing) else {
+            return false;
+        };
+        // Songs picked outside the queue show where they are queued, if they are
+        let place = song.place.or_else(|| {
+            self.queue
+                .songs
+                .iter()
+                .find(|queued| queued.file == song.file)
+                .and_then(|queued| queued.place)
+        });
+        self.overlay = Some(Overlay::song_info(song, place, status));
+        true
+    }
+
+    // Volume keys only say so when MPD has no mixer to change the volume with
+    fn run_volume(&mut self, action: Action) -> bool {
+        let conf = &self.conf.volume;
+        let (step, fine) = (i16::from(conf.step), i16::from(conf.fine_step));
+        let result = match action {
+            Action::VolUp => self.mpd.change_volume(step),
+            Action::VolDown => self.mpd.change_volume(-step),
+            Action::VolUpFine => self.mpd.change_volume(fine),
+            Action::VolDownFine => self.mpd.change_volume(-fine),
+            Action::Mute => self.mpd.toggle_mute(),
+            _ => return false,
+        };
+        self.report(result);
+        true
+    }
+
+    // The slider takes the cursor keys to change the volume until it is closed
+    fn run_slider(&mut self, action: Action) -> bool {
+        match action {
+            Action::Up => self.run_volume(Action::VolUp),
+            Action::Down => self.run_volume(Action::VolDown),
+            Action::Top | Action::Bottom => {
+                let result = self
+                    .mpd
+                    .set_volume(if action == Action::Top { 100 } else { 0 });
+                self.report(result);
+                true
+            }
+            Action::VolumeSlider | Action::Quit | Action::Back | Action::Cancel => {
+                self.volume_slider = false;
+                true
+            }
+            action => self.run_volume(action),
+        }
+    }
+
+    // Opens the tag editor on the selected songs
+    fn edit_tags(&mut self, view: View) -> bool {
+        let songs = match (view, &self.playlists.tracks) {
+            (View::Queue, _) => selected_songs(&self.queue.list, &self.queue.songs),
+            (View::Library, _) => selected_songs(&self.library.list, &self.library.songs),
+            (View::Playlists, Some(tracks)) => selected_songs(&tracks.list, &tracks.songs),
+            _ => return false,
+        };
+        if songs.is_empty() {
+            return false;
+        }
+        if self.conf.mpd.music_dir().is_none() {
+            self.show_error(
+                "Cannot edit tags",
+                anyhow!("Set mpd.music_dir to where MPD reads the music from"),
+            );
+        } else {
+            self.tags = Some(TagEditor::new(songs));
+        }
+        true
+    }
+
+    // Cursor movement in overlays, which take the keys of the view below
+    fn move_in_overlay(&mut self, action: Action) -> bool {
+        let (list, len) = self.active_list();
+        match action {
+            Action::Up => list.up(),
+            Action::Down => list.down(len),
+            Action::Top => list.jump(0, len),
+            Action::Bottom => list.jump(len.saturating_sub(1), len),
+            _ => return false,
+        }
+        true
+    }
+
+    // Overlays scroll and close, and leave everything else alone
+    fn run_overlay(&mut self, action: Action) -> bool {
+        match action {
+            Action::Help
+            | Action::SongInfo
+            | Action::Errors
+            | Action::Quit
+            | Action::Back
+            | Action::Cancel => self.overlay = None,
+            _ => return self.move_in_overlay(action),
+        }
+        true
+    }
+
+    fn run_modes(&mut self, action: Action) -> bool {
+        let Some(menu) = &mut self.modes else {
+            return false;
+        };
+        match action {
+            Action::ChooseValue => {
+                let result = menu.choose(&mut self.mpd, None);
+                self.report(result);
+            }
+            Action::NextValue | Action::PrevValue if menu.choosing.is_none() => {
+                let result = menu.change(&mut self.mpd, action == Action::NextValue);
+                self.report(result);
+            }
+            // Leaving the values goes back to the modes
+            Action::Quit | Action::Back | Action::Cancel if menu.choosing.is_some() => {
+                menu.close(&self.mpd)
+            }
+            Action::PlaybackModes | Action::Quit | Action::Back | Action::Cancel => {
+                self.modes = None
+            }
+            _ => return self.move_in_overlay(action),
+        }
+        true
+    }
+
+    fn run_tags(&mut self, action: Action) -> bool {
+        let Some(editor) = &mut self.tags else {
+            return false;
+        };
+        let field = editor.selected_field();
+        match action {
+            Action::EditField => {
+                self.popup = Some(Popup::input(
+                    format!("Set {}", field.label()),
+                    editor.shared(field).unwrap_or_default(),
+                    PopupAction::SetTag(field),
+                ))
+            }
+            Action::KeepField => editor.keep(field),
+            // Shows what would change, and asks before writing it
+            Action::WriteTags => {
+                let changes = editor.changes();
+                let mut files: Vec<_> = changes.iter().map(|change| &change.file).collect();
+                files.dedup();
+                let title = match files.len() {
+                    0 => {
+                        self.popup = Some(Popup::message(
+                            "Nothing to write",
+                            "The tags are unchanged",
+                            PopupAction::Dismiss,
+                        ));
+                        return true;
+                    }
+                    1 => "Write tags to 1 file?".to_owned(),
+                    len => format!("Write tags to {len} files?"),
+                };
+                self.overlay = Some(tags::preview(&changes));
+                self.popup = Some(Popup::confirm(title, PopupAction::WriteTags(changes)));
+            }
+            Action::EditTags | Action::Quit | Action::Back | Action::Cancel => self.tags = None,
+            _ => return self.move_in_overlay(action),
+        }
+        true
+    }
+
+    // Writes confirmed tag changes and has MPD read the files again
+    fn write_tags(&mut self, changes: &[Change]) {
+        let Some(music_dir) = self.conf.mpd.music_dir() else {
+            return;
+        };
+        let (written, errors) = tags::write(&music_dir, changes);
+        let result = self.mpd.update_paths(written);
+        self.report(result);
+        self.overlay = None;
+        let failed = errors.len();
+        match errors.into_iter().next() {
+            None => self.tags = None,
+            Some(error) if failed == 1 => self.show_error("Failed to write tags", error),
+            Some(error) => {
+                self.show_error(&format!("Failed to write tags to {failed} files"), error)
+            }
+        }
+    }
+
+    fn run_popup_action(&mut self, action: PopupAction, text: &str) {
+        let mpd = &mut self.mpd;
+        let result = match action {
+            PopupAction::ClearQueue => mpd.clear_queue(),
+            PopupAction::DeleteSongs => self.queue.delete_selected(mpd),
+            PopupAction::CropQueue => self.queue.crop_to_selected(mpd),
+            PopupAction::SaveQueue if !text.is_empty() => mpd.save_playlist(text),
+            PopupAction::AddSongs(files) if !text.is_empty() => mpd.add_to_playlist(text, files),
+            PopupAction::RenamePlaylist(name) if !text.is_empty() => {
+                mpd.rename_playlist(&name, text)
+            }
+            PopupAction::DeletePlaylist(name) => mpd.delete_playlist(&name),
+            PopupAction::DeletePlaylistSongs => self.playlists.delete_tracks(mpd),
+            PopupAction::SetTag(field) => {
+                if let Some(editor) = &mut self.tags {
+                    editor.set(field, text);
+                }
+                Ok(())
+            }
+            PopupAction::WriteTags(changes) => {
+                self.write_tags(&changes);
+                Ok(())
+            }
+            PopupAction::ReplaceQueue(name) => mpd.replace_with_playlist(&name),
+            PopupAction::QueueUris(uris) => {
+                self.overlay = None;
+                mpd.add_files(uris)
+            }
+            _ => Ok(()),
+        };
+        self.report(result);
+    }
+
+    // Runs one line of the command line or of a script
+    pub fn execute(&mut self, line: &str) -> Result<()> {
+        match parse(line)? {
+            ExCommand::Run(action) => {
+                if !self.run(action) {
+                    bail!("\"{}\" is not available in this view", action.name());
+                }
+            }
+            ExCommand::Add { tag, value } => {
+                let files = self
+                    .mpd
+                    .get_library()
+                    .into_iter()
+                    .filter(|song| {
+                        tag_value(song, &tag)
+                            .is_some_and(|found| found.eq_ignore_ascii_case(&value))
+                    })
+                    .map(|song| song.file)
+                    .collect::<Vec<_>>();
+                if files.is_empty() {
+                    bail!("No songs with {tag} \"{value}\"");
+                }
+                self.mpd.add_files(files)?;
+            }
+            ExCommand::Volume(adjust) => {
+                if !self.mpd.is_connected() {
+                    return Err(MpdError::NotConnected.into());
+                }
+                let volume = u32::from(self.mpd.get_volume().ok_or(MpdError::NoMixer)?);
+                let volume = match adjust {
+                    Adjust::To(volume) => volume,
+                    Adjust::Up(step) => volume + step,
+                    Adjust::Down(step) => volume.saturating_sub(step),
+                };
+                self.mpd.set_volume(volume.min(100) as u8)?;
+            }
+            ExCommand::Seek(adjust) => {
+                let (elapsed, _) = self.mpd.get_time().ok_or(MpdError::NotPlaying)?;
+                self.mpd.seek(adjust_time(elapsed, adjust))?;
+            }
+            ExCommand::Save(name) => {
+                self.mpd.save_playlist(&name)?;
+                self.playlists.reload();
+            }
+            ExCommand::Set(setting @ (Setting::Repeat | Setting::Random), value) => {
+                let current = match setting {
+                    Setting::Repeat => self.mpd.get_repeat(),
+                    _ => self.mpd.get_random(),
+                };
+                let value = match value {
+                    Some(mode) => mode != OneshotMode::Off,
+                    None => !current.unwrap_or_default(),
+                };
+                match setting {
+                    Setting::Repeat => self.mpd.set_repeat(value)?,
+                    _ => self.mpd.set_random(value)?,
+                }
+            }
+            ExCommand::Set(setting, value) => {
+                let current = match setting {
+                    Setting::Single => self.mpd.get_single(),
+                    _ => self.mpd.get_consume(),
+                };
+                // Toggling turns oneshot off as well
+                let value = value.unwrap_or(match current.unwrap_or_default() {
+                    OneshotMode::Off => OneshotMode::On,
+                    _ => OneshotMode::Off,
+                });
+                match setting {
+                    Setting::Single => self.mpd.set_single(value)?,
+                    _ => self.mpd.set_consume(value)?,
+                }
+            }
+            ExCommand::Bind(keys, action) => self.conf.keymaps.normal.bind(keys, action),
+            ExCommand::Unbind(keys) => {
+                let removed = self
+                    .conf
+                    .keymaps
+                    .named_mut()
+                    .map(|(_, map)| map.unbind(&keys))
+                    .into_iter()
+                    .filter(|removed| *removed)
+                    .count();
+                if removed == 0 {
+                    bail!("Nothing is bound to these keys");
+                }
+            }
+            ExCommand::Source(path) => self.source(&path)?,
+        }
+        Ok(())
+    }
+
+    // Runs a script of commands, skipping blank lines and `#` comments
+    pub fn source(&mut self, path: &Path) -> Result<()> {
+        let script = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        for (idx, line) in script.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            self.execute(line.trim_start_matches(':'))
+                .with_context(|| format!("{}:{}", path.display(), idx + 1))?;
+        }
+        Ok(())
+    }
+
+    // The top line, with the tabs on the left and playback modes on the right
+    fn draw_status<R: Render>(&self, ct: &mut R, rect: Rect) {
+        ct.set_background(rect, Color::Reset);
+        ct.set_foreground(rect, Color::Reset);
+        ct.set_attributes(rect, Attribute::NormalIntensity);
+        ct.set_text(rect, "", Overflow::Char);
+        let tabs_end = tabs::draw(ct, rect, &self.conf.ui.tabs, self.view);
+
+        let status = self.mpd.get_status();
+        let on = (Color::Yellow, Attribute::Bold);
+        let off = (Color::DarkGrey, Attribute::NormalIntensity);
+        let mut items = vec![];
+        if status.is_some() {
+            items.push(match self.mpd.get_volume() {
+                None => ("no mixer".to_owned(), off),
+                Some(_) if self.mpd.is_muted() => ("muted".to_owned(), on),
+                Some(volume) => (
+                    format!("vol {volume}%"),
+                    (Color::Reset, Attribute::NormalIntensity),
+                ),
+            });
+        }
+        if let Some(seconds) = self.mpd.get_crossfade().filter(|seconds| *seconds > 0) {
+            items.push((format!("xfade {seconds}s"), on));
+        }
+        for (name, enabled) in [
+            ("random", self.mpd.get_random()),
+            ("repeat", self.mpd.get_repeat()),
+        ] {
+            let style = if enabled == Some(true) { on } else { off };
+            items.push((name.to_owned(), style));
+        }
+        for (name, mode) in [
+            ("single", self.mpd.get_single()),
+            ("consume", self.mpd.get_consume()),
+        ] {
+            items.push(match mode.unwrap_or_default() {
+                OneshotMode::Off => (name.to_owned(), off),
+                OneshotMode::On => (name.to_owned(), on),
+                OneshotMode::Oneshot => (format!("{name} once"), on),
+            });
+        }
+        let mut x = rect.x + rect.width;
+        for (text, (color, attribute)) in items.iter().rev() {
+            let width = text.width() as u32;
+            if x < tabs_end + width + 1 {
+                break;
+            }
+            x -= width + 1;
+            let item_rect = Rect { x, width, ..rect };
+            ct.set_foreground(item_rect, *color);
+            ct.set_attributes(item_rect, *attribute);
+            ct.set_text(item_rect, text.as_str(), Overflow::Char);
+        }
+    }
+
+    // The bottom line, with how far into the current song playback is
+    fn draw_progress<R: Render>(&self, ct: &mut R, rect: Rect) {
+        if self
+            .mpd
+            .get_status()
+            .is_none_or(|status| status.state == State::Stop)
+        {
+            ct.set_background(rect, Color::Reset);
+            ct.set_text(rect, "", Overflow::Char);
+            return;
+        }
+        let (elapsed, total) = self.mpd.get_time().unzip();
+        progress::draw(
+            ct,
+            rect,
+            &self.conf.progress,
+            elapsed.unwrap_or_default(),
+            total,
+        );
+    }
+
+    // A bar across a small box, drawn over the views while the slider is open
+    fn draw_volume_slider<R: Render>(&self, ct: &mut R, screen: Rect) {
+        let rect = screen.centered(screen.width.min(40), 3);
+        ct.set_background(rect, Color::Reset);
+        ct.set_foreground(rect, Color::Reset);
+        ct.set_attributes(rect, Attribute::NormalIntensity);
+        ct.set_text(rect, "", Overflow::Char);
+        ct.draw_border(rect);
+        let title = Rect {
+            x: rect.x + 2,
+            width: 8.min(rect.width.saturating_sub(4)),
+            height: 1,
+            ..rect
+        };
+        ct.set_attributes(title, Attribute::Bold);
+        ct.set_text(title, " Volume ", Overflow::Char);
+
+        let inner = rect.inner();
+        if inner.width == 0 {
+            return;
+        }
+        let Some(volume) = self.mpd.get_volume() else {
+            ct.set_foreground(inner, Color::DarkGrey);
+            ct.set_text(inner, "MPD has no mixer", Overflow::Char);
+            return;
+        };
+        let label = if self.mpd.is_muted() {
+            " muted".to_owned()
+        } else {
+            format!(" {volume:>3}%")
+        };
+        let width = inner.width.saturating_sub(label.len() as u32);
+        let filled = width * u32::from(volume) / 100;
+        let bar = "█".repeat(filled as usize) + &"░".repeat((width - filled) as usize);
+        ct.set_text(inner, (bar + &label).as_str(), Overflow::Char);
+    }
+
+    pub fn draw<R: Render>(&mut self, ct: &mut R, screen: Rect) {
+        self.draw_status(
+            ct,
+            Rect {
+                height: 1.min(screen.height),
+                ..screen
+            },
+        );
+        let bottom = Rect {
+            y: (screen.y + screen.height).saturating_sub(1),
+            height: 1,
+            ..screen
+        };
+        self.draw_progress(ct, bottom);
+        let view_rect = Rect {
+            y: screen.y + 1,
+            height: screen.height.saturating_sub(2),
+            ..screen
+        };
+        // The cover goes beside the queue when both fit
+        let art_width = self.conf.art.width;
+        let (view_rect, art_rect) = match self.view {
+            View::Queue
+                if self.art.is_enabled() && art_width > 1 && view_rect.width >= art_width * 2 =>
+            {
+                (
+                    Rect {
+                        width: view_rect.width - art_width,
+                        ..view_rect
+                    },
+                    Some(Rect {
+                        x: view_rect.x + view_rect.width - art_width + 1,
+                        width: art_width - 1,
+                        ..view_rect
+                    }),
+                )
+            }
+            _ => (view_rect, None),
+        };
+        if let Some(rect) = art_rect {
+            self.art.draw(ct, rect);
+        }
+        match self.view {
+            View::Queue => self.queue.draw(
+                ct,
+                view_rect,
+                self.mpd
+                    .get_status()
+                    .and_then(|status| status.song)
+                    .map(|place| place.pos),
+            ),
+            View::Library => self.library.draw(ct, view_rect),
+            View::Playlists => self.playlists.draw(ct, view_rect),
+            View::Lyrics => self.lyrics.draw(ct, view_rect),
+        }
+        if let Some(editor) = &mut self.tags {
+            editor.draw(ct, view_rect);
+        }
+        if let Some(menu) = &mut self.modes {
+            menu.draw(ct, view_rect);
+        }
+        if let Some(overlay) = &mut self.overlay {
+            overlay.draw(ct, view_rect);
+        }
+        if self.volume_slider {
+            self.draw_volume_slider(ct, view_rect);
+        }
+        // Above the command line, so typing does not hide them
+        self.toasts.draw(
+            ct,
+            Rect {
+                height: view_rect.height.saturating_sub(1),
+                ..view_rect
+            },
+        );
+        if let Some(active) = &self.popup {
+            active.draw(ct, screen);
+        }
+        self.command_line.draw(ct, bottom);
+        self.search.draw(ct, bottom);
+        // Count and keys of an unfinished binding, like vim's showcmd
+        let pending = self.keys.pending();
+        if !pending.is_empty() {
+            let width = pending.width() as u32;
+            ct.set_text(
+                Rect {
+                    x: (bottom.x + bottom.width).saturating_sub(width + 1),
+                    width,
+                    ..bottom
+                },
+                pending.as_str(),
+                Overflow::Char,
+            );
+        }
+    }
+}
@@ -0,0 +1,343 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    env,
+    fmt::Write,
+    io::Cursor,
+    iter,
+    sync::{mpsc::Sender, Arc},
+};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use crossterm::{style::Color, terminal};
+use image::{imageops::FilterType, DynamicImage, ImageFormat, RgbImage};
+use log::warn;
+use mpd::Song;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    mpd::{Backend, Mpd},
+    ui::{Overflow, Rect, Render},
+};
+
+// How many decoded covers are kept around for songs played again soon
+const CACHE_SIZE: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Protocol {
+    Auto,
+    Kitty,
+    Sixel,
+    HalfBlock,
+    Off,
+}
+
+impl Protocol {
+    // Resolves `Auto` from what the terminal says about itself
+    pub fn detect(self) -> Protocol {
+        if self != Protocol::Auto {
+            return self;
+        }
+        let var = |name| env::var(name).unwrap_or_default();
+        let (term, program) = (var("TERM"), var("TERM_PROGRAM"));
+        if term.contains("kitty")
+            || env::var_os("KITTY_WINDOW_ID").is_some()
+            || ["WezTerm", "ghostty"].contains(&program.as_str())
+        {
+            Protocol::Kitty
+        } else if term.contains("sixel") || term.starts_with("foot") || term == "mlterm" {
+            Protocol::Sixel
+        } else {
+            Protocol::HalfBlock
+        }
+    }
+}
+
+// Pixels of one terminal cell, guessed when the terminal does not report them
+fn cell_size() -> (u32, u32) {
+    match terminal::window_size() {
+        Ok(size) if size.columns > 0 && size.rows > 0 && size.width > 0 && size.height > 0 => (
+            u32::from(size.width / size.columns).max(1),
+            u32::from(size.height / size.rows).max(1),
+        ),
+        _ => (10, 20),
+    }
+}
+
+// Largest size with the aspect ratio of `image` that fits `cols` by `rows` cells
+// of `cell` pixels each, in pixels
+pub fn fit(image: (u32, u32), cols: u32, rows: u32, cell: (u32, u32)) -> (u32, u32) {
+    let (width, height) = (cols * cell.0, rows * cell.1);
+    if image.0 == 0 || image.1 == 0 {
+        return (0, 0);
+    }
+    let scale = f64::min(
+        f64::from(width) / f64::from(image.0),
+        f64::from(height) / f64::from(image.1),
+    );
+    (
+        ((f64::from(image.0) * scale) as u32).clamp(1, width.max(1)),
+        ((f64::from(image.1) * scale) as u32).clamp(1, height.max(1)),
+    )
+}
+
+pub enum Rendered {
+    // Colours of the upper and lower half of each cell, row by row
+    HalfBlock {
+        width: u32,
+        cells: Vec<(Color, Color)>,
+    },
+    // Terminal graphics covering the cells
+    Escape(String),
+}
+
+// A cover scaled to a panel, and the cells it takes up
+pub struct Picture {
+    pub cols: u32,
+    pub rows: u32,
+    pub rendered: Rendered,
+}
+
+impl Picture {
+    pub fn new(image: &DynamicImage, protocol: Protocol, cols: u32, rows: u32) -> Option<Self> {
+        if cols == 0 || rows == 0 {
+            return None;
+        }
+        let cell = match protocol {
+            Protocol::Kitty | Protocol::Sixel => cell_size(),
+            Protocol::HalfBlock => (1, 2),
+            Protocol::Auto | Protocol::Off => return None,
+        };
+        let (width, height) = fit((image.width(), image.height()), cols, rows, cell);
+        let scaled = image.resize_exact(width, height, FilterType::Triangle);
+        let (cols, rows) = (width.div_ceil(cell.0), height.div_ceil(cell.1));
+        let rendered = match protocol {
+            Protocol::Kitty => Rendered::Escape(kitty(&scaled, cols, rows)?),
+            Protocol::Sixel => Rendered::Escape(sixel(&scaled.to_rgb8())),
+            _ => half_block(&scaled.to_rgb8()),
+        };
+        Some(Self {
+            cols,
+            rows,
+            rendered,
+        })
+    }
+}
+
+fn half_block(image: &RgbImage) -> Rendered {
+    let color = |x, y| {
+        if y < image.height() {
+            let [r, g, b] = image.get_pixel(x, y).0;
+            Color::Rgb { r, g, b }
+        } else {
+            Color::Reset
+        }
+    };
+    let cells = (0..image.height().div_ceil(2))
+        .flat_map(|row| (0..image.width()).map(move |x| (row, x)))
+        .map(|(row, x)| (color(x, row * 2), color(x, row * 2 + 1)))
+        .collect();
+    Rendered::HalfBlock {
+        width: image.width(),
+        cells,
+    }
+}
+
+// Sends the image as PNG for the terminal to scale onto `cols` by `rows` cells,
+// below the text so popups stay readable
+fn kitty(image: &DynamicImage, cols: u32, rows: u32) -> Option<String> {
+    let mut png = vec![];
+    if let Err(err) = image.write_to(&mut Cursor::new(&mut png), ImageFormat::Png) {
+        warn!("Failed to encode Album Art: {err}");
+        return None;
+    }
+    let data = STANDARD.encode(png);
+    let chunks: Vec<_> = data.as_bytes().chunks(4096).collect();
+    let mut out = String::new();
+    for (idx, chunk) in chunks.iter().enumerate() {
+        let more = u8::from(idx + 1 < chunks.len());
+        let chunk = String::from_utf8_lossy(chunk);
+        if idx == 0 {
+            let _ = write!(
+                out,
+                "\x1b_Ga=T,f=100,q=2,z=-1,c={cols},r={rows},m={more};{chunk}\x1b\\"
+            );
+        } else {
+            let _ = write!(out, "\x1b_Gm={more};{chunk}\x1b\\");
+        }
+    }
+    Some(out)
+}
+
+// Index into a 6x6x6 colour cube, which the Sixel palette is made of
+fn cube(pixel: [u8; 3]) -> usize {
+    let level = |c: u8| (usize::from(c) * 5 + 127) / 255;
+    level(pixel[0]) * 36 + level(pixel[1]) * 6 + level(pixel[2])
+}
+
+pub fn sixel(image: &RgbImage) -> String {
+    let (width, height) = image.dimensions();
+    let mut out = format!("\x1bPq\"1;1;{width};{height}");
+    for color in 0..216 {
+        let (r, g, b) = (color / 36, color / 6 % 6, color % 6);
+        let _ = write!(out, "#{color};2;{};{};{}", r * 20, g * 20, b * 20);
+    }
+    // Each band is six pixel rows, written once per colour it uses
+    for band in (0..height).step_by(6) {
+        let mut colors: BTreeMap<usize, Vec<u8>> = BTreeMap::new();
+        for dy in 0..6.min(height - band) {
+            for x in 0..width {
+                let color = cube(image.get_pixel(x, band + dy).0);
+                colors
+                    .entry(color)
+                    .or_insert_with(|| vec![0; width as usize])[x as usize] |= 1 << dy;
+            }
+        }
+        for (color, bits) in colors {
+            let _ = write!(out, "#{color}");
+            let mut run = 0;
+            for (x, bit) in bits.iter().enumerate() {
+                run += 1;
+                if bits.get(x + 1) != Some(bit) {
+                    let char = char::from(63 + bit);
+                    match run {
+                        1..=3 => out.extend(iter::repeat_n(char, run)),
+                        _ => {
+                            let _ = write!(out, "!{run}{char}");
+                        }
+                    }
+                    run = 0;
+                }
+            }
+            out.push('$');
+        }
+        out.push('-');
+    }
+    out.push_str("\x1b\\");
+    out
+}
+
+// Fetches and decodes the cover of `file`, which `Events::watch_art` does off the
+// main thread
+pub fn fetch<B: Backend>(mpd: &mut Mpd<B>, file: &str) -> Option<Arc<DynamicImage>> {
+    let data = mpd.album_art(file)?;
+    match image::load_from_memory(&data) {
+        Ok(image) => Some(Arc::new(image)),
+        Err(err) => {
+            warn!("Failed to decode Album Art of {file}: {err}");
+            None
+        }
+    }
+}
+
+// Cover of the playing song, next to the queue
+pub struct ArtPanel {
+    pub protocol: Protocol,
+    file: Option<String>,
+    // Stays on screen until the cover of `file` arrives
+    image: Option<Arc<DynamicImage>>,
+    // Output for the last panel size, only redone when the song or size changes
+    picture: Option<(Rect, Option<Picture>)>,
+    cache: VecDeque<(String, Option<Arc<DynamicImage>>)>,
+    // Where covers missing from the cache are asked for
+    requests: Option<Sender<String>>,
+}
+
+impl ArtPanel {
+    pub fn new(protocol: Protocol) -> Self {
+        Self {
+            protocol: protocol.detect(),
+            file: None,
+            image: None,
+            picture: None,
+            cache: VecDeque::new(),
+            requests: None,
+        }
+    }
+
+    // Has covers fetched by whatever reads `requests`, like `Events::watch_art`
+    pub fn fetch_with(&mut self, requests: Sender<String>) {
+        self.requests = Some(requests);
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.protocol != Protocol::Off
+    }
+
+    pub fn image(&self) -> Option<&DynamicImage> {
+        self.image.as_deref()
+    }
+
+    // Asks for the cover when the song changes, unless it was seen recently
+    pub fn sync(&mut self, song: Option<&Song>) {
+        let file = song.map(|song| song.file.clone());
+        if !self.is_enabled() || file == self.file {
+            return;
+        }
+        match file.as_deref() {
+            None => self.show(None),
+            Some(file) => match self.cache.iter().find(|(cached, _)| cached == file) {
+                Some((_, image)) => self.show(image.clone()),
+                None => {
+                    if let Some(requests) = &self.requests {
+                        let _ = requests.send(file.to_owned());
+                    }
+                }
+            },
+        }
+        self.file = file;
+    }
+
+    // Takes in a fetched cover. Returns whether it is the one to show now
+    pub fn receive(&mut self, file: String, image: Option<Arc<DynamicImage>>) -> bool {
+        let current = self.file.as_ref() == Some(&file);
+        if current {
+            self.show(image.clone());
+        }
+        if !self.cache.iter().any(|(cached, _)| *cached == file) {
+            if self.cache.len() >= CACHE_SIZE {
+                self.cache.pop_front();
+            }
+            self.cache.push_back((file, image));
+        }
+        current
+    }
+
+    fn show(&mut self, image: Option<Arc<DynamicImage>>) {
+        self.picture = None;
+        self.image = image;
+    }
+
+    pub fn draw<R: Render>(&mut self, ct: &mut R, rect: Rect) {
+        ct.set_background(rect, Color::Reset);
+        ct.set_foreground(rect, Color::Reset);
+        ct.set_text(rect, "", Overflow::Char);
+        let Some(image) = &self.image else {
+            return;
+        };
+        if self.picture.as_ref().map(|(size, _)| *size) != Some(rect) {
+            let picture = Picture::new(image, self.protocol, rect.width, rect.height);
+            self.picture = Some((rect, picture));
+        }
+        let Some((_, Some(picture))) = &self.picture else {
+            return;
+        };
+        let area = rect.centered(picture.cols, picture.rows);
+        match &picture.rendered {
+            Rendered::HalfBlock { width, cells } => {
+                for (idx, (top, bottom)) in cells.iter().enumerate() {
+                    let cell = Rect {
+                        x: area.x + idx as u32 % width,
+                        y: area.y + idx as u32 / width,
+                        width: 1,
+                        height: 1,
+                    };
+                    ct.set_foreground(cell, *top);
+                    ct.set_background(cell, *bottom);
+                    ct.set_text(cell, "▀", Overflow::Char);
+                }
+            }
+            Rendered::Escape(escape) => ct.set_graphic(area, escape),
+        }
+    }
+}
@@ -0,0 +1,265 @@
+use std::fmt;
+
+use crossterm::style::{Attribute, Color};
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+use super::ui::Rect;
+
+// The grapheme in a cell, kept inline so cells stay small and Copy. A wide one is
+// followed by an empty continuation cell, which the terminal covers with it
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Symbol {
+    len: u8,
+    bytes: [u8; Symbol::CAPACITY],
+}
+
+impl Symbol {
+    const CAPACITY: usize = 31;
+    pub const BLANK: Symbol = Symbol::new(" ");
+    pub const CONTINUATION: Symbol = Symbol::new("");
+
+    // Graphemes longer than a cell holds, like a letter under dozens of combining
+    // marks, lose the marks that don't fit
+    pub const fn new(grapheme: &str) -> Self {
+        let mut len = if grapheme.len() < Self::CAPACITY {
+            grapheme.len()
+        } else {
+            Self::CAPACITY
+        };
+        while !grapheme.is_char_boundary(len) {
+            len -= 1;
+        }
+        let mut bytes = [0; Self::CAPACITY];
+        let mut idx = 0;
+        while idx < len {
+            bytes[idx] = grapheme.as_bytes()[idx];
+            idx += 1;
+        }
+        Self {
+            len: len as u8,
+            bytes,
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        std::str::from_utf8(&self.bytes[..self.len as usize]).unwrap_or_default()
+    }
+
+    pub fn is_continuation(&self) -> bool {
+        self.len == 0
+    }
+
+    // Cells taken up: two for wide graphemes, and one even for a lone combining mark
+    pub fn width(&self) -> usize {
+        match self.is_continuation() {
+            true => 0,
+            false => self.as_str().width().clamp(1, 2),
+        }
+    }
+}
+
+impl From<char> for Symbol {
+    fn from(char: char) -> Self {
+        Self::new(char.encode_utf8(&mut [0; 4]))
+    }
+}
+
+impl fmt::Debug for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.as_str().fmt(f)
+    }
+}
+
+// One terminal cell
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cell {
+    pub symbol: Symbol,
+    pub fg: Color,
+    pub bg: Color,
+    pub attribute: Option<Attribute>,
+}
+
+impl Cell {
+    // Equal to nothing that gets drawn, for cells whose content on screen is unknown
+    const UNKNOWN: Cell = Cell {
+        symbol: Symbol::new("\0"),
+        fg: Color::Reset,
+        bg: Color::Reset,
+        attribute: None,
+    };
+}
+
+impl Default for Cell {
+    fn default() -> Self {
+        Self {
+            symbol: Symbol::BLANK,
+            fg: Color::Reset,
+            bg: Color::Reset,
+            attribute: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Patch {
+    pub x: u32,
+    pub y: u32,
+    pub cell: Cell,
+}
+
+// A frame of cells. Rows changed since the last `sync` are marked, so comparing
+// two buffers only looks at the rows that may differ
+#[derive(Clone)]
+pub struct Buffer {
+    cells: Vec<Cell>,
+    dirty: Vec<bool>,
+    screen: Rect,
+}
+
+impl Buffer {
+    pub fn new(rect: Rect) -> Self {
+        Self {
+            cells: vec![Cell::default(); (rect.height * rect.width) as usize],
+            dirty: vec![true; rect.height as usize],
+            screen: rect,
+        }
+    }
+
+    fn index(&self, x: usize, y: usize) -> Option<usize> {
+        (x < self.screen.width as usize && y < self.screen.height as usize)
+            .then(|| y * self.screen.width as usize + x)
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> Option<&Cell> {
+        self.index(x, y).map(|idx| &self.cells[idx])
+    }
+
+    // Whether row `y` may differ from the last frame
+    pub fn is_dirty(&self, y: usize) -> bool {
+        self.dirty.get(y).copied().unwrap_or(false)
+    }
+
+    // Changes a cell, marking its row only when the cell ends up different, since
+    // every frame draws over every cell again
+    pub fn update(&mut self, x: usize, y: usize, change: impl FnOnce(&mut Cell)) {
+        let Some(idx) = self.index(x, y) else {
+            return;
+        };
+        let old = self.cells[idx];
+        change(&mut self.cells[idx]);
+        if self.cells[idx] != old {
+            self.dirty[y] = true;
+        }
+    }
+
+    // Puts `symbol` in a cell, blanking the other half of a wide grapheme it
+    // breaks up, which the terminal would clear
+    pub fn set_symbol(&mut self, x: usize, y: usize, symbol: Symbol) {
+        let Some(old) = self.get(x, y).map(|cell| cell.symbol) else {
+            return;
+        };
+        if old != symbol && old.is_continuation() && x > 0 {
+            self.update(x - 1, y, |cell| cell.symbol = Symbol::BLANK);
+        } else if old != symbol && old.width() == 2 {
+            self.update(x + 1, y, |cell| {
+                if cell.symbol.is_continuation() {
+                    cell.symbol = Symbol::BLANK;
+                }
+            });
+        }
+        self.update(x, y, |cell| cell.symbol = symbol);
+    }
+
+    // Fills `rect` with `text`, a grapheme to a cell or two, wrapping at its right
+    // edge and at newlines. Cells after the text are blanked
+    pub fn set_text(&mut self, rect: Rect, text: &str) {
+        let end = (rect.x + rect.width) as usize;
+        let mut graphemes = text.graphemes(true).peekable();
+        for y in (rect.y..rect.y + rect.height).map(|y| y as usize) {
+            let mut x = rect.x as usize;
+            while let Some(&grapheme) = graphemes.peek() {
+                let newline = matches!(grapheme, "\n" | "\r\n");
+                let symbol = Symbol::new(grapheme);
+                if newline || x + symbol.width() > end {
+                    // A full row ends the line it is on, and a grapheme wider than
+                    // the rect is dropped
+                    if newline || x == rect.x as usize {
+                        graphemes.next();
+                    }
+                    break;
+                }
+                graphemes.next();
+                self.set_symbol(x, y, symbol);
+                if symbol.width() == 2 {
+                    self.set_symbol(x + 1, y, Symbol::CONTINUATION);
+                }
+                x += symbol.width();
+            }
+            for x in x..end {
+                self.set_symbol(x, y, Symbol::BLANK);
+            }
+        }
+    }
+
+    // Forgets what `rect` holds, so its cells are sent again by the next diff
+    pub fn invalidate(&mut self, rect: Rect) {
+        for y in rect.y..rect.y + rect.height {
+            for x in rect.x..rect.x + rect.width {
+                self.update(x as usize, y as usize, |cell| *cell = Cell::UNKNOWN);
+            }
+        }
+    }
+
+    // Fills `patches` with the cells that turn `other`, the frame on screen, into
+    // this one
+    pub fn diff(&self, other: &Self, patches: &mut Vec<Patch>) {
+        patches.clear();
+        let width = self.screen.width as usize;
+        if self.screen != other.screen {
+            patches.extend(self.cells.iter().enumerate().map(|(idx, cell)| Patch {
+                x: (idx % width) as u32,
+                y: (idx / width) as u32,
+                cell: *cell,
+            }));
+            return;
+        }
+        for y in 0..self.screen.height as usize {
+            if !self.dirty[y] && !other.dirty[y] {
+                continue;
+            }
+            let row = y * width..(y + 1) * width;
+            for (x, (cell, shown)) in self.cells[row.clone()]
+                .iter()
+                .zip(&other.cells[row])
+                .enumerate()
+            {
+                if cell != shown {
+                    patches.push(Patch {
+                        x: x as u32,
+                        y: y as u32,
+                        cell: *cell,
+                    });
+                }
+            }
+        }
+    }
+
+    // Catches this buffer up with `front` after the two were swapped, copying only
+    // the rows either of them had written
+    pub fn sync(&mut self, front: &mut Self) {
+        if self.screen != front.screen {
+            self.clone_from(front);
+        } else {
+            let width = self.screen.width as usize;
+            for y in 0..self.screen.height as usize {
+                if self.dirty[y] || front.dirty[y] {
+                    let row = y * width..(y + 1) * width;
+                    self.cells[row.clone()].copy_from_slice(&front.cells[row]);
+                }
+            }
+        }
+        self.dirty.fill(false);
+        front.dirty.fill(false);
+    }
+}
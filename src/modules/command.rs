@@ -0,0 +1,566 @@
+use std::{path::PathBuf, time::Duration};
+
+use anyhow::{anyhow, bail, Result};
+use crossterm::style::{Attribute, Color};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    keymap::{parse_keys, Key},
+    mpd::OneshotMode,
+    ui::{Overflow, Rect, Render},
+};
+
+// Everything that keys, the command line and scripts can trigger by name
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    Quit,
+    Suspend,
+    Help,
+    Errors,
+    CommandLine,
+    Search,
+    Up,
+    Down,
+    Top,
+    Bottom,
+    SwitchView,
+    PrevTab,
+    Tab1,
+    Tab2,
+    Tab3,
+    Tab4,
+    Tab5,
+    Tab6,
+    Tab7,
+    Tab8,
+    Tab9,
+    Lyrics,
+    SongInfo,
+    EditTags,
+    EditField,
+    KeepField,
+    WriteTags,
+    PlayPause,
+    Stop,
+    Next,
+    Prev,
+    Repeat,
+    Random,
+    Single,
+    Consume,
+    PlaybackModes,
+    NextValue,
+    PrevValue,
+    ChooseValue,
+    Shuffle,
+    ShuffleSelected,
+    ShuffleRest,
+    VolUp,
+    VolDown,
+    VolUpFine,
+    VolDownFine,
+    Mute,
+    VolumeSlider,
+    SeekForward,
+    SeekBack,
+    ClearQueue,
+    AddToQueue,
+    PlaySelected,
+    DeleteSong,
+    MoveUp,
+    MoveDown,
+    MoveToTop,
+    MoveAfterCurrent,
+    CropQueue,
+    Priority,
+    Undo,
+    Redo,
+    ToggleMark,
+    VisualMode,
+    SelectAll,
+    InvertSelection,
+    SelectAlbum,
+    ClearSelection,
+    Back,
+    LoadPlaylist,
+    SavePlaylist,
+    RenamePlaylist,
+    AddToPlaylist,
+    // Editing the command line, search and popups
+    Cancel,
+    Submit,
+    Confirm,
+    Deny,
+    DeleteChar,
+    HistoryPrev,
+    HistoryNext,
+    Complete,
+}
+
+impl Action {
+    // Names match the keybind fields in the config
+    pub const ALL: [(&'static str, Action); 81] = [
+        ("quit", Action::Quit),
+        ("suspend", Action::Suspend),
+        ("help", Action::Help),
+        ("errors", Action::Errors),
+        ("command_line", Action::CommandLine),
+        ("search", Action::Search),
+        ("up", Action::Up),
+        ("down", Action::Down),
+        ("top", Action::Top),
+        ("bottom", Action::Bottom),
+        ("switch_view", Action::SwitchView),
+        ("prev_tab", Action::PrevTab),
+        ("tab1", Action::Tab1),
+        ("tab2", Action::Tab2),
+        ("tab3", Action::Tab3),
+        ("tab4", Action::Tab4),
+        ("tab5", Action::Tab5),
+        ("tab6", Action::Tab6),
+        ("tab7", Action::Tab7),
+        ("tab8", Action::Tab8),
+        ("tab9", Action::Tab9),
+        ("lyrics", Action::Lyrics),
+        ("song_info", Action::SongInfo),
+        ("edit_tags", Action::EditTags),
+        ("edit_field", Action::EditField),
+        ("keep_field", Action::KeepField),
+        ("write_tags", Action::WriteTags),
+        ("play_pause", Action::PlayPause),
+        ("stop", Action::Stop),
+        ("next", Action::Next),
+        ("prev", Action::Prev),
+        ("repeat", Action::Repeat),
+        ("random", Action::Random),
+        ("single", Action::Single),
+        ("consume", Action::Consume),
+        ("playback_modes", Action::PlaybackModes),
+        ("next_value", Action::NextValue),
+        ("prev_value", Action::PrevValue),
+        ("choose_value", Action::ChooseValue),
+        ("shuffle", Action::Shuffle),
+        ("shuffle_selected", Action::ShuffleSelected),
+        ("shuffle_rest", Action::ShuffleRest),
+        ("vol_up", Action::VolUp),
+        ("vol_down", Action::VolDown),
+        ("vol_up_fine", Action::VolUpFine),
+        ("vol_down_fine", Action::VolDownFine),
+        ("mute", Action::Mute),
+        ("volume_slider", Action::VolumeSlider),
+        ("seek_forward", Action::SeekForward),
+        ("seek_back", Action::SeekBack),
+        ("clear_queue", Action::ClearQueue),
+        ("add_to_queue", Action::AddToQueue),
+        ("play_selected", Action::PlaySelected),
+        ("delete_song", Action::DeleteSong),
+        ("move_up", Action::MoveUp),
+        ("move_down", Action::MoveDown),
+        ("move_to_top", Action::MoveToTop),
+        ("move_after_current", Action::MoveAfterCurrent),
+        ("crop_queue", Action::CropQueue),
+        ("priority", Action::Priority),
+        ("undo", Action::Undo),
+        ("redo", Action::Redo),
+        ("toggle_mark", Action::ToggleMark),
+        ("visual_mode", Action::VisualMode),
+        ("select_all", Action::SelectAll),
+        ("invert_selection", Action::InvertSelection),
+        ("select_album", Action::SelectAlbum),
+        ("clear_selection", Action::ClearSelection),
+        ("back", Action::Back),
+        ("load_playlist", Action::LoadPlaylist),
+        ("save_playlist", Action::SavePlaylist),
+        ("rename_playlist", Action::RenamePlaylist),
+        ("add_to_playlist", Action::AddToPlaylist),
+        ("cancel", Action::Cancel),
+        ("submit", Action::Submit),
+        ("confirm", Action::Confirm),
+        ("deny", Action::Deny),
+        ("delete_char", Action::DeleteChar),
+        ("history_prev", Action::HistoryPrev),
+        ("history_next", Action::HistoryNext),
+        ("complete", Action::Complete),
+    ];
+
+    pub fn from_name(name: &str) -> Option<Action> {
+        Self::ALL
+            .iter()
+            .find(|(action_name, _)| *action_name == name)
+            .map(|(_, action)| *action)
+    }
+
+    pub fn name(self) -> &'static str {
+        Self::ALL
+            .iter()
+            .find(|(_, action)| *action == self)
+            .map(|(name, _)| *name)
+            .unwrap_or_default()
+    }
+
+    // Whether a count typed before the action repeats it
+    pub fn repeats(self) -> bool {
+        matches!(
+            self,
+            Action::Up
+                | Action::Down
+                | Action::Next
+                | Action::Prev
+                | Action::VolUp
+                | Action::VolDown
+                | Action::MoveUp
+                | Action::MoveDown
+                | Action::Undo
+                | Action::Redo
+                | Action::SwitchView
+        )
+    }
+
+    // Which tab the numbered tab actions go to, counting from 0
+    pub fn tab(self) -> Option<usize> {
+        let tab = match self {
+            Action::Tab1 => 0,
+            Action::Tab2 => 1,
+            Action::Tab3 => 2,
+            Action::Tab4 => 3,
+            Action::Tab5 => 4,
+            Action::Tab6 => 5,
+            Action::Tab7 => 6,
+            Action::Tab8 => 7,
+            Action::Tab9 => 8,
+            _ => return None,
+        };
+        Some(tab)
+    }
+
+    // Whether holding the key down runs the action again, instead of only the first press
+    pub fn held(self) -> bool {
+        matches!(
+            self,
+            Action::Up
+                | Action::Down
+                | Action::VolUp
+                | Action::VolDown
+                | Action::VolUpFine
+                | Action::VolDownFine
+                | Action::SeekForward
+                | Action::SeekBack
+        )
+    }
+}
+
+// An absolute value, or a step relative to the current one
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Adjust {
+    To(u32),
+    Up(u32),
+    Down(u32),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Setting {
+    Repeat,
+    Random,
+    Single,
+    Consume,
+}
+
+impl Setting {
+    const ALL: [(&'static str, Setting); 4] = [
+        ("repeat", Setting::Repeat),
+        ("random", Setting::Random),
+        ("single", Setting::Single),
+        ("consume", Setting::Consume),
+    ];
+}
+
+// A parsed line of the command line or of a script
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExCommand {
+    Run(Action),
+    Add { tag: String, value: String },
+    Volume(Adjust),
+    Seek(Adjust),
+    Save(String),
+    // `None` toggles the setting, only single and consume take `Oneshot`
+    Set(Setting, Option<OneshotMode>),
+    Bind(Vec<Key>, Action),
+    Unbind(Vec<Key>),
+    Source(PathBuf),
+}
+
+// Commands that take arguments, next to the action names
+const EX_COMMANDS: [&str; 8] = [
+    "add", "vol", "seek", "save", "set", "bind", "unbind", "source",
+];
+const TAGS: [&str; 6] = ["artist", "album", "title", "genre", "date", "file"];
+
+// Splits on whitespace, keeping "double quoted" words together
+fn split_words(line: &str) -> Result<Vec<String>> {
+    let mut words = vec![];
+    let mut chars = line.chars().peekable();
+    while let Some(char) = chars.next() {
+        match char {
+            c if c.is_whitespace() => {}
+            '"' => {
+                let mut word = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => word.extend(chars.next()),
+                        Some(c) => word.push(c),
+                        None => bail!("Unterminated quote"),
+                    }
+                }
+                words.push(word);
+            }
+            c => {
+                let mut word = String::from(c);
+                while let Some(c) = chars.next_if(|c| !c.is_whitespace()) {
+                    word.push(c);
+                }
+                words.push(word);
+            }
+        }
+    }
+    Ok(words)
+}
+
+// Accepts `40`, `+5`, `-5` and, for times, `1:30`
+fn parse_adjust(arg: &str) -> Result<Adjust> {
+    let number = |text: &str| -> Result<u32> {
+        match text.split_once(':') {
+            Some((mins, secs)) => Ok(mins.parse::<u32>()? * 60 + secs.parse::<u32>()?),
+            None => Ok(text.parse()?),
+        }
+    };
+    let invalid = |_| anyhow!("Invalid value \"{arg}\"");
+    match arg.as_bytes().first() {
+        Some(b'+') => number(&arg[1..]).map(Adjust::Up).map_err(invalid),
+        Some(b'-') => number(&arg[1..]).map(Adjust::Down).map_err(invalid),
+        _ => number(arg).map(Adjust::To).map_err(invalid),
+    }
+}
+
+pub fn parse(line: &str) -> Result<ExCommand> {
+    let words = split_words(line)?;
+    let Some((name, args)) = words.split_first() else {
+        bail!("Empty command");
+    };
+    let rest = args.join(" ");
+    match (name.as_str(), args) {
+        ("q" | "quit", []) => Ok(ExCommand::Run(Action::Quit)),
+        ("add", []) => Ok(ExCommand::Run(Action::AddToQueue)),
+        ("add", [tag, value @ ..]) if !value.is_empty() => Ok(ExCommand::Add {
+            tag: tag.to_lowercase(),
+            value: value.join(" "),
+        }),
+        ("vol" | "volume", [arg]) => parse_adjust(arg).map(ExCommand::Volume),
+        ("seek", [arg]) => parse_adjust(arg).map(ExCommand::Seek),
+        ("save", []) => Ok(ExCommand::Run(Action::SavePlaylist)),
+        ("save", _) => Ok(ExCommand::Save(rest)),
+        ("set", [option, value @ ..]) => {
+            let (option, bang) = match option.strip_suffix('!') {
+                Some(option) => (option, true),
+                None => (option.as_str(), false),
+            };
+            let setting = Setting::ALL
+                .iter()
+                .find(|(name, _)| *name == option)
+                .map(|(_, setting)| *setting)
+                .ok_or_else(|| anyhow!("Unknown option \"{option}\""))?;
+            let value = match (value, bang) {
+                ([], true) => None,
+                ([], false) => Some(OneshotMode::On),
+                ([value], false) => match value.as_str() {
+                    "on" | "true" | "1" => Some(OneshotMode::On),
+                    "off" | "false" | "0" => Some(OneshotMode::Off),
+                    "oneshot" if matches!(setting, Setting::Single | Setting::Consume) => {
+                        Some(OneshotMode::Oneshot)
+                    }
+                    "toggle" => None,
+                    _ if matches!(setting, Setting::Single | Setting::Consume) => {
+                        bail!("Expected on, off, oneshot or toggle")
+                    }
+                    _ => bail!("Expected on, off or toggle"),
+                },
+                _ => bail!("Usage: set <option> [on|off|oneshot|toggle]"),
+            };
+            Ok(ExCommand::Set(setting, value))
+        }
+        ("bind", [keys, action]) => {
+            let keys = parse_keys(keys)?;
+            let action =
+                Action::from_name(action).ok_or_else(|| anyhow!("Unknown action \"{action}\""))?;
+            Ok(ExCommand::Bind(keys, action))
+        }
+        ("unbind", [keys]) => parse_keys(keys).map(ExCommand::Unbind),
+        ("so" | "source", [path]) => Ok(ExCommand::Source(PathBuf::from(path))),
+        (name, []) => Action::from_name(name)
+            .map(ExCommand::Run)
+            .ok_or_else(|| anyhow!("Unknown command \"{name}\"")),
+        (name, _) => bail!("Wrong arguments for \"{name}\""),
+    }
+}
+
+// Full lines that `line` can be completed to
+pub fn complete(line: &str) -> Vec<String> {
+    let (head, word) = match line.rfind(' ') {
+        Some(idx) => line.split_at(idx + 1),
+        None => ("", line),
+    };
+    let words = head.split_whitespace().collect::<Vec<_>>();
+    let candidates: Vec<&str> = match words.as_slice() {
+        [] => EX_COMMANDS
+            .into_iter()
+            .chain(Action::ALL.iter().map(|(name, _)| *name))
+            .collect(),
+        ["set"] => Setting::ALL.iter().map(|(name, _)| *name).collect(),
+        ["set", "single" | "consume"] => vec!["on", "off", "oneshot", "toggle"],
+        ["set", _] => vec!["on", "off", "toggle"],
+        ["add"] => TAGS.to_vec(),
+        ["bind", _] => Action::ALL.iter().map(|(name, _)| *name).collect(),
+        _ => vec![],
+    };
+    candidates
+        .into_iter()
+        .filter(|candidate| candidate.starts_with(word))
+        .map(|candidate| format!("{head}{candidate}"))
+        .collect()
+}
+
+// Applies a step to the playback position
+pub fn adjust_time(current: Duration, adjust: Adjust) -> Duration {
+    match adjust {
+        Adjust::To(secs) => Duration::from_secs(secs.into()),
+        Adjust::Up(secs) => current + Duration::from_secs(secs.into()),
+        Adjust::Down(secs) => current.saturating_sub(Duration::from_secs(secs.into())),
+    }
+}
+
+// A one line prompt like `:` or `/` with a history of submitted lines
+pub struct CommandLine {
+    prefix: char,
+    input: Option<String>,
+    history: Vec<String>,
+    // Position while browsing the history, counted from the newest entry
+    recall: Option<usize>,
+    // Candidates being cycled through with Tab and the index of the shown one
+    completions: Vec<String>,
+    completion: usize,
+}
+
+impl CommandLine {
+    pub fn new(prefix: char) -> Self {
+        Self {
+            prefix,
+            input: None,
+            history: vec![],
+            recall: None,
+            completions: vec![],
+            completion: 0,
+        }
+    }
+
+    pub fn open(&mut self) {
+        self.input = Some(String::new());
+        self.recall = None;
+        self.completions.clear();
+    }
+
+    pub fn close(&mut self) {
+        self.input = None;
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.input.is_some()
+    }
+
+    pub fn history(&self) -> &[String] {
+        &self.history
+    }
+
+    fn set_input(&mut self, input: String) {
+        if self.input.is_some() {
+            self.input = Some(input);
+        }
+    }
+
+    pub fn insert(&mut self, char: char) {
+        self.completions.clear();
+        if let Some(input) = &mut self.input {
+            input.push(char);
+        }
+    }
+
+    // Backspace on an empty line closes it, like in vim
+    pub fn delete_char(&mut self) {
+        self.completions.clear();
+        if let Some(input) = &mut self.input {
+            if input.pop().is_none() {
+                self.input = None;
+            }
+        }
+    }
+
+    pub fn recall(&mut self, older: bool) {
+        self.completions.clear();
+        if self.history.is_empty() {
+            return;
+        }
+        let recall = match (self.recall, older) {
+            (None, true) => Some(0),
+            (Some(idx), true) => Some((idx + 1).min(self.history.len() - 1)),
+            (Some(0) | None, false) => None,
+            (Some(idx), false) => Some(idx - 1),
+        };
+        self.recall = recall;
+        self.set_input(match recall {
+            Some(idx) => self.history[self.history.len() - 1 - idx].clone(),
+            None => String::new(),
+        });
+    }
+
+    // Cycles through the commands the line can be completed to
+    pub fn complete(&mut self) {
+        let Some(input) = &self.input else {
+            return;
+        };
+        if self.completions.is_empty() {
+            self.completions = complete(input);
+            self.completion = 0;
+        } else {
+            self.completion = (self.completion + 1) % self.completions.len();
+        }
+        if let Some(completion) = self.completions.get(self.completion).cloned() {
+            self.set_input(completion);
+        }
+    }
+
+    // Closes the line and returns it, unless it was blank
+    pub fn submit(&mut self) -> Option<String> {
+        let line = self.input.take()?.trim().to_owned();
+        if line.is_empty() {
+            return None;
+        }
+        if self.history.last() != Some(&line) {
+            self.history.push(line.clone());
+        }
+        Some(line)
+    }
+
+    pub fn draw<R: Render>(&self, ct: &mut R, rect: Rect) {
+        let Some(input) = &self.input else {
+            return;
+        };
+        ct.set_background(rect, Color::Reset);
+        ct.set_foreground(rect, Color::Reset);
+        ct.set_attributes(rect, Attribute::NormalIntensity);
+        ct.set_text(
+            rect,
+            format!("{}{input}_", self.prefix).as_str(),
+            Overflow::Char,
+        );
+    }
+}
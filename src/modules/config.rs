@@ -1,15 +1,25 @@
 use std::{
+    collections::BTreeMap,
     fs::{self, File},
     io::Write,
     net::{SocketAddr, ToSocketAddrs},
-    ops::{Deref, DerefMut},
+    path::{Path, PathBuf},
 };
 
-use anyhow::{Error, Ok, Result};
-use crossterm::event::{Event, KeyCode, KeyEvent, KeyModifiers};
-use dirs::config_dir;
-use log::info;
+use anyhow::{anyhow, Context, Error, Ok, Result};
+use crossterm::{event::Event, style::Color};
+use dirs::{cache_dir, config_dir, home_dir};
+use log::{info, warn};
 use serde::{Deserialize, Serialize};
+
+use super::{
+    app::View,
+    art::Protocol,
+    command::Action,
+    keymap::{deserialize_key, keys_name, parse_key, serialize_key, Key, Keymap, LegacyKey},
+    progress::TimeFormat,
+    tabs,
+};
 #[derive(Serialize, Deserialize)]
 #[serde(untagged)]
 enum Either<L, R> {
@@ -21,6 +31,9 @@ enum Either<L, R> {
 pub struct Mpd {
     addr: Either<SocketAddr, Box<str>>,
     password: Option<Box<str>>,
+    // The music_directory of MPD, for files that sit next to the tracks
+    #[serde(default)]
+    music_dir: Option<PathBuf>,
 }
 
 impl Default for Mpd {
@@ -28,6 +41,7 @@ impl Default for Mpd {
         Self {
             addr: Either::Left(SocketAddr::new([127, 0, 0, 1].into(), 6600)),
             password: None,
+            music_dir: None,
         }
     }
 }
@@ -41,152 +55,469 @@ impl Mpd {
             Either::Right(addr) => Ok(addr.to_socket_addrs()?.next().unwrap()),
         }
     }
+
+    pub fn music_dir(&self) -> Option<PathBuf> {
+        self.music_dir.as_deref().map(expand_home)
+    }
+}
+
+// Paths in the config may start with `~` like they do in MPD's own config
+fn expand_home(path: &Path) -> PathBuf {
+    match (path.strip_prefix("~"), home_dir()) {
+        (Result::Ok(rest), Some(home)) => home.join(rest),
+        _ => path.to_owned(),
+    }
 }
 
 #[derive(Serialize, Deserialize)]
-pub struct EventWrapper(Vec<Event>);
+#[serde(default)]
+pub struct Lyrics {
+    // Fetched lyrics are kept here, and looked up as `Artist - Title.lrc`
+    cache_dir: Option<PathBuf>,
+    // Command printing the lyrics of `{artist}`, `{title}`, `{album}` or `{file}`,
+    // fetching is off while it is empty
+    pub fetch_command: Vec<String>,
+}
 
-impl Deref for EventWrapper {
-    type Target = Vec<Event>;
+impl Default for Lyrics {
+    fn default() -> Self {
+        Self {
+            cache_dir: cache_dir().map(|p| p.join(env!("CARGO_PKG_NAME")).join("lyrics")),
+            fetch_command: vec![],
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct Art {
+    // auto, kitty, sixel, half_block or off
+    pub protocol: Protocol,
+    // Columns of the cover panel beside the queue, shown once the queue keeps as many
+    pub width: u32,
+}
 
-    fn deref(&self) -> &Self::Target {
-        &self.0
+impl Default for Art {
+    fn default() -> Self {
+        Self {
+            protocol: Protocol::Auto,
+            width: 40,
+        }
     }
 }
 
-impl DerefMut for EventWrapper {
-    fn deref_mut(&mut self) -> &mut Self::Target {
-        &mut self.0
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct Volume {
+    // Points the volume keys move by, and the fine ones with Alt held
+    pub step: u8,
+    pub fine_step: u8,
+}
+
+impl Default for Volume {
+    fn default() -> Self {
+        Self {
+            step: 5,
+            fine_step: 1,
+        }
+    }
+}
+
+impl Lyrics {
+    pub fn cache_dir(&self) -> Option<PathBuf> {
+        self.cache_dir.as_deref().map(expand_home)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct Progress {
+    // Glyphs of the played part and the rest
+    pub fill: char,
+    pub empty: char,
+    // Eighth blocks at the edge of the played part, which suit a full block fill
+    pub smooth: bool,
+    pub fill_color: Color,
+    pub empty_color: Color,
+    // elapsed, remaining, elapsed_total or percent
+    pub time: TimeFormat,
+}
+
+impl Default for Progress {
+    fn default() -> Self {
+        Self {
+            fill: '█',
+            empty: '─',
+            smooth: true,
+            fill_color: Color::Rgb {
+                r: 127,
+                g: 0,
+                b: 185,
+            },
+            empty_color: Color::DarkGrey,
+            time: TimeFormat::ElapsedTotal,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct Ui {
+    // Most frames drawn a second, changes coming in faster are drawn together
+    pub fps: u32,
+    // Views on the tab bar, in order: queue, library, playlists and lyrics
+    pub tabs: Vec<View>,
+}
+
+impl Default for Ui {
+    fn default() -> Self {
+        Self {
+            fps: 60,
+            tabs: tabs::DEFAULT.to_vec(),
+        }
     }
 }
 
-impl EventWrapper {
-    pub fn matches(&self, case: &Event) -> bool {
-        self.iter().any(|v| v == case)
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct Queue {
+    // How many queue changes can be undone
+    pub history_depth: usize,
+}
+
+impl Default for Queue {
+    fn default() -> Self {
+        Self { history_depth: 50 }
     }
 }
-impl From<Vec<Event>> for EventWrapper {
-    fn from(value: Vec<Event>) -> Self {
-        Self(value)
+
+// Which destructive actions ask before running
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct Confirm {
+    pub clear_queue: bool,
+    pub delete_songs: bool,
+    pub crop_queue: bool,
+    pub delete_playlist: bool,
+    pub delete_playlist_songs: bool,
+    pub replace_queue: bool,
+}
+
+impl Default for Confirm {
+    fn default() -> Self {
+        Self {
+            clear_queue: true,
+            delete_songs: false,
+            crop_queue: false,
+            delete_playlist: true,
+            delete_playlist_songs: true,
+            replace_queue: true,
+        }
     }
 }
 
+// Keymaps per mode, normal mode has one for every view that is tried before the shared one
 #[derive(Serialize, Deserialize)]
-pub struct Keybinds {
-    //UI Keybinds
-    pub up: EventWrapper,
-    pub down: EventWrapper,
-
-    //Player Keybinds
-    pub play_pause: EventWrapper,
-    pub stop: EventWrapper,
-        pub next: EventWrapper,
-    pub prev: EventWrapper,
-    pub repeat: EventWrapper,
-    pub shuffle: EventWrapper,
-    pub clear_queue: EventWrapper,
-    pub add_to_queue: EventWrapper,
-    pub vol_up: EventWrapper,
-    pub vol_down: EventWrapper,
-}
-
-impl Default for Keybinds {
+#[serde(default)]
+pub struct Keymaps {
+    #[serde(serialize_with = "serialize_key", deserialize_with = "deserialize_key")]
+    pub leader: Event,
+    // How long to wait for the next key of a sequence, in milliseconds
+    pub timeout: u64,
+    pub normal: Keymap,
+    pub queue: Keymap,
+    pub library: Keymap,
+    pub playlists: Keymap,
+    pub lyrics: Keymap,
+    // Used while the tag editor is open, before the normal one
+    pub tags: Keymap,
+    // Same for the playback modes menu
+    pub modes: Keymap,
+    pub search: Keymap,
+    pub command: Keymap,
+    pub popup: Keymap,
+}
+
+impl Default for Keymaps {
     fn default() -> Self {
+        let editing = [
+            ("<Esc>", Action::Cancel),
+            ("C-c", Action::Cancel),
+            ("<Enter>", Action::Submit),
+            ("<BS>", Action::DeleteChar),
+            ("<Up>", Action::HistoryPrev),
+            ("<Down>", Action::HistoryNext),
+        ];
+        let mut command = Keymap::from(editing);
+        command
+            .0
+            .extend(Keymap::from([("<Tab>", Action::Complete)]).0);
+
         Self {
-            up: vec![Event::Key(KeyEvent::new(
-                KeyCode::Char('j'),
-                KeyModifiers::NONE,
-            ))]
-            .into(),
-            down: vec![Event::Key(KeyEvent::new(
-                KeyCode::Char('k'),
-                KeyModifiers::NONE,
-            ))]
-            .into(),
-            play_pause: vec![
-                Event::Key(KeyEvent::new(
-                    KeyCode::Media(crossterm::event::MediaKeyCode::Play),
-                    KeyModifiers::NONE,
-                )),
-                Event::Key(KeyEvent::new(KeyCode::Char(' '), KeyModifiers::NONE)),
-            ]
-            .into(),
-            stop: vec![
-                Event::Key(KeyEvent::new(
-                    KeyCode::Media(crossterm::event::MediaKeyCode::Stop),
-                    KeyModifiers::NONE,
-                )),
-                Event::Key(KeyEvent::new(KeyCode::Char('s'), KeyModifiers::NONE)),
-            ]
-            .into(),
-              repeat: vec![Event::Key(KeyEvent::new(
-                KeyCode::Char('r'),
-                KeyModifiers::NONE,
-            ))]
-            .into(),
-            shuffle: vec![Event::Key(KeyEvent::new(
-                KeyCode::Char('s'),
-                KeyModifiers::SHIFT,
-            ))]
-            .into(),
-
-            clear_queue: vec![Event::Key(KeyEvent::new(
-                KeyCode::Char('d'),
-                KeyModifiers::NONE,
-            ))]
-            .into(),
-            add_to_queue: vec![Event::Key(KeyEvent::new(
-                KeyCode::Char('a'),
-                KeyModifiers::NONE,
-            ))]
-            .into(),
-            next: vec![
-                Event::Key(KeyEvent::new(KeyCode::Char('n'), KeyModifiers::NONE)),
-                Event::Key(KeyEvent::new(
-                    KeyCode::Media(crossterm::event::MediaKeyCode::TrackNext),
-                    KeyModifiers::NONE,
-                )),
-            ]
-            .into(),
-            prev: vec![
-                Event::Key(KeyEvent::new(KeyCode::Char('N'), KeyModifiers::NONE)),
-                Event::Key(KeyEvent::new(
-                    KeyCode::Media(crossterm::event::MediaKeyCode::TrackPrevious),
-                    KeyModifiers::NONE,
-                )),
-            ]
-            .into(),
-            vol_up: vec![
-                Event::Key(KeyEvent::new(KeyCode::Char('+'), KeyModifiers::NONE)),
-                Event::Key(KeyEvent::new(
-                    KeyCode::Media(crossterm::event::MediaKeyCode::RaiseVolume),
-                    KeyModifiers::NONE,
-                )),
-            ]
-            .into(),
-            vol_down: vec![
-                Event::Key(KeyEvent::new(KeyCode::Char('-'), KeyModifiers::NONE)),
-                Event::Key(KeyEvent::new(
-                    KeyCode::Media(crossterm::event::MediaKeyCode::LowerVolume),
-                    KeyModifiers::NONE,
-                )),
-            ]
-            .into(),
+            leader: parse_key("\\").expect("Invalid built-in key"),
+            timeout: 1000,
+            normal: Keymap::from([
+                ("q", Action::Quit),
+                ("?", Action::Help),
+                ("E", Action::Errors),
+                ("i", Action::SongInfo),
+                ("e", Action::EditTags),
+                ("C-c", Action::Quit),
+                ("C-z", Action::Suspend),
+                ("<Esc>", Action::Quit),
+                ("<MediaPlay>", Action::PlayPause),
+                ("<Space>", Action::PlayPause),
+                ("n", Action::Next),
+                ("<MediaTrackNext>", Action::Next),
+                ("N", Action::Prev),
+                ("<MediaTrackPrevious>", Action::Prev),
+                ("<MediaStop>", Action::Stop),
+                ("s", Action::Stop),
+                ("r", Action::Repeat),
+                ("z", Action::Random),
+                ("y", Action::Single),
+                ("R", Action::Consume),
+                ("O", Action::PlaybackModes),
+                ("Z", Action::Shuffle),
+                ("+", Action::VolUp),
+                ("<MediaRaiseVolume>", Action::VolUp),
+                ("-", Action::VolDown),
+                ("<MediaLowerVolume>", Action::VolDown),
+                ("A-+", Action::VolUpFine),
+                ("A--", Action::VolDownFine),
+                ("M", Action::Mute),
+                ("v", Action::VolumeSlider),
+                ("]", Action::SeekForward),
+                ("<MediaFastForward>", Action::SeekForward),
+                ("[", Action::SeekBack),
+                ("<MediaRewind>", Action::SeekBack),
+                ("d", Action::ClearQueue),
+                ("u", Action::Undo),
+                ("C-r", Action::Redo),
+                ("j", Action::Down),
+                ("k", Action::Up),
+                ("gg", Action::Top),
+                ("G", Action::Bottom),
+                ("<Tab>", Action::SwitchView),
+                ("<BackTab>", Action::PrevTab),
+                ("A-1", Action::Tab1),
+                ("A-2", Action::Tab2),
+                ("A-3", Action::Tab3),
+                ("A-4", Action::Tab4),
+                ("A-5", Action::Tab5),
+                ("A-6", Action::Tab6),
+                ("A-7", Action::Tab7),
+                ("A-8", Action::Tab8),
+                ("A-9", Action::Tab9),
+                ("L", Action::Lyrics),
+                (":", Action::CommandLine),
+                ("/", Action::Search),
+                ("t", Action::ToggleMark),
+                ("<Insert>", Action::ToggleMark),
+                ("V", Action::VisualMode),
+                ("C-a", Action::SelectAll),
+                ("I", Action::InvertSelection),
+                ("b", Action::SelectAlbum),
+                ("<BS>", Action::ClearSelection),
+                ("w", Action::SavePlaylist),
+                ("P", Action::AddToPlaylist),
+                ("a", Action::AddToQueue),
+                ("<Enter>", Action::PlaySelected),
+                ("x", Action::DeleteSong),
+                ("<Del>", Action::DeleteSong),
+                ("K", Action::MoveUp),
+                ("J", Action::MoveDown),
+            ]),
+            queue: Keymap::from([
+                ("T", Action::MoveToTop),
+                ("m", Action::MoveAfterCurrent),
+                ("C", Action::CropQueue),
+                ("p", Action::Priority),
+                ("<leader>z", Action::ShuffleSelected),
+                ("<leader>Z", Action::ShuffleRest),
+            ]),
+            library: Keymap::default(),
+            playlists: Keymap::from([
+                ("<Left>", Action::Back),
+                ("h", Action::Back),
+                ("o", Action::LoadPlaylist),
+                ("R", Action::RenamePlaylist),
+            ]),
+            lyrics: Keymap::default(),
+            tags: Keymap::from([
+                ("<Enter>", Action::EditField),
+                ("c", Action::EditField),
+                ("x", Action::KeepField),
+                ("<Del>", Action::KeepField),
+                ("w", Action::WriteTags),
+            ]),
+            modes: Keymap::from([
+                ("<Enter>", Action::ChooseValue),
+                ("<Space>", Action::NextValue),
+                ("l", Action::NextValue),
+                ("<Right>", Action::NextValue),
+                ("h", Action::PrevValue),
+                ("<Left>", Action::PrevValue),
+            ]),
+            search: Keymap::from(editing),
+            command,
+            popup: Keymap::from([
+                ("<Esc>", Action::Cancel),
+                ("<Enter>", Action::Submit),
+                ("<BS>", Action::DeleteChar),
+                ("y", Action::Confirm),
+                ("Y", Action::Confirm),
+                ("n", Action::Deny),
+                ("N", Action::Deny),
+                ("q", Action::Deny),
+                ("<Space>", Action::Deny),
+            ]),
         }
     }
 }
 
+impl Keymaps {
+    // Every keymap under its name in the config
+    pub fn named(&self) -> [(&'static str, &Keymap); 10] {
+        [
+            ("normal", &self.normal),
+            ("queue", &self.queue),
+            ("library", &self.library),
+            ("playlists", &self.playlists),
+            ("lyrics", &self.lyrics),
+            ("tags", &self.tags),
+            ("modes", &self.modes),
+            ("search", &self.search),
+            ("command", &self.command),
+            ("popup", &self.popup),
+        ]
+    }
+
+    pub fn named_mut(&mut self) -> [(&'static str, &mut Keymap); 10] {
+        [
+            ("normal", &mut self.normal),
+            ("queue", &mut self.queue),
+            ("library", &mut self.library),
+            ("playlists", &mut self.playlists),
+            ("lyrics", &mut self.lyrics),
+            ("tags", &mut self.tags),
+            ("modes", &mut self.modes),
+            ("search", &mut self.search),
+            ("command", &mut self.command),
+            ("popup", &mut self.popup),
+        ]
+    }
+
+    pub fn conflicts(&self) -> Vec<String> {
+        let named = self.named();
+        let mut conflicts: Vec<String> = named
+            .into_iter()
+            .flat_map(|(name, map)| {
+                map.conflicts().into_iter().map(move |(keys, actions)| {
+                    let actions: Vec<_> = actions.iter().map(|action| action.name()).collect();
+                    format!(
+                        "\"{}\" in {name} is bound to {}",
+                        keys_name(keys),
+                        actions.join(", ")
+                    )
+                })
+            })
+            .collect();
+        // Keys are looked up in the map of the view or sheet together with normal,
+        // where a view taking over a key of normal is fine but a prefix still waits
+        let normal = named[0];
+        let pairs = named.into_iter().map(|map| (map, map)).chain(
+            named[1..7]
+                .iter()
+                .flat_map(|&view| [(view, normal), (normal, view)]),
+        );
+        for ((name, map), (other_name, other)) in pairs {
+            for (short, long) in map.prefixes(other) {
+                let conflict = format!(
+                    "\"{}\" in {name} waits for more keys, as \"{}\" in {other_name} starts with it",
+                    keys_name(short),
+                    keys_name(long)
+                );
+                if !conflicts.contains(&conflict) {
+                    conflicts.push(conflict);
+                }
+            }
+        }
+        conflicts
+    }
+}
+
 #[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
 pub struct Config {
     pub mpd: Mpd,
-    pub keybinds: Keybinds,
+    pub queue: Queue,
+    pub confirm: Confirm,
+    pub lyrics: Lyrics,
+    pub art: Art,
+    pub volume: Volume,
+    pub ui: Ui,
+    pub progress: Progress,
+    pub keymaps: Keymaps,
+    // Keys per action, as configs had them before keymaps
+    #[serde(skip_serializing)]
+    keybinds: BTreeMap<String, Vec<LegacyKey>>,
 }
 
 impl Config {
+    fn path() -> Option<PathBuf> {
+        config_dir().map(|p| p.join(env!("CARGO_PKG_NAME")).join("config.yaml"))
+    }
+
+    // Reads the config file, writing one with the defaults if there is none yet
+    pub fn load() -> Result<Self> {
+        let Some(path) = Self::path() else {
+            return Err(Error::msg("Failed to find the Config directory"));
+        };
+        if !path.exists() {
+            let conf = Self::default();
+            conf.generate_config()?;
+            return Ok(conf);
+        }
+        let text = fs::read_to_string(&path)?;
+        let conf =
+            Self::from_yaml(&text).with_context(|| format!("Invalid config {}", path.display()))?;
+        for conflict in conf.keymaps.conflicts() {
+            warn!("Key conflict: {conflict}");
+        }
+        Ok(conf)
+    }
+
+    pub fn from_yaml(text: &str) -> Result<Self> {
+        let mut conf: Config = serde_yml::from_str(text)?;
+        conf.apply_keybinds()?;
+        Ok(conf)
+    }
+
+    // Keys of an old `keybinds` entry replace the normal mode keys of that action
+    fn apply_keybinds(&mut self) -> Result<()> {
+        for (name, keys) in std::mem::take(&mut self.keybinds) {
+            let action = Action::from_name(&name)
+                .ok_or_else(|| anyhow!("Unknown action \"{name}\" in keybinds"))?;
+            let maps = &mut self.keymaps;
+            for map in [
+                &mut maps.normal,
+                &mut maps.queue,
+                &mut maps.library,
+                &mut maps.playlists,
+                &mut maps.lyrics,
+            ] {
+                map.0.retain(|binding| binding.action != action);
+            }
+            for key in keys {
+                maps.normal.bind(vec![Key::Event(key.0)], action);
+            }
+        }
+        Ok(())
+    }
+
     pub fn generate_config(&self) -> Result<()> {
-        let home = config_dir();
-        if let Some(p) = home {
-            let conf_path = p.join(env!("CARGO_PKG_NAME")).join("config.yaml");
+        if let Some(conf_path) = Self::path() {
             if conf_path.exists() {
                 info!("Config exists. Skipping Generation");
                 return Ok(());
@@ -1,102 +1,70 @@
 use std::{
-    borrow::BorrowMut,
     io::{self, Stdout, Write},
-    time::Duration,
+    mem, panic,
 };
 
 use anyhow::Result;
 use crossterm::{
-    cursor::{Hide, MoveTo},
+    cursor::{Hide, MoveTo, Show},
     event::{
-        poll, read, DisableMouseCapture, EnableMouseCapture, Event, KeyboardEnhancementFlags,
-        PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags,
+        DisableBracketedPaste, DisableMouseCapture, EnableBracketedPaste, EnableMouseCapture,
+        KeyboardEnhancementFlags, PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags,
     },
     execute, queue,
-    style::{Attribute, Color, PrintStyledContent, Stylize},
-    terminal::{self, supports_keyboard_enhancement, EnterAlternateScreen, LeaveAlternateScreen},
+    style::{Attribute, Color, Print, PrintStyledContent, Stylize},
+    terminal::{
+        self, supports_keyboard_enhancement, Clear, ClearType, EnterAlternateScreen,
+        LeaveAlternateScreen,
+    },
     ExecutableCommand,
 };
-use log::error;
-
-use super::ui::{Overflow, Rect, Render, UI};
 
-#[derive(Clone)]
-struct Patch {
-    x: u32,
-    y: u32,
-    data: Cell,
-}
+use super::{
+    buffer::{Buffer, Patch},
+    ui::{Overflow, Rect, Render, UI},
+};
 
-#[derive(Clone, PartialEq, Eq, PartialOrd, Ord)]
-struct Cell {
-    attribute: Option<Attribute>,
-    char: String,
-    fg: Color,
-    bg: Color,
-}
-impl Default for Cell {
-    fn default() -> Self {
-        Self {
-            attribute: None,
-            char: " ".to_owned(),
-            fg: Color::Reset,
-            bg: Color::Reset,
-        }
+// Raw mode, the alternate screen and the input modes the UI runs in
+fn enter(stdout: &mut Stdout, keyboard_enhancement: bool) -> Result<()> {
+    terminal::enable_raw_mode()?;
+    execute!(
+        stdout,
+        EnableMouseCapture,
+        EnableBracketedPaste,
+        EnterAlternateScreen,
+        Hide
+    )?;
+    if keyboard_enhancement {
+        stdout.execute(PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::all()))?;
     }
+    Ok(())
 }
 
-#[derive(Clone)]
-struct Buffer {
-    data: Vec<Cell>,
-    screen: Rect,
+// Puts the terminal back the way the shell had it. Safe to call more than once,
+// which the panic hook relies on
+pub fn restore() -> Result<()> {
+    let mut stdout = io::stdout();
+    queue!(
+        stdout,
+        DisableMouseCapture,
+        DisableBracketedPaste,
+        PopKeyboardEnhancementFlags,
+        LeaveAlternateScreen,
+        Show
+    )?;
+    stdout.flush()?;
+    terminal::disable_raw_mode()?;
+    Ok(())
 }
-impl Buffer {
-    fn new(rect: Rect) -> Self {
-        Self {
-            data: vec![Cell::default(); (rect.height * rect.width) as usize],
-            screen: rect,
-        }
-    }
 
-    fn get(&mut self, x: usize, y: usize) -> Option<&mut Cell> {
-        let idx = y * self.screen.width as usize + x;
-        if idx < self.data.len() {
-            Some(self.data[idx].borrow_mut())
-        } else {
-            None
-        }
-    }
-
-    fn diff(&self, other: &Self) -> Vec<Patch> {
-        if self.data.len() != other.data.len() {
-            self.data
-                .iter()
-                .enumerate()
-                .map(|(idx, a)| Patch {
-                    data: a.clone(),
-                    x: idx as u32 % self.screen.width,
-                    y: idx as u32 / self.screen.width,
-                })
-                .collect::<Vec<_>>()
-        } else {
-            self.data
-                .iter()
-                .zip(&other.data)
-                .enumerate()
-                .filter_map(|(idx, (a, b))| {
-                    if a.ne(b) {
-                        Some(Patch {
-                            data: a.clone(),
-                            x: idx as u32 % self.screen.width,
-                            y: idx as u32 / self.screen.width,
-                        })
-                    } else {
-                        None
-                    }
-                })
-                .collect::<Vec<_>>()
-        }
-    }
+// Restores the terminal before the panic message is printed, so it ends up on the
+// normal screen instead of vanishing with the alternate one
+fn install_panic_hook() {
+    let hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        let _ = restore();
+        hook(info);
+    }));
 }
 
 pub struct Crossterm {
@@ -104,11 +72,21 @@ pub struct Crossterm {
     pub stdout: Stdout,
     prev_buffer: Buffer,
     buffer: Buffer,
+    // Kept between frames so diffing allocates nothing once it has grown
+    patches: Vec<Patch>,
+    // Graphics of this frame and the ones on screen now
+    graphics: Vec<(Rect, String)>,
+    shown_graphics: Vec<(Rect, String)>,
+    // Asked once at the start, since the answer comes in on the same input the
+    // event thread reads later on
+    keyboard_enhancement: bool,
 }
 
 impl UI for Crossterm {
     fn init() -> Result<Self> {
+        install_panic_hook();
         terminal::enable_raw_mode()?;
+        let keyboard_enhancement = supports_keyboard_enhancement()?;
         let size = terminal::size()?;
         let rect = Rect {
             x: 0,
@@ -121,137 +99,120 @@ impl UI for Crossterm {
             screen: rect,
             buffer: Buffer::new(rect),
             prev_buffer: Buffer::new(rect),
+            patches: vec![],
+            graphics: vec![],
+            shown_graphics: vec![],
+            keyboard_enhancement,
         };
-        execute!(ct.stdout, EnableMouseCapture, EnterAlternateScreen, Hide)?;
-        if supports_keyboard_enhancement()? {
-            ct.stdout
-                .execute(PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::all()))?;
-        }
+        enter(&mut ct.stdout, keyboard_enhancement)?;
         Ok(ct)
     }
-    fn read_event(&mut self) -> anyhow::Result<crossterm::event::Event> {
-        match read() {
-            Ok(event) => {
-                if let Event::Resize(cols, rows) = event {
-                    self.screen.width = cols.into();
-                    self.screen.height = rows.into();
-                    self.buffer = Buffer::new(self.screen);
-                }
-                Ok(event)
-            }
-            Err(err) => {
-                error!("Failed to read Event: {err}");
-                Err(err.into())
-            }
-        }
-    }
-    fn is_event_ready(&self) -> bool {
-        poll(Duration::from_millis(250)).unwrap_or(false)
+    fn resize(&mut self, width: u32, height: u32) {
+        self.screen.width = width;
+        self.screen.height = height;
+        self.buffer = Buffer::new(self.screen);
     }
     fn destroy(&mut self) -> Result<()> {
-        queue!(
-            self.stdout,
-            DisableMouseCapture,
-            PopKeyboardEnhancementFlags,
-            LeaveAlternateScreen
-        )?;
-        self.stdout.flush()?;
-        terminal::disable_raw_mode()?;
+        restore()
+    }
+    fn resume(&mut self) -> Result<()> {
+        enter(&mut self.stdout, self.keyboard_enhancement)?;
+        execute!(self.stdout, Clear(ClearType::All))?;
+        // The size may have changed while stopped, and nothing drawn before is
+        // left on screen, so the next frame is sent in full
+        let (width, height) = terminal::size()?;
+        self.screen.width = width.into();
+        self.screen.height = height.into();
+        self.buffer = Buffer::new(self.screen);
+        self.prev_buffer = Buffer::new(Rect::default());
+        self.shown_graphics.clear();
         Ok(())
     }
 }
 
 impl Render for Crossterm {
     fn set_text(&mut self, rect: Rect, text: &str, overflow: Overflow) {
-        {
-            for x in rect.x..(rect.x + rect.width) {
-                for y in rect.y..(rect.y + rect.height) {
-                    if let Some(cell) = self.buffer.get(x as usize, y as usize) {
-                        cell.char = " ".to_string()
-                    };
-                }
-            }
-        }
-
-        let mut x = 0;
-        let mut y = 0;
-
         match overflow {
-            Overflow::Char => {
-                for (idx, char) in text.char_indices() {
-                    if idx % rect.width as usize == 0 && idx != 0 || char == '\n' {
-                        y += 1;
-                        x = 0;
-                    }
-                    if y >= rect.height {
-                        break;
-                    }
-                    if char == '\n' {
-                        continue;
-                    }
-                    if let Some(cell) = self.buffer.get(x + rect.x as usize, (y + rect.y) as usize)
-                    {
-                        cell.char = char.to_string();
-
-                        x += 1;
-                    }
-                }
-            }
-            Overflow::Word => {
-                todo!()
-            }
+            Overflow::Char => self.buffer.set_text(rect, text),
         }
     }
 
     fn set_background(&mut self, rect: Rect, color: Color) {
         for x in rect.x..(rect.x + rect.width) {
             for y in rect.y..(rect.y + rect.height) {
-                if let Some(cell) = self.buffer.get(x as usize, y as usize) {
-                    cell.bg = color
-                };
+                self.buffer
+                    .update(x as usize, y as usize, |cell| cell.bg = color);
             }
         }
     }
     fn set_foreground(&mut self, rect: Rect, color: Color) {
         for x in rect.x..(rect.x + rect.width) {
             for y in rect.y..(rect.y + rect.height) {
-                if let Some(cell) = self.buffer.get(x as usize, y as usize) {
-                    cell.fg = color
-                };
+                self.buffer
+                    .update(x as usize, y as usize, |cell| cell.fg = color);
             }
         }
     }
     fn set_attributes(&mut self, rect: Rect, attr: Attribute) {
         for x in rect.x..(rect.x + rect.width) {
             for y in rect.y..(rect.y + rect.height) {
-                if let Some(cell) = self.buffer.get(x as usize, y as usize) {
-                    cell.attribute = Some(attr);
-                };
+                self.buffer
+                    .update(x as usize, y as usize, |cell| cell.attribute = Some(attr));
             }
         }
     }
+    fn set_graphic(&mut self, rect: Rect, escape: &str) {
+        self.graphics.push((rect, escape.to_owned()));
+    }
+
     fn render_frame(&mut self) -> Result<()> {
-        let patches = self.buffer.diff(&self.prev_buffer);
-        for patch in &patches {
-            let mut p = patch
-                .data
-                .char
-                .clone()
-                .with(patch.data.fg)
-                .on(patch.data.bg);
-            if let Some(attr) = patch.data.attribute {
+        let graphics = mem::take(&mut self.graphics);
+        let graphics_changed = graphics != self.shown_graphics;
+        if graphics_changed {
+            // Kitty keeps images apart from the cells, Sixel paints over them, so
+            // both are cleared by deleting every image and repainting their cells
+            queue!(self.stdout, Print("\x1b_Ga=d,d=A,q=2\x1b\\"))?;
+            for (rect, _) in &self.shown_graphics {
+                self.prev_buffer.invalidate(*rect);
+            }
+        }
+        self.buffer.diff(&self.prev_buffer, &mut self.patches);
+        let mut cursor = None;
+        for patch in &self.patches {
+            // Covered by the wide grapheme before it
+            if patch.cell.symbol.is_continuation() {
+                continue;
+            }
+            let text = patch.cell.symbol.as_str();
+            let mut p = text.with(patch.cell.fg).on(patch.cell.bg);
+            if let Some(attr) = patch.cell.attribute {
                 p = p.attribute(attr);
             };
-            queue!(
-                self.stdout,
-                MoveTo(patch.x as u16, patch.y as u16),
-                PrintStyledContent(p)
-            )?;
+            // Printing moves the cursor along, so runs of cells need one move
+            if cursor != Some((patch.x, patch.y)) {
+                queue!(self.stdout, MoveTo(patch.x as u16, patch.y as u16))?;
+            }
+            queue!(self.stdout, PrintStyledContent(p))?;
+            // Terminals disagree on how far wide and joined graphemes move it
+            let simple = text.chars().nth(1).is_none() && patch.cell.symbol.width() == 1;
+            cursor = simple.then_some((patch.x + 1, patch.y));
+        }
+        if graphics_changed {
+            for (rect, escape) in &graphics {
+                queue!(
+                    self.stdout,
+                    MoveTo(rect.x as u16, rect.y as u16),
+                    Print(escape)
+                )?;
+            }
+            self.shown_graphics = graphics;
         }
-        if !patches.is_empty() {
+        if !self.patches.is_empty() || graphics_changed {
             self.stdout.flush()?;
-            self.prev_buffer = self.buffer.clone();
         }
+        // What was drawn is now on screen, and the old frame is drawn over next
+        mem::swap(&mut self.buffer, &mut self.prev_buffer);
+        self.buffer.sync(&mut self.prev_buffer);
         Ok(())
     }
 }
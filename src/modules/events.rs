@@ -0,0 +1,149 @@
+use std::{
+    net::SocketAddr,
+    sync::{
+        mpsc::{self, Receiver, RecvTimeoutError, Sender},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use crossterm::event::{self, Event};
+use image::DynamicImage;
+use log::{info, warn};
+use mpd::{idle::Subsystem, Client, Idle};
+
+use super::{art, mpd::Mpd};
+
+// How long the input thread waits before letting go of the terminal for a moment
+const INPUT_POLL: Duration = Duration::from_millis(100);
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(2);
+
+// Everything that wakes the main loop up
+#[derive(Debug)]
+pub enum Message {
+    Input(Event),
+    // MPD reported changes to these subsystems
+    Changed(Vec<Subsystem>),
+    // The timer, for key timeouts, toasts and the periodic poll
+    Tick,
+    // The cover of a file, or None when it has none
+    Art(String, Option<Arc<DynamicImage>>),
+}
+
+// Merges terminal input, MPD change notifications and a timer tick into one queue,
+// so the main loop sleeps until there is something to do
+pub struct Events {
+    sender: Sender<Message>,
+    receiver: Receiver<Message>,
+    tick: Duration,
+    next_tick: Instant,
+}
+
+impl Events {
+    pub fn new(tick: Duration) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        Self {
+            sender,
+            receiver,
+            tick,
+            next_tick: Instant::now() + tick,
+        }
+    }
+
+    // For messages from anywhere else, like tests
+    pub fn sender(&self) -> Sender<Message> {
+        self.sender.clone()
+    }
+
+    // Reads the terminal on a thread of its own. It polls instead of blocking in
+    // `read`, which would hold crossterm's reader lock for good
+    pub fn watch_input(&self) {
+        let sender = self.sender();
+        thread::spawn(move || loop {
+            match event::poll(INPUT_POLL) {
+                Ok(false) => continue,
+                Ok(true) => {}
+                Err(err) => {
+                    warn!("Stopped reading input: {err}");
+                    return;
+                }
+            }
+            match event::read() {
+                Ok(event) => {
+                    if sender.send(Message::Input(event)).is_err() {
+                        return;
+                    }
+                }
+                Err(err) => warn!("Failed to read Event: {err}"),
+            }
+        });
+    }
+
+    // Waits on MPD's `idle` over a connection of its own, reconnecting whenever it
+    // drops. Each (re)connect is reported too, since changes in between were missed
+    pub fn watch_mpd(&self, addr: SocketAddr) {
+        let sender = self.sender();
+        thread::spawn(move || loop {
+            if let Ok(mut client) = Client::connect(addr) {
+                info!("Watching MPD for changes at {addr}");
+                let mut changed = vec![];
+                loop {
+                    if sender.send(Message::Changed(changed)).is_err() {
+                        return;
+                    }
+                    match client.wait(&[]) {
+                        Ok(subsystems) => changed = subsystems,
+                        Err(err) => {
+                            warn!("Stopped watching MPD for changes: {err}");
+                            break;
+                        }
+                    }
+                }
+            }
+            thread::sleep(RECONNECT_INTERVAL);
+        });
+    }
+
+    // Fetches and decodes the covers of the files sent to the returned sender, over
+    // a connection of its own. Files asked for while busy are skipped but the last
+    pub fn watch_art(&self, addr: SocketAddr) -> Sender<String> {
+        let sender = self.sender();
+        let (requests, files) = mpsc::channel::<String>();
+        thread::spawn(move || {
+            let mut mpd: Mpd = Mpd::new(addr);
+            while let Ok(file) = files.recv() {
+                let file = files.try_iter().last().unwrap_or(file);
+                let image = art::fetch(&mut mpd, &file);
+                if sender.send(Message::Art(file, image)).is_err() {
+                    return;
+                }
+            }
+        });
+        requests
+    }
+
+    // The next message, or a tick once one is due. Gives up with None at `until`,
+    // which the main loop sets while a frame is waiting to be drawn
+    pub fn next(&mut self, until: Option<Instant>) -> Option<Message> {
+        // Checked first, so a stream of held keys can't hold the ticks back
+        if Instant::now() >= self.next_tick {
+            self.next_tick = Instant::now() + self.tick;
+            return Some(Message::Tick);
+        }
+        let deadline = until.map_or(self.next_tick, |until| until.min(self.next_tick));
+        match self
+            .receiver
+            .recv_timeout(deadline.saturating_duration_since(Instant::now()))
+        {
+            Ok(message) => Some(message),
+            Err(RecvTimeoutError::Timeout | RecvTimeoutError::Disconnected) => {
+                if Instant::now() < self.next_tick {
+                    return None;
+                }
+                self.next_tick = Instant::now() + self.tick;
+                Some(Message::Tick)
+            }
+        }
+    }
+}
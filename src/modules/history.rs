@@ -0,0 +1,57 @@
+use std::collections::VecDeque;
+
+// Bounded undo/redo stacks of snapshots
+pub struct History<T> {
+    undo: VecDeque<T>,
+    redo: Vec<T>,
+    depth: usize,
+}
+
+impl<T> History<T> {
+    pub fn new(depth: usize) -> Self {
+        Self {
+            undo: VecDeque::new(),
+            redo: vec![],
+            depth,
+        }
+    }
+
+    pub fn set_depth(&mut self, depth: usize) {
+        self.depth = depth;
+        self.undo.truncate(depth);
+        self.redo.truncate(depth);
+    }
+
+    // Stores the state from before a change, which invalidates anything undone
+    pub fn record(&mut self, state: T) {
+        if self.depth == 0 {
+            return;
+        }
+        self.redo.clear();
+        self.undo.push_front(state);
+        self.undo.truncate(self.depth);
+    }
+
+    // Swaps `current` for the previous state
+    pub fn undo(&mut self, current: T) -> Option<T> {
+        let previous = self.undo.pop_front()?;
+        self.redo.push(current);
+        Some(previous)
+    }
+
+    // Swaps `current` for the state that was last undone
+    pub fn redo(&mut self, current: T) -> Option<T> {
+        let next = self.redo.pop()?;
+        self.undo.push_front(current);
+        self.undo.truncate(self.depth);
+        Some(next)
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+}
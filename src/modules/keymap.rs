@@ -0,0 +1,617 @@
+use std::{
+    fmt,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use crossterm::event::{
+    Event, KeyCode, KeyEvent, KeyEventKind, KeyEventState, KeyModifiers, MediaKeyCode,
+};
+use serde::{
+    de::{
+        self,
+        value::{EnumAccessDeserializer, MapAccessDeserializer},
+        EnumAccess, MapAccess, SeqAccess, Visitor,
+    },
+    ser::SerializeMap,
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+
+use super::command::Action;
+
+// One key of a binding, where `Leader` stands for the configured leader key
+#[derive(Debug, Clone, PartialEq)]
+pub enum Key {
+    Leader,
+    Event(Event),
+}
+
+impl Key {
+    // `key` has to be normalized already
+    fn matches(&self, key: &KeyEvent, leader: &Event) -> bool {
+        let bound = match self {
+            Key::Leader => leader,
+            Key::Event(event) => event,
+        };
+        let Event::Key(bound) = bound else {
+            return false;
+        };
+        let bound = normalize(*bound);
+        bound.code == key.code
+            && bound.modifiers == key.modifiers
+            && (bound.kind == KeyEventKind::Release) == (key.kind == KeyEventKind::Release)
+    }
+}
+
+// Terminals report the same key in different ways, so bindings and typed keys are
+// compared in one form: Shift only on letters, which are then uppercase, Shift+Tab
+// as BackTab and no lock state
+pub fn normalize(mut key: KeyEvent) -> KeyEvent {
+    key.state = KeyEventState::NONE;
+    match key.code {
+        KeyCode::Char(c)
+            if c.is_alphabetic()
+                && (key.modifiers.contains(KeyModifiers::SHIFT) || c.is_uppercase()) =>
+        {
+            key.modifiers |= KeyModifiers::SHIFT;
+            key.code = KeyCode::Char(c.to_uppercase().next().unwrap_or(c));
+        }
+        KeyCode::Char(_) | KeyCode::BackTab => key.modifiers -= KeyModifiers::SHIFT,
+        KeyCode::Tab if key.modifiers.contains(KeyModifiers::SHIFT) => {
+            key.code = KeyCode::BackTab;
+            key.modifiers -= KeyModifiers::SHIFT;
+        }
+        _ => {}
+    }
+    key
+}
+
+const MEDIA_KEYS: [(&str, MediaKeyCode); 13] = [
+    ("Play", MediaKeyCode::Play),
+    ("Pause", MediaKeyCode::Pause),
+    ("PlayPause", MediaKeyCode::PlayPause),
+    ("Reverse", MediaKeyCode::Reverse),
+    ("Stop", MediaKeyCode::Stop),
+    ("FastForward", MediaKeyCode::FastForward),
+    ("Rewind", MediaKeyCode::Rewind),
+    ("TrackNext", MediaKeyCode::TrackNext),
+    ("TrackPrevious", MediaKeyCode::TrackPrevious),
+    ("Record", MediaKeyCode::Record),
+    ("LowerVolume", MediaKeyCode::LowerVolume),
+    ("RaiseVolume", MediaKeyCode::RaiseVolume),
+    ("MuteVolume", MediaKeyCode::MuteVolume),
+];
+
+// Names written as `<Name>`, the first one is used when writing a key out
+const NAMED_KEYS: [(&str, KeyCode); 22] = [
+    ("Space", KeyCode::Char(' ')),
+    ("lt", KeyCode::Char('<')),
+    ("Enter", KeyCode::Enter),
+    ("CR", KeyCode::Enter),
+    ("Tab", KeyCode::Tab),
+    ("BackTab", KeyCode::BackTab),
+    ("Esc", KeyCode::Esc),
+    ("BS", KeyCode::Backspace),
+    ("Backspace", KeyCode::Backspace),
+    ("Del", KeyCode::Delete),
+    ("Delete", KeyCode::Delete),
+    ("Insert", KeyCode::Insert),
+    ("Left", KeyCode::Left),
+    ("Right", KeyCode::Right),
+    ("Up", KeyCode::Up),
+    ("Down", KeyCode::Down),
+    ("Home", KeyCode::Home),
+    ("End", KeyCode::End),
+    ("PageUp", KeyCode::PageUp),
+    ("PageDown", KeyCode::PageDown),
+    ("Null", KeyCode::Null),
+    ("CapsLock", KeyCode::CapsLock),
+];
+
+fn parse_code(name: &str) -> Result<KeyCode> {
+    let mut chars = name.chars();
+    if let (Some(char), None) = (chars.next(), chars.next()) {
+        return Ok(KeyCode::Char(char));
+    }
+    if let Some(media) = name.strip_prefix("Media") {
+        return MEDIA_KEYS
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(media))
+            .map(|(_, code)| KeyCode::Media(*code))
+            .ok_or_else(|| {
+                let names = MEDIA_KEYS.map(|(key, _)| key).join(", Media");
+                anyhow!("Unknown media key \"{name}\", expected one of Media{names}")
+            });
+    }
+    if let Some(number) = name.strip_prefix(['F', 'f']) {
+        if let Ok(number) = number.parse() {
+            return Ok(KeyCode::F(number));
+        }
+    }
+    NAMED_KEYS
+        .iter()
+        .find(|(key, _)| key.eq_ignore_ascii_case(name))
+        .map(|(_, code)| *code)
+        .ok_or_else(|| {
+            let names = NAMED_KEYS.map(|(key, _)| key).join(", ");
+            anyhow!("Unknown key \"{name}\", expected a character, F1-F12, a Media key or one of {names}")
+        })
+}
+
+// Parses one key like `j`, `J`, `C-d`, `S-s`, `<Space>`, `<C-Left>` or `<MediaPlay>`.
+// Named keys need the brackets, so that bare letters like `end` stay letters
+pub fn parse_key(key: &str) -> Result<Event> {
+    let bracketed = key.strip_prefix('<').and_then(|key| key.strip_suffix('>'));
+    let mut name = bracketed.unwrap_or(key);
+    if name.is_empty() {
+        bail!("Empty key");
+    }
+    let mut modifiers = KeyModifiers::NONE;
+    let mut kind = KeyEventKind::Press;
+    while let Some((modifier, rest)) = name.split_once('-').filter(|(_, rest)| !rest.is_empty()) {
+        match modifier {
+            "C" | "c" => modifiers |= KeyModifiers::CONTROL,
+            "S" | "s" => modifiers |= KeyModifiers::SHIFT,
+            "A" | "a" | "M" | "m" => modifiers |= KeyModifiers::ALT,
+            // Only terminals with keyboard enhancement report releases
+            _ if modifier.eq_ignore_ascii_case("release") => kind = KeyEventKind::Release,
+            _ => bail!("Unknown modifier \"{modifier}\" in \"{key}\", expected C, S, A or Release"),
+        };
+        name = rest;
+    }
+    if bracketed.is_none() && name.chars().nth(1).is_some() {
+        bail!("\"{key}\" is not a single character, named keys are written like <{key}>");
+    }
+    let code = parse_code(name)?;
+    Ok(Event::Key(normalize(KeyEvent::new_with_kind(
+        code, modifiers, kind,
+    ))))
+}
+
+// Old configs stored events as the terminal reported them
+fn normalize_event(event: Event) -> Event {
+    match event {
+        Event::Key(key) => Event::Key(normalize(key)),
+        event => event,
+    }
+}
+
+// Writes a key the way `parse_key` reads it
+pub fn key_name(event: &Event) -> String {
+    let Event::Key(KeyEvent {
+        code,
+        modifiers,
+        kind,
+        ..
+    }) = event
+    else {
+        return format!("{event:?}");
+    };
+    let mut prefix = String::new();
+    if *kind == KeyEventKind::Release {
+        prefix.push_str("Release-");
+    }
+    for (modifier, name) in [
+        (KeyModifiers::CONTROL, "C-"),
+        (KeyModifiers::ALT, "A-"),
+        (KeyModifiers::SHIFT, "S-"),
+    ] {
+        // Shift is already part of uppercase letters
+        let implied = modifier == KeyModifiers::SHIFT && matches!(code, KeyCode::Char(_));
+        if modifiers.contains(modifier) && !implied {
+            prefix.push_str(name);
+        }
+    }
+    let name = match code {
+        KeyCode::Char(c) if *c != ' ' && *c != '<' => c.to_string(),
+        KeyCode::F(number) => format!("F{number}"),
+        KeyCode::Media(media) => MEDIA_KEYS
+            .iter()
+            .find(|(_, code)| code == media)
+            .map(|(key, _)| format!("Media{key}"))
+            .unwrap_or_default(),
+        code => NAMED_KEYS
+            .iter()
+            .find(|(_, named)| named == code)
+            .map(|(key, _)| key.to_string())
+            .unwrap_or_else(|| format!("{code:?}")),
+    };
+    match code {
+        KeyCode::Char(c) if prefix.is_empty() && *c != ' ' && *c != '<' => name,
+        _ => format!("<{prefix}{name}>"),
+    }
+}
+
+// Parses a sequence like `gg`, `<C-x>` or `<leader>d`, the leader only goes first.
+// A lone key with modifiers can skip the brackets, like `C-d`
+pub fn parse_keys(keys: &str) -> Result<Vec<Key>> {
+    if let Ok(event) = parse_key(keys) {
+        return Ok(vec![Key::Event(event)]);
+    }
+    let mut sequence = vec![];
+    let mut rest = keys;
+    while let Some(char) = rest.chars().next() {
+        let len = match rest.find('>') {
+            Some(end) if char == '<' => end + 1,
+            _ => char.len_utf8(),
+        };
+        let (key, tail) = rest.split_at(len);
+        sequence.push(match key {
+            _ if key.eq_ignore_ascii_case("<leader>") && sequence.is_empty() => Key::Leader,
+            key => Key::Event(parse_key(key).with_context(|| format!("In \"{keys}\""))?),
+        });
+        rest = tail;
+    }
+    if sequence.is_empty() {
+        bail!("Empty key sequence");
+    }
+    Ok(sequence)
+}
+
+pub fn keys_name(keys: &[Key]) -> String {
+    keys.iter()
+        .map(|key| match key {
+            Key::Leader => "<leader>".to_owned(),
+            Key::Event(event) => key_name(event),
+        })
+        .collect()
+}
+
+// Reads a key written in notation, or as a serialized event like older configs had
+struct KeyVisitor;
+
+impl<'de> Visitor<'de> for KeyVisitor {
+    type Value = Event;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a key like \"j\", \"C-d\" or \"<Space>\"")
+    }
+
+    fn visit_str<E: de::Error>(self, key: &str) -> Result<Event, E> {
+        parse_key(key).map_err(|err| E::custom(format!("{err:#}")))
+    }
+
+    fn visit_enum<A: EnumAccess<'de>>(self, data: A) -> Result<Event, A::Error> {
+        Event::deserialize(EnumAccessDeserializer::new(data)).map(normalize_event)
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Event, A::Error> {
+        Event::deserialize(MapAccessDeserializer::new(map)).map(normalize_event)
+    }
+}
+
+pub fn serialize_key<S: Serializer>(event: &Event, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&key_name(event))
+}
+
+pub fn deserialize_key<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Event, D::Error> {
+    deserializer.deserialize_any(KeyVisitor)
+}
+
+// One key of the old list of events per action
+#[derive(Deserialize)]
+pub struct LegacyKey(#[serde(deserialize_with = "deserialize_key")] pub Event);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Binding {
+    pub keys: Vec<Key>,
+    pub action: Action,
+}
+
+// The list of bindings that configs written before the key notation had
+#[derive(Deserialize)]
+struct LegacyBinding {
+    #[serde(default)]
+    leader: bool,
+    keys: Vec<LegacyKey>,
+    action: Action,
+}
+
+impl From<LegacyBinding> for Binding {
+    fn from(legacy: LegacyBinding) -> Self {
+        let leader = legacy.leader.then_some(Key::Leader);
+        Self {
+            keys: leader
+                .into_iter()
+                .chain(legacy.keys.into_iter().map(|key| Key::Event(key.0)))
+                .collect(),
+            action: legacy.action,
+        }
+    }
+}
+
+// Key sequences and the actions they trigger, written in the config as a map like `gg: top`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Keymap(pub Vec<Binding>);
+
+impl Keymap {
+    pub fn bind(&mut self, keys: Vec<Key>, action: Action) {
+        self.unbind(&keys);
+        self.0.push(Binding { keys, action });
+    }
+
+    pub fn unbind(&mut self, keys: &[Key]) -> bool {
+        let len = self.0.len();
+        self.0.retain(|binding| binding.keys != keys);
+        self.0.len() != len
+    }
+
+    // Key sequences bound to several actions, of which only the first that applies runs
+    pub fn conflicts(&self) -> Vec<(&[Key], Vec<Action>)> {
+        let mut conflicts: Vec<(&[Key], Vec<Action>)> = vec![];
+        for (idx, binding) in self.0.iter().enumerate() {
+            if self.0[..idx].iter().any(|other| other.keys == binding.keys) {
+                continue;
+            }
+            let actions: Vec<_> = self.0[idx..]
+                .iter()
+                .filter(|other| other.keys == binding.keys)
+                .map(|other| other.action)
+                .collect();
+            if actions.len() > 1 {
+                conflicts.push((&binding.keys, actions));
+            }
+        }
+        conflicts
+    }
+
+    // Bindings here that start a longer binding of `other`, so they only run once
+    // no further key follows within the timeout
+    pub fn prefixes<'a>(&'a self, other: &'a Keymap) -> Vec<(&'a [Key], &'a [Key])> {
+        self.0
+            .iter()
+            .flat_map(|short| {
+                other
+                    .0
+                    .iter()
+                    .filter(|long| {
+                        long.keys.len() > short.keys.len() && long.keys.starts_with(&short.keys)
+                    })
+                    .map(|long| (&short.keys[..], &long.keys[..]))
+            })
+            .collect()
+    }
+}
+
+// Built-in keymaps are written in notation, so a typo is a bug
+impl<const N: usize> From<[(&str, Action); N]> for Keymap {
+    fn from(bindings: [(&str, Action); N]) -> Self {
+        Self(
+            bindings
+                .into_iter()
+                .map(|(keys, action)| Binding {
+                    keys: parse_keys(keys).expect("Invalid built-in key"),
+                    action,
+                })
+                .collect(),
+        )
+    }
+}
+
+impl Serialize for Keymap {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.0.len()))?;
+        for binding in &self.0 {
+            map.serialize_entry(&keys_name(&binding.keys), &binding.action)?;
+        }
+        map.end()
+    }
+}
+
+struct KeymapVisitor;
+
+impl<'de> Visitor<'de> for KeymapVisitor {
+    type Value = Keymap;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a map of keys to actions, like `gg: top`")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Keymap, A::Error> {
+        let mut keymap = Keymap::default();
+        while let Some((keys, action)) = map.next_entry::<String, Action>()? {
+            let keys = parse_keys(&keys).map_err(|err| de::Error::custom(format!("{err:#}")))?;
+            keymap.0.push(Binding { keys, action });
+        }
+        Ok(keymap)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Keymap, A::Error> {
+        let mut keymap = Keymap::default();
+        while let Some(binding) = seq.next_element::<LegacyBinding>()? {
+            keymap.0.push(binding.into());
+        }
+        Ok(keymap)
+    }
+}
+
+impl<'de> Deserialize<'de> for Keymap {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Keymap, D::Error> {
+        deserializer.deserialize_any(KeymapVisitor)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    Normal,
+    Search,
+    Command,
+    Popup,
+}
+
+pub enum Step {
+    // Waiting for more keys of a sequence or count
+    Pending,
+    // A single key no binding starts with, which input modes insert as text
+    Unbound(Event),
+    // Actions bound to the sequence, to try in order, and the count typed before it
+    Run(Vec<Action>, Option<usize>),
+}
+
+// Keys typed so far towards a multi-key binding
+#[derive(Default)]
+pub struct KeyState {
+    pending: Vec<KeyEvent>,
+    count: Option<usize>,
+    last: Option<Instant>,
+}
+
+impl KeyState {
+    pub fn reset(&mut self) {
+        self.pending.clear();
+        self.count = None;
+        self.last = None;
+    }
+
+    // What has been typed so far, for display
+    pub fn pending(&self) -> String {
+        let count = self.count.map(|count| count.to_string());
+        let keys = self.pending.iter().map(|key| match key.code {
+            KeyCode::Char(c) => c.to_string(),
+            _ => "…".to_owned(),
+        });
+        count.into_iter().chain(keys).collect()
+    }
+
+    fn lookup(&self, maps: &[&Keymap], leader: &Event) -> (Vec<Action>, bool) {
+        let mut exact = vec![];
+        let mut longer = false;
+        for binding in maps.iter().flat_map(|map| map.0.iter()) {
+            let prefix = binding.keys.len() >= self.pending.len()
+                && binding
+                    .keys
+                    .iter()
+                    .zip(&self.pending)
+                    .all(|(key, event)| key.matches(event, leader));
+            if prefix && binding.keys.len() == self.pending.len() {
+                exact.push(binding.action);
+            } else if prefix {
+                longer = true;
+            }
+        }
+        (exact, longer)
+    }
+
+    // Actions of the one key bindings for `key`, which releases and repeats only run
+    fn single(maps: &[&Keymap], leader: &Event, key: &KeyEvent) -> Vec<Action> {
+        maps.iter()
+            .flat_map(|map| map.0.iter())
+            .filter(|binding| matches!(&binding.keys[..], [bound] if bound.matches(key, leader)))
+            .map(|binding| binding.action)
+            .collect()
+    }
+
+    fn finish(&mut self, actions: Vec<Action>) -> Step {
+        let count = self.count;
+        self.reset();
+        Step::Run(actions, count)
+    }
+
+    // Feeds one key in, `maps` are tried in order. Normal mode takes counts, and
+    // runs only actions meant to be held on key repeat where prompts type again
+    pub fn feed(
+        &mut self,
+        event: &Event,
+        maps: &[&Keymap],
+        leader: &Event,
+        normal: bool,
+        now: Instant,
+    ) -> Step {
+        let Event::Key(key) = event else {
+            return Step::Pending;
+        };
+        // Shift or Ctrl on their own, reported with keyboard enhancement
+        if matches!(key.code, KeyCode::Modifier(_)) {
+            return Step::Pending;
+        }
+        let mut key = normalize(*key);
+        let idle = self.pending.is_empty() && self.count.is_none();
+        match key.kind {
+            // Releases only run bindings that ask for them, like `<Release-Space>`
+            KeyEventKind::Release if idle => {
+                let actions = Self::single(maps, leader, &key);
+                if actions.is_empty() {
+                    return Step::Pending;
+                }
+                return Step::Run(actions, None);
+            }
+            KeyEventKind::Release => return Step::Pending,
+            KeyEventKind::Repeat if normal => {
+                let actions: Vec<_> = Self::single(maps, leader, &key)
+                    .into_iter()
+                    .filter(|action| action.held())
+                    .collect();
+                if !idle || actions.is_empty() {
+                    return Step::Pending;
+                }
+                return Step::Run(actions, None);
+            }
+            KeyEventKind::Repeat => key.kind = KeyEventKind::Press,
+            KeyEventKind::Press => {}
+        }
+
+        // Esc abandons a half typed sequence or count
+        if key.code == KeyCode::Esc && !idle {
+            self.reset();
+            return Step::Pending;
+        }
+        if let KeyCode::Char(digit @ '0'..='9') = key.code {
+            let starts_binding = self.pending.is_empty()
+                && maps.iter().flat_map(|map| map.0.iter()).any(|binding| {
+                    binding
+                        .keys
+                        .first()
+                        .is_some_and(|bound| bound.matches(&key, leader))
+                });
+            let leading_zero = digit == '0' && self.count.is_none();
+            if normal
+                && self.pending.is_empty()
+                && key.modifiers == KeyModifiers::NONE
+                && !starts_binding
+                && !leading_zero
+            {
+                let digit = digit.to_digit(10).unwrap_or_default() as usize;
+                self.count = Some(self.count.unwrap_or_default().saturating_mul(10) + digit);
+                self.last = Some(now);
+                return Step::Pending;
+            }
+        }
+
+        self.pending.push(key);
+        self.last = Some(now);
+        match self.lookup(maps, leader) {
+            (exact, false) if !exact.is_empty() => self.finish(exact),
+            (_, true) => Step::Pending,
+            _ if self.pending.len() > 1 => {
+                // The sequence broke off, so start over from the last key
+                self.reset();
+                self.feed(&Event::Key(key), maps, leader, normal, now)
+            }
+            _ => {
+                self.reset();
+                Step::Unbound(event.clone())
+            }
+        }
+    }
+
+    // Resolves a sequence that is both a binding and the prefix of a longer one
+    // once no key has followed it within `timeout`
+    pub fn tick(
+        &mut self,
+        maps: &[&Keymap],
+        leader: &Event,
+        timeout: Duration,
+        now: Instant,
+    ) -> Option<Step> {
+        let last = self.last?;
+        if now.duration_since(last) < timeout {
+            return None;
+        }
+        let (exact, _) = self.lookup(maps, leader);
+        if exact.is_empty() || self.pending.is_empty() {
+            self.reset();
+            return None;
+        }
+        Some(self.finish(exact))
+    }
+}
@@ -0,0 +1,87 @@
+use std::time::Duration;
+
+use mpd::Song;
+
+use super::{
+    list::{album, draw_songs, ListState},
+    mpd::{Backend, Mpd, MpdResult},
+    ui::{Rect, Render},
+};
+
+#[derive(Default)]
+pub struct Library {
+    pub songs: Vec<Song>,
+    pub list: ListState,
+    loaded: bool,
+    // Time of the database update the songs were loaded after
+    db_update: Option<Duration>,
+}
+
+impl Library {
+    // Loads the database once a connection is available, and again after it changed
+    pub fn sync<B: Backend>(&mut self, mpd: &mut Mpd<B>) {
+        let db_update = mpd.get_stats().map(|stats| stats.db_update);
+        if db_update.is_some() && db_update != self.db_update {
+            self.db_update = db_update;
+            self.loaded = false;
+        }
+        if !mpd.is_connected() {
+            self.loaded = false;
+        } else if !self.loaded {
+            let mut songs = mpd.get_library();
+            songs.sort_by(|a, b| a.file.cmp(&b.file));
+            // Marks stay with their files when the database changed
+            self.list.remap(&files(&self.songs), &files(&songs));
+            self.songs = songs;
+            self.loaded = true;
+        }
+    }
+
+    pub fn reload(&mut self) {
+        self.loaded = false;
+    }
+
+    pub fn selected(&self) -> Option<&Song> {
+        self.songs.get(self.list.cursor)
+    }
+
+    pub fn cursor_up(&mut self) {
+        self.list.up();
+    }
+
+    pub fn cursor_down(&mut self) {
+        self.list.down(self.songs.len());
+    }
+
+    pub fn select_album(&mut self) {
+        let Some(current) = self.selected().and_then(album).map(str::to_owned) else {
+            return;
+        };
+        let songs = &self.songs;
+        self.list.select_where(songs.len(), |idx| {
+            album(&songs[idx]) == Some(current.as_str())
+        });
+    }
+
+    pub fn selected_files(&self) -> Vec<String> {
+        self.list
+            .targets(self.songs.len())
+            .into_iter()
+            .map(|idx| self.songs[idx].file.clone())
+            .collect()
+    }
+
+    pub fn add_selected<B: Backend>(&mut self, mpd: &mut Mpd<B>) -> MpdResult<()> {
+        mpd.add_files(self.selected_files())?;
+        self.list.clear_selection();
+        Ok(())
+    }
+
+    pub fn draw<R: Render>(&mut self, ct: &mut R, rect: Rect) {
+        draw_songs(ct, rect, &self.songs, &mut self.list, None);
+    }
+}
+
+fn files(songs: &[Song]) -> Vec<&str> {
+    songs.iter().map(|song| song.file.as_str()).collect()
+}
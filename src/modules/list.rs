@@ -0,0 +1,217 @@
+use std::collections::BTreeSet;
+
+use crossterm::style::{Attribute, Color};
+use mpd::Song;
+
+use super::ui::{Overflow, Rect, Render};
+
+// Cursor, scroll position and multi-selection of a list view
+#[derive(Default)]
+pub struct ListState {
+    pub cursor: usize,
+    offset: usize,
+    marked: BTreeSet<usize>,
+    // Anchor of the vim-like visual range, which spans to the cursor
+    visual: Option<usize>,
+}
+
+impl ListState {
+    // Keeps the cursor and marks inside a list of `len` items
+    pub fn clamp(&mut self, len: usize) {
+        self.cursor = self.cursor.min(len.saturating_sub(1));
+        self.marked.retain(|idx| *idx < len);
+        if self.visual.is_some_and(|anchor| anchor >= len) {
+            self.visual = None;
+        }
+    }
+
+    // Moves the marks along with their items when the list is fetched again, with
+    // `old` and `new` naming each item by what stays the same, like a song id
+    pub fn remap<K: Ord>(&mut self, old: &[K], new: &[K]) {
+        let marked = self
+            .marked
+            .iter()
+            .filter_map(|idx| old.get(*idx))
+            .collect::<BTreeSet<_>>();
+        self.marked = (0..new.len())
+            .filter(|idx| marked.contains(&new[*idx]))
+            .collect();
+        self.visual = self
+            .visual
+            .and_then(|anchor| old.get(anchor))
+            .and_then(|key| new.iter().position(|other| other == key));
+        self.clamp(new.len());
+    }
+
+    pub fn up(&mut self) {
+        self.cursor = self.cursor.saturating_sub(1);
+    }
+
+    pub fn down(&mut self, len: usize) {
+        if self.cursor + 1 < len {
+            self.cursor += 1;
+        }
+    }
+
+    pub fn jump(&mut self, idx: usize, len: usize) {
+        self.cursor = idx.min(len.saturating_sub(1));
+    }
+
+    pub fn toggle_mark(&mut self, len: usize) {
+        if self.cursor < len && !self.marked.remove(&self.cursor) {
+            self.marked.insert(self.cursor);
+        }
+    }
+
+    // Leaving visual mode keeps the range as marked items
+    pub fn toggle_visual(&mut self) {
+        match self.visual.take() {
+            Some(anchor) => self
+                .marked
+                .extend(anchor.min(self.cursor)..=anchor.max(self.cursor)),
+            None => self.visual = Some(self.cursor),
+        }
+    }
+
+    pub fn is_visual(&self) -> bool {
+        self.visual.is_some()
+    }
+
+    pub fn select_all(&mut self, len: usize) {
+        self.visual = None;
+        self.marked = (0..len).collect();
+    }
+
+    pub fn invert_selection(&mut self, len: usize) {
+        let selected = self.selection();
+        self.visual = None;
+        self.marked = (0..len).filter(|idx| !selected.contains(idx)).collect();
+    }
+
+    pub fn select_where(&mut self, len: usize, predicate: impl Fn(usize) -> bool) {
+        self.marked.extend((0..len).filter(|idx| predicate(*idx)));
+    }
+
+    pub fn clear_selection(&mut self) {
+        self.visual = None;
+        self.marked.clear();
+    }
+
+    // Replaces the selection, e.g. after the selected items were moved
+    pub fn set_selection(&mut self, marked: impl IntoIterator<Item = usize>) {
+        self.visual = None;
+        self.marked = marked.into_iter().collect();
+    }
+
+    // Marked items plus the visual range, without falling back to the cursor
+    pub fn selection(&self) -> BTreeSet<usize> {
+        let mut selection = self.marked.clone();
+        if let Some(anchor) = self.visual {
+            selection.extend(anchor.min(self.cursor)..=anchor.max(self.cursor));
+        }
+        selection
+    }
+
+    pub fn is_selected(&self, idx: usize) -> bool {
+        self.marked.contains(&idx)
+            || self.visual.is_some_and(|anchor| {
+                (anchor.min(self.cursor)..=anchor.max(self.cursor)).contains(&idx)
+            })
+    }
+
+    // Indices an action applies to: the selection, or the cursor if nothing is selected
+    pub fn targets(&self, len: usize) -> Vec<usize> {
+        let selection = self.selection();
+        if selection.is_empty() {
+            (self.cursor < len)
+                .then_some(self.cursor)
+                .into_iter()
+                .collect()
+        } else {
+            selection.into_iter().filter(|idx| *idx < len).collect()
+        }
+    }
+
+    // Scrolls so the cursor is visible and returns the first visible index
+    pub fn scroll(&mut self, height: usize) -> usize {
+        if self.cursor < self.offset {
+            self.offset = self.cursor;
+        } else if height > 0 && self.cursor >= self.offset + height {
+            self.offset = self.cursor + 1 - height;
+        }
+        self.offset
+    }
+}
+
+pub fn draw_songs<R: Render>(
+    ct: &mut R,
+    rect: Rect,
+    songs: &[Song],
+    list: &mut ListState,
+    playing: Option<usize>,
+) {
+    let height = rect.height as usize;
+    if height == 0 {
+        return;
+    }
+    let offset = list.scroll(height);
+
+    ct.set_background(rect, Color::Reset);
+    ct.set_foreground(rect, Color::Reset);
+    ct.set_attributes(rect, Attribute::NormalIntensity);
+    ct.set_text(rect, "", Overflow::Char);
+
+    for (row, (pos, song)) in songs
+        .iter()
+        .enumerate()
+        .skip(offset)
+        .take(height)
+        .enumerate()
+    {
+        let line = Rect {
+            y: rect.y + row as u32,
+            height: 1,
+            ..rect
+        };
+        if list.is_selected(pos) {
+            ct.set_foreground(line, Color::Yellow);
+        }
+        if pos == list.cursor {
+            ct.set_background(line, Color::DarkGrey);
+        }
+        if Some(pos) == playing {
+            ct.set_attributes(line, Attribute::Bold);
+        }
+
+        let title = song.title.as_deref().unwrap_or(&song.file);
+        let artist = song.artist.as_deref().unwrap_or_default();
+        ct.set_text(
+            Rect {
+                x: line.x + 1,
+                width: line.width.saturating_sub(2),
+                ..line
+            },
+            format!("{title} - {artist}").as_str(),
+            Overflow::Char,
+        );
+        if let Some(duration) = song.duration {
+            let dur = format!("{}:{:02}", duration.as_secs() / 60, duration.as_secs() % 60);
+            ct.set_text(
+                Rect {
+                    x: (line.x + line.width).saturating_sub(dur.len() as u32 + 1),
+                    width: dur.len() as u32,
+                    ..line
+                },
+                dur.as_str(),
+                Overflow::Char,
+            );
+        }
+    }
+}
+
+pub fn album(song: &Song) -> Option<&str> {
+    song.tags
+        .iter()
+        .find(|(tag, _)| tag == "Album")
+        .map(|(_, value)| value.as_str())
+}
@@ -0,0 +1,362 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+    sync::{
+        mpsc::{self, Receiver},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+use anyhow::{bail, Result};
+use crossterm::style::{Attribute, Color};
+use log::warn;
+use mpd::Song;
+use unicode_width::UnicodeWidthStr;
+
+use super::{
+    list::{album, ListState},
+    ui::{Overflow, Rect, Render},
+};
+
+pub struct Line {
+    pub time: Option<Duration>,
+    pub text: String,
+}
+
+// Lyrics of one song, with a time per line when they came from an .lrc file
+#[derive(Default)]
+pub struct Lyrics {
+    pub lines: Vec<Line>,
+}
+
+// Reads `[mm:ss.xx]` stamps off the front of an LRC line, a line may have several
+fn timestamps(mut line: &str) -> (Vec<Duration>, &str) {
+    let mut times = vec![];
+    while let Some((stamp, rest)) = line.strip_prefix('[').and_then(|line| line.split_once(']')) {
+        let Some((mins, secs)) = stamp.split_once(':') else {
+            break;
+        };
+        // Negative, infinite and nan seconds parse as floats but are no time
+        let (Ok(mins), Ok(Ok(secs))) = (
+            mins.parse::<u64>(),
+            secs.parse().map(Duration::try_from_secs_f64),
+        ) else {
+            break;
+        };
+        times.push(Duration::from_secs(mins * 60) + secs);
+        line = rest;
+    }
+    (times, line)
+}
+
+impl Lyrics {
+    // Parses LRC text, plain text without stamps is kept as unsynced lines
+    pub fn parse(text: &str) -> Self {
+        let mut offset = 0;
+        let mut timed = vec![];
+        let mut plain = vec![];
+        for line in text.lines() {
+            let line = line.trim();
+            let (times, rest) = timestamps(line);
+            if times.is_empty() {
+                // Tags like `[ar:Artist]`, of which only the offset matters
+                if let Some(tag) = line.strip_prefix('[').and_then(|tag| tag.strip_suffix(']')) {
+                    if let Some(ms) = tag.strip_prefix("offset:") {
+                        offset = ms.trim().parse::<i64>().unwrap_or_default();
+                    }
+                    if tag.contains(':') {
+                        continue;
+                    }
+                }
+                plain.push(line.to_owned());
+            }
+            for time in times {
+                timed.push((time, rest.trim().to_owned()));
+            }
+        }
+        if timed.is_empty() {
+            return Self {
+                lines: plain
+                    .into_iter()
+                    .map(|text| Line { time: None, text })
+                    .collect(),
+            };
+        }
+        // A positive offset shows lines earlier
+        let shift = Duration::from_millis(offset.unsigned_abs());
+        timed.sort_by_key(|(time, _)| *time);
+        Self {
+            lines: timed
+                .into_iter()
+                .map(|(time, text)| Line {
+                    time: Some(if offset > 0 {
+                        time.saturating_sub(shift)
+                    } else {
+                        time + shift
+                    }),
+                    text,
+                })
+                .collect(),
+        }
+    }
+
+    pub fn is_synced(&self) -> bool {
+        self.lines.iter().any(|line| line.time.is_some())
+    }
+
+    // The last line that started at or before `elapsed`
+    pub fn current(&self, elapsed: Duration) -> Option<usize> {
+        self.lines
+            .iter()
+            .rposition(|line| line.time.is_some_and(|time| time <= elapsed))
+    }
+}
+
+// Where lyrics are cached by artist and title, as `Artist - Title.lrc`
+fn cache_name(song: &Song) -> Option<String> {
+    let title = song.title.as_deref()?;
+    let name = match song.artist.as_deref() {
+        Some(artist) => format!("{artist} - {title}"),
+        None => title.to_owned(),
+    };
+    Some(name.replace(['/', '\\'], "_"))
+}
+
+// Files that may hold lyrics for `song`, best first
+pub fn candidates(song: &Song, music_dir: Option<&Path>, cache_dir: Option<&Path>) -> Vec<PathBuf> {
+    let mut paths = vec![];
+    if let Some(dir) = music_dir {
+        let track = dir.join(&song.file);
+        paths.push(track.with_extension("lrc"));
+        paths.push(track.with_extension("txt"));
+    }
+    if let (Some(dir), Some(name)) = (cache_dir, cache_name(song)) {
+        paths.push(dir.join(format!("{name}.lrc")));
+        paths.push(dir.join(format!("{name}.txt")));
+    }
+    paths
+}
+
+// A source of lyrics that are not on disk yet, run off the UI thread
+pub trait Provider: Send + Sync {
+    fn fetch(&self, song: &Song) -> Result<Option<String>>;
+}
+
+// Runs a command that prints the lyrics, with `{artist}`, `{title}`, `{album}`
+// and `{file}` in its arguments filled in. Printing nothing means none were found
+pub struct CommandProvider(pub Vec<String>);
+
+impl Provider for CommandProvider {
+    fn fetch(&self, song: &Song) -> Result<Option<String>> {
+        let Some((program, args)) = self.0.split_first() else {
+            bail!("Empty lyrics command");
+        };
+        let fill = |arg: &String| {
+            arg.replace("{artist}", song.artist.as_deref().unwrap_or_default())
+                .replace("{title}", song.title.as_deref().unwrap_or_default())
+                .replace("{album}", album(song).unwrap_or_default())
+                .replace("{file}", &song.file)
+        };
+        let output = Command::new(program).args(args.iter().map(fill)).output()?;
+        if !output.status.success() {
+            bail!("{program} exited with {}", output.status);
+        }
+        let text = String::from_utf8_lossy(&output.stdout).trim().to_owned();
+        Ok((!text.is_empty()).then_some(text))
+    }
+}
+
+enum State {
+    Empty,
+    Fetching(Receiver<Result<Option<String>>>),
+    Missing,
+    Found(Lyrics),
+}
+
+// The lyrics view, following whatever song is playing
+pub struct LyricsPanel {
+    pub music_dir: Option<PathBuf>,
+    pub cache_dir: Option<PathBuf>,
+    pub provider: Option<Arc<dyn Provider>>,
+    song: Option<Song>,
+    state: State,
+    pub list: ListState,
+    // Whether the cursor keeps to the line being sung
+    pub follow: bool,
+}
+
+impl LyricsPanel {
+    pub fn new(
+        music_dir: Option<PathBuf>,
+        cache_dir: Option<PathBuf>,
+        provider: Option<Arc<dyn Provider>>,
+    ) -> Self {
+        Self {
+            music_dir,
+            cache_dir,
+            provider,
+            song: None,
+            state: State::Empty,
+            list: ListState::default(),
+            follow: true,
+        }
+    }
+
+    pub fn lyrics(&self) -> Option<&Lyrics> {
+        match &self.state {
+            State::Found(lyrics) => Some(lyrics),
+            _ => None,
+        }
+    }
+
+    pub fn is_fetching(&self) -> bool {
+        matches!(self.state, State::Fetching(_))
+    }
+
+    fn len(&self) -> usize {
+        self.lyrics().map_or(0, |lyrics| lyrics.lines.len())
+    }
+
+    // Loads lyrics when the song changes, and moves along with `elapsed`
+    pub fn sync(&mut self, song: Option<&Song>, elapsed: Option<Duration>) {
+        if song.map(|song| &song.file) != self.song.as_ref().map(|song| &song.file) {
+            self.song = song.cloned();
+            self.list = ListState::default();
+            self.follow = true;
+            self.state = match song {
+                Some(song) => self.load(song),
+                None => State::Empty,
+            };
+        }
+        if let State::Fetching(result) = &self.state {
+            match result.try_recv() {
+                Ok(Ok(Some(text))) => {
+                    self.store(&text);
+                    self.state = State::Found(Lyrics::parse(&text));
+                }
+                Ok(Ok(None)) => self.state = State::Missing,
+                Ok(Err(err)) => {
+                    warn!("Failed to fetch lyrics: {err:#}");
+                    self.state = State::Missing;
+                }
+                Err(mpsc::TryRecvError::Empty) => {}
+                Err(mpsc::TryRecvError::Disconnected) => self.state = State::Missing,
+            }
+        }
+        if let (true, Some(lyrics), Some(elapsed)) = (self.follow, self.lyrics(), elapsed) {
+            let len = lyrics.lines.len();
+            if let Some(current) = lyrics.current(elapsed) {
+                self.list.jump(current, len);
+            }
+        }
+    }
+
+    fn load(&self, song: &Song) -> State {
+        let paths = candidates(song, self.music_dir.as_deref(), self.cache_dir.as_deref());
+        if let Some(text) = paths.iter().find_map(|path| fs::read_to_string(path).ok()) {
+            return State::Found(Lyrics::parse(&text));
+        }
+        let Some(provider) = self.provider.clone() else {
+            return State::Missing;
+        };
+        let (send, receive) = mpsc::channel();
+        let song = song.clone();
+        thread::spawn(move || send.send(provider.fetch(&song)));
+        State::Fetching(receive)
+    }
+
+    // Keeps fetched lyrics in the cache so they are only fetched once
+    fn store(&self, text: &str) {
+        let (Some(dir), Some(name)) = (&self.cache_dir, self.song.as_ref().and_then(cache_name))
+        else {
+            return;
+        };
+        let extension = if Lyrics::parse(text).is_synced() {
+            "lrc"
+        } else {
+            "txt"
+        };
+        let stored = fs::create_dir_all(dir)
+            .and_then(|_| fs::write(dir.join(format!("{name}.{extension}")), text));
+        if let Err(err) = stored {
+            warn!("Failed to cache lyrics: {err}");
+        }
+    }
+
+    pub fn cursor_up(&mut self) {
+        self.follow = false;
+        self.list.up();
+    }
+
+    pub fn cursor_down(&mut self) {
+        self.follow = false;
+        let len = self.len();
+        self.list.down(len);
+    }
+
+    // Time of the line under the cursor, to seek to
+    pub fn selected_time(&self) -> Option<Duration> {
+        self.lyrics()?.lines.get(self.list.cursor)?.time
+    }
+
+    pub fn active_list(&mut self) -> (&mut ListState, usize) {
+        let len = self.len();
+        self.follow = false;
+        (&mut self.list, len)
+    }
+
+    pub fn draw<R: Render>(&mut self, ct: &mut R, rect: Rect) {
+        ct.set_background(rect, Color::Reset);
+        ct.set_foreground(rect, Color::Reset);
+        ct.set_attributes(rect, Attribute::NormalIntensity);
+        ct.set_text(rect, "", Overflow::Char);
+
+        let message = match (&self.song, &self.state) {
+            (None, _) => Some("Nothing is playing"),
+            (_, State::Fetching(_)) => Some("Fetching lyrics…"),
+            (_, State::Missing | State::Empty) => Some("No lyrics found"),
+            (_, State::Found(_)) => None,
+        };
+        if let Some(message) = message {
+            let line = rect.centered(message.width() as u32, 1);
+            ct.set_foreground(line, Color::DarkGrey);
+            ct.set_text(line, message, Overflow::Char);
+            return;
+        }
+        let height = rect.height as usize;
+        // Keeps the current line in the middle while following
+        let offset = if self.follow {
+            self.list.cursor.saturating_sub(height / 2)
+        } else {
+            self.list.scroll(height)
+        };
+        let State::Found(lyrics) = &self.state else {
+            return;
+        };
+        let synced = lyrics.is_synced();
+        for (row, (pos, line)) in lyrics
+            .lines
+            .iter()
+            .enumerate()
+            .skip(offset)
+            .take(height)
+            .enumerate()
+        {
+            let width = (line.text.width() as u32).min(rect.width);
+            let line_rect = Rect {
+                x: rect.x + (rect.width - width) / 2,
+                y: rect.y + row as u32,
+                width,
+                height: 1,
+            };
+            if pos == self.list.cursor && (synced || !self.follow) {
+                ct.set_foreground(line_rect, Color::Yellow);
+                ct.set_attributes(line_rect, Attribute::Bold);
+            }
+            ct.set_text(line_rect, line.text.as_str(), Overflow::Char);
+        }
+    }
+}
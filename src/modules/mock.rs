@@ -0,0 +1,761 @@
+use std::{
+    collections::BTreeMap,
+    io::{self, BufRead, BufReader, Write},
+    net::{Shutdown, SocketAddr, TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, MutexGuard,
+    },
+    thread,
+    time::Duration,
+};
+
+use mpd::{song::QueuePlace, Id, Song, State};
+
+use super::mpd::OneshotMode;
+
+const ACK_ERROR_ARG: u8 = 2;
+const ACK_ERROR_UNKNOWN: u8 = 5;
+const ACK_ERROR_NO_EXIST: u8 = 50;
+const ACK_ERROR_EXIST: u8 = 56;
+
+type Reply = Result<String, (u8, String)>;
+
+// In-memory model of everything the fake daemon knows about
+pub struct MockState {
+    pub library: Vec<Song>,
+    pub queue: Vec<Song>,
+    pub state: State,
+    pub current: Option<usize>,
+    pub elapsed: Duration,
+    pub volume: i8,
+    pub repeat: bool,
+    pub random: bool,
+    pub single: OneshotMode,
+    pub consume: OneshotMode,
+    // Seconds
+    pub crossfade: u64,
+    pub queue_version: u32,
+    // Seconds since the epoch of the last database update
+    pub db_update: u64,
+    pub playlists: BTreeMap<String, Vec<String>>,
+    // Covers by song file, embedded ones for `readpicture` and folder ones for `albumart`
+    pub pictures: BTreeMap<String, Vec<u8>>,
+    pub covers: BTreeMap<String, Vec<u8>>,
+    // Every command line received, in order, for asserting on round trips
+    pub commands: Vec<String>,
+    next_id: u32,
+}
+
+impl Default for MockState {
+    fn default() -> Self {
+        Self {
+            library: vec![],
+            queue: vec![],
+            state: State::Stop,
+            current: None,
+            elapsed: Duration::ZERO,
+            volume: 50,
+            repeat: false,
+            random: false,
+            single: OneshotMode::Off,
+            consume: OneshotMode::Off,
+            crossfade: 0,
+            queue_version: 1,
+            db_update: 0,
+            playlists: BTreeMap::new(),
+            pictures: BTreeMap::new(),
+            covers: BTreeMap::new(),
+            commands: vec![],
+            next_id: 1,
+        }
+    }
+}
+
+impl MockState {
+    pub fn current_song(&self) -> Option<&Song> {
+        self.current.and_then(|pos| self.queue.get(pos))
+    }
+
+    pub fn queue_files(&self) -> Vec<&str> {
+        self.queue.iter().map(|song| song.file.as_str()).collect()
+    }
+
+    fn renumber(&mut self) {
+        for (pos, song) in self.queue.iter_mut().enumerate() {
+            if let Some(place) = &mut song.place {
+                place.pos = pos as u32;
+            }
+        }
+        self.queue_version += 1;
+    }
+
+    fn find_file(&self, uri: &str) -> Result<Song, (u8, String)> {
+        if uri.contains("://") {
+            return Ok(Song {
+                file: uri.to_owned(),
+                ..Default::default()
+            });
+        }
+        self.library
+            .iter()
+            .find(|song| song.file == uri)
+            .cloned()
+            .ok_or((ACK_ERROR_NO_EXIST, "No such directory".to_owned()))
+    }
+
+    fn position_of(&self, id: u32) -> Result<usize, (u8, String)> {
+        self.queue
+            .iter()
+            .position(|song| song.place.map(|place| place.id) == Some(Id(id)))
+            .ok_or((ACK_ERROR_NO_EXIST, "No such song".to_owned()))
+    }
+
+    fn insert(&mut self, mut song: Song, pos: Option<usize>) -> u32 {
+        let id = self.next_id;
+        self.next_id += 1;
+        song.place = Some(QueuePlace {
+            id: Id(id),
+            pos: 0,
+            prio: 0,
+        });
+        let pos = pos.unwrap_or(self.queue.len()).min(self.queue.len());
+        if let Some(current) = &mut self.current {
+            if pos <= *current {
+                *current += 1;
+            }
+        }
+        self.queue.insert(pos, song);
+        self.renumber();
+        id
+    }
+
+    fn remove(&mut self, range: std::ops::Range<usize>) {
+        self.queue.drain(range.clone());
+        self.current = match self.current {
+            Some(current) if range.contains(&current) => {
+                self.state = State::Stop;
+                None
+            }
+            Some(current) if current >= range.end => Some(current - range.len()),
+            current => current,
+        };
+        self.renumber();
+    }
+
+    fn playlist(&mut self, name: Option<&String>) -> Result<&mut Vec<String>, (u8, String)> {
+        name.and_then(|name| self.playlists.get_mut(name))
+            .ok_or((ACK_ERROR_NO_EXIST, "No such playlist".to_owned()))
+    }
+
+    fn play_at(&mut self, pos: usize) -> Reply {
+        if pos >= self.queue.len() {
+            return Err((ACK_ERROR_ARG, "Bad song index".to_owned()));
+        }
+        self.current = Some(pos);
+        self.elapsed = Duration::ZERO;
+        self.state = State::Play;
+        Ok(String::new())
+    }
+}
+
+fn write_song(out: &mut String, song: &Song) {
+    out.push_str(&format!("file: {}\n", song.file));
+    if let Some(last_mod) = &song.last_mod {
+        out.push_str(&format!("Last-Modified: {last_mod}\n"));
+    }
+    if let Some(artist) = &song.artist {
+        out.push_str(&format!("Artist: {artist}\n"));
+    }
+    if let Some(title) = &song.title {
+        out.push_str(&format!("Title: {title}\n"));
+    }
+    if let Some(name) = &song.name {
+        out.push_str(&format!("Name: {name}\n"));
+    }
+    for (tag, value) in &song.tags {
+        out.push_str(&format!("{tag}: {value}\n"));
+    }
+    if let Some(duration) = song.duration {
+        out.push_str(&format!("Time: {}\n", duration.as_secs()));
+    }
+    if let Some(place) = song.place {
+        out.push_str(&format!(
+            "Pos: {}\nId: {}\nPrio: {}\n",
+            place.pos, place.id.0, place.prio
+        ));
+    }
+}
+
+// Splits a command line into words, honouring MPD's double quoting
+fn tokenize(line: &str) -> Vec<String> {
+    let mut args = vec![];
+    let mut chars = line.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+            continue;
+        }
+        let mut arg = String::new();
+        if c == '"' {
+            chars.next();
+            while let Some(c) = chars.next() {
+                match c {
+                    '\\' => arg.extend(chars.next()),
+                    '"' => break,
+                    c => arg.push(c),
+                }
+            }
+        } else {
+            while let Some(&c) = chars.peek() {
+                if c.is_whitespace() {
+                    break;
+                }
+                arg.push(c);
+                chars.next();
+            }
+        }
+        args.push(arg);
+    }
+    args
+}
+
+fn parse_num<T: std::str::FromStr>(arg: Option<&String>) -> Result<T, (u8, String)> {
+    arg.and_then(|arg| arg.parse().ok())
+        .ok_or((ACK_ERROR_ARG, "need an integer".to_owned()))
+}
+
+fn parse_bool(arg: Option<&String>) -> Result<bool, (u8, String)> {
+    match arg.map(String::as_str) {
+        Some("1") => Ok(true),
+        Some("0") => Ok(false),
+        _ => Err((ACK_ERROR_ARG, "Boolean (0/1) expected".to_owned())),
+    }
+}
+
+fn parse_mode(arg: Option<&String>) -> Result<OneshotMode, (u8, String)> {
+    arg.and_then(|arg| OneshotMode::parse(arg)).ok_or((
+        ACK_ERROR_ARG,
+        "Boolean (0/1) or \"oneshot\" expected".to_owned(),
+    ))
+}
+
+// Parses either `POS` or `START:END` into a half-open range
+fn parse_range(arg: Option<&String>, len: usize) -> Result<std::ops::Range<usize>, (u8, String)> {
+    let bad = || (ACK_ERROR_ARG, "Bad song index".to_owned());
+    let arg = arg.ok_or_else(bad)?;
+    let range = match arg.split_once(':') {
+        Some((start, "")) => start.parse().map_err(|_| bad())?..len,
+        Some((start, end)) => start.parse().map_err(|_| bad())?..end.parse().map_err(|_| bad())?,
+        None => {
+            let pos: usize = arg.parse().map_err(|_| bad())?;
+            pos..pos + 1
+        }
+    };
+    if range.start > range.end || range.end > len {
+        return Err(bad());
+    }
+    Ok(range)
+}
+
+fn handle(state: &mut MockState, args: &[String]) -> Reply {
+    let mut out = String::new();
+    let Some(command) = args.first() else {
+        return Err((ACK_ERROR_UNKNOWN, "No command given".to_owned()));
+    };
+    match command.as_str() {
+        "ping" | "password" => {}
+        // Tags are not read from anywhere, so an update only marks the database as changed
+        "update" => {
+            state.db_update += 1;
+            out.push_str(&format!("updating_db: {}\n", state.db_update));
+        }
+        "replay_gain_status" => out.push_str("replay_gain_mode: off\n"),
+        "status" => {
+            out.push_str(&format!(
+                "volume: {}\nrepeat: {}\nrandom: {}\nsingle: {}\nconsume: {}\nplaylist: {}\nplaylistlength: {}\n",
+                state.volume,
+                state.repeat as u8,
+                state.random as u8,
+                state.single.arg(),
+                state.consume.arg(),
+                state.queue_version,
+                state.queue.len(),
+            ));
+            out.push_str(match state.state {
+                State::Play => "state: play\n",
+                State::Pause => "state: pause\n",
+                State::Stop => "state: stop\n",
+            });
+            if state.crossfade > 0 {
+                out.push_str(&format!("xfade: {}\n", state.crossfade));
+            }
+            if let Some(song) = state.current_song() {
+                let place = song.place.unwrap_or_default();
+                let total = song.duration.unwrap_or_default();
+                out.push_str(&format!(
+                    "song: {}\nsongid: {}\ntime: {}:{}\nelapsed: {:.3}\nduration: {:.3}\n",
+                    place.pos,
+                    place.id.0,
+                    state.elapsed.as_secs(),
+                    total.as_secs(),
+                    state.elapsed.as_secs_f32(),
+                    total.as_secs_f32(),
+                ));
+                if state.state != State::Stop {
+                    out.push_str("bitrate: 320\naudio: 44100:16:2\n");
+                }
+            }
+        }
+        "stats" => {
+            let playtime: u64 = state
+                .library
+                .iter()
+                .filter_map(|song| song.duration)
+                .map(|duration| duration.as_secs())
+                .sum();
+            out.push_str(&format!(
+                "artists: 0\nalbums: 0\nsongs: {}\nuptime: 0\nplaytime: 0\ndb_playtime: {playtime}\ndb_update: {}\n",
+                state.library.len(),
+                state.db_update,
+            ));
+        }
+        "currentsong" => {
+            if let Some(song) = state.current_song() {
+                write_song(&mut out, song);
+            }
+        }
+        "playlistinfo" => {
+            let range = match args.get(1) {
+                Some(_) => parse_range(args.get(1), state.queue.len())?,
+                None => 0..state.queue.len(),
+            };
+            for song in &state.queue[range] {
+                write_song(&mut out, song);
+            }
+        }
+        "playlistid" => {
+            let pos = state.position_of(parse_num(args.get(1))?)?;
+            write_song(&mut out, &state.queue[pos]);
+        }
+        "listall" => {
+            for song in &state.library {
+                out.push_str(&format!("file: {}\n", song.file));
+            }
+        }
+        "listallinfo" => {
+            for song in &state.library {
+                write_song(&mut out, song);
+            }
+        }
+        "play" => match args.get(1) {
+            Some(_) => return state.play_at(parse_num(args.get(1))?),
+            None if state.state == State::Pause => state.state = State::Play,
+            None => return state.play_at(state.current.unwrap_or(0)),
+        },
+        "playid" => {
+            let pos = state.position_of(parse_num(args.get(1))?)?;
+            return state.play_at(pos);
+        }
+        "pause" => {
+            let pause = match args.get(1) {
+                Some(_) => parse_bool(args.get(1))?,
+                None => state.state == State::Play,
+            };
+            if state.state != State::Stop {
+                state.state = if pause { State::Pause } else { State::Play };
+            }
+        }
+        "stop" => {
+            state.state = State::Stop;
+            state.elapsed = Duration::ZERO;
+        }
+        "next" | "previous" => {
+            if state.state == State::Stop {
+                return Err((ACK_ERROR_ARG, "Not playing".to_owned()));
+            }
+            let current = state.current.unwrap_or(0);
+            let target = if command == "next" {
+                current + 1
+            } else {
+                current.saturating_sub(1)
+            };
+            if target < state.queue.len() {
+                state.play_at(target)?;
+            } else if state.repeat && !state.queue.is_empty() {
+                state.play_at(0)?;
+            } else {
+                state.state = State::Stop;
+                state.current = None;
+            }
+        }
+        "seekcur" => {
+            let seconds: f64 = parse_num(args.get(1))?;
+            if state.current.is_none() {
+                return Err((ACK_ERROR_ARG, "Not playing".to_owned()));
+            }
+            state.elapsed = Duration::from_secs_f64(seconds.max(0.0));
+        }
+        "setvol" => {
+            let volume: i8 = parse_num(args.get(1))?;
+            if !(0..=100).contains(&volume) {
+                return Err((ACK_ERROR_ARG, "Invalid volume value".to_owned()));
+            }
+            state.volume = volume;
+        }
+        "repeat" => state.repeat = parse_bool(args.get(1))?,
+        "random" => state.random = parse_bool(args.get(1))?,
+        "single" => state.single = parse_mode(args.get(1))?,
+        "consume" => state.consume = parse_mode(args.get(1))?,
+        "crossfade" => state.crossfade = parse_num(args.get(1))?,
+        "clear" => {
+            state.queue.clear();
+            state.current = None;
+            state.state = State::Stop;
+            state.renumber();
+        }
+        "add" => {
+            let song = state.find_file(args.get(1).map_or("", String::as_str))?;
+            state.insert(song, None);
+        }
+        "addid" => {
+            let song = state.find_file(args.get(1).map_or("", String::as_str))?;
+            let pos = match args.get(2) {
+                Some(_) => Some(parse_num(args.get(2))?),
+                None => None,
+            };
+            let id = state.insert(song, pos);
+            out.push_str(&format!("Id: {id}\n"));
+        }
+        "delete" => {
+            let range = parse_range(args.get(1), state.queue.len())?;
+            state.remove(range);
+        }
+        "move" | "moveid" => {
+            let range = if command == "moveid" {
+                let pos = state.position_of(parse_num(args.get(1))?)?;
+                pos..pos + 1
+            } else {
+                parse_range(args.get(1), state.queue.len())?
+            };
+            let to: usize = parse_num(args.get(2))?;
+            if to + range.len() > state.queue.len() {
+                return Err((ACK_ERROR_ARG, "Bad song index".to_owned()));
+            }
+            let current_id = state
+                .current_song()
+                .and_then(|song| song.place)
+                .map(|p| p.id);
+            let moved = state.queue.drain(range).collect::<Vec<_>>();
+            state.queue.splice(to..to, moved);
+            state.renumber();
+            state.current = current_id.and_then(|id| {
+                state
+                    .queue
+                    .iter()
+                    .position(|song| song.place.map(|p| p.id) == Some(id))
+            });
+        }
+        "prioid" => {
+            let prio: u8 = parse_num(args.get(1))?;
+            for arg in &args[2..] {
+                let pos = state.position_of(parse_num(Some(arg))?)?;
+                if let Some(place) = &mut state.queue[pos].place {
+                    place.prio = prio;
+                }
+            }
+            state.renumber();
+        }
+        "listplaylists" => {
+            for name in state.playlists.keys() {
+                out.push_str(&format!(
+                    "playlist: {name}\nLast-Modified: 2024-01-01T00:00:00Z\n"
+                ));
+            }
+        }
+        "listplaylist" | "listplaylistinfo" => {
+            let files = state.playlist(args.get(1))?.clone();
+            for file in files {
+                match state.library.iter().find(|song| song.file == file) {
+                    Some(song) if command == "listplaylistinfo" => write_song(&mut out, song),
+                    _ => out.push_str(&format!("file: {file}\n")),
+                }
+            }
+        }
+        "load" => {
+            let files = state.playlist(args.get(1))?.clone();
+            for file in files {
+                let song = state.find_file(&file)?;
+                state.insert(song, None);
+            }
+        }
+        "save" => {
+            let name = args.get(1).cloned().unwrap_or_default();
+            if state.playlists.contains_key(&name) {
+                return Err((ACK_ERROR_EXIST, "Playlist already exists".to_owned()));
+            }
+            let files = state.queue_files().into_iter().map(str::to_owned).collect();
+            state.playlists.insert(name, files);
+        }
+        "rename" => {
+            let files = state.playlist(args.get(1))?.clone();
+            let new_name = args.get(2).cloned().unwrap_or_default();
+            if state.playlists.contains_key(&new_name) {
+                return Err((ACK_ERROR_EXIST, "Playlist already exists".to_owned()));
+            }
+            state.playlists.remove(&args[1]);
+            state.playlists.insert(new_name, files);
+        }
+        "rm" => {
+            state.playlist(args.get(1))?;
+            state.playlists.remove(&args[1]);
+        }
+        "playlistclear" => state.playlist(args.get(1))?.clear(),
+        "playlistadd" => {
+            let song = state.find_file(args.get(2).map_or("", String::as_str))?;
+            let name = args.get(1).cloned().unwrap_or_default();
+            state.playlists.entry(name).or_default().push(song.file);
+        }
+        "playlistdelete" => {
+            let pos: usize = parse_num(args.get(2))?;
+            let playlist = state.playlist(args.get(1))?;
+            if pos >= playlist.len() {
+                return Err((ACK_ERROR_ARG, "Bad song index".to_owned()));
+            }
+            playlist.remove(pos);
+        }
+        "playlistmove" => {
+            let from: usize = parse_num(args.get(2))?;
+            let to: usize = parse_num(args.get(3))?;
+            let playlist = state.playlist(args.get(1))?;
+            if from >= playlist.len() || to >= playlist.len() {
+                return Err((ACK_ERROR_ARG, "Bad song index".to_owned()));
+            }
+            let file = playlist.remove(from);
+            playlist.insert(to, file);
+        }
+        "deleteid" => {
+            let pos = state.position_of(parse_num(args.get(1))?)?;
+            state.remove(pos..pos + 1);
+        }
+        "shuffle" => {
+            let range = match args.get(1) {
+                Some(_) => parse_range(args.get(1), state.queue.len())?,
+                None => 0..state.queue.len(),
+            };
+            let current_id = state
+                .current_song()
+                .and_then(|song| song.place)
+                .map(|p| p.id);
+            // Deterministic xorshift so tests can rely on the queue actually changing
+            let mut seed = 0x9E37_79B9_u32 ^ state.queue_version;
+            let slice = &mut state.queue[range];
+            for idx in (1..slice.len()).rev() {
+                seed ^= seed << 13;
+                seed ^= seed >> 17;
+                seed ^= seed << 5;
+                slice.swap(idx, seed as usize % (idx + 1));
+            }
+            state.renumber();
+            state.current = state
+                .queue
+                .iter()
+                .position(|song| song.place.map(|p| p.id) == current_id && current_id.is_some());
+        }
+        _ => return Err((ACK_ERROR_UNKNOWN, format!("unknown command \"{command}\""))),
+    }
+    Ok(out)
+}
+
+// Binary chunk of a cover, in pieces as small as MPD's default binarylimit
+fn picture(state: &MockState, args: &[String]) -> Result<Vec<u8>, (u8, String)> {
+    const CHUNK: usize = 8192;
+    let (Some(command), Some(uri)) = (args.first(), args.get(1)) else {
+        return Err((ACK_ERROR_ARG, "wrong number of arguments".to_owned()));
+    };
+    let offset = args
+        .get(2)
+        .and_then(|offset| offset.parse::<usize>().ok())
+        .unwrap_or_default();
+    let cover = match command.as_str() {
+        // Only files in the database can be decoded for a picture
+        "readpicture" if !state.library.iter().any(|song| &song.file == uri) => {
+            return Err((ACK_ERROR_NO_EXIST, "No such song".to_owned()));
+        }
+        "readpicture" => state.pictures.get(uri),
+        _ => Some(
+            state
+                .covers
+                .get(uri)
+                .ok_or((ACK_ERROR_NO_EXIST, "No file exists".to_owned()))?,
+        ),
+    };
+    let Some(cover) = cover else {
+        return Ok(b"OK\n".to_vec());
+    };
+    let chunk = cover.get(offset..).unwrap_or_default();
+    let chunk = &chunk[..chunk.len().min(CHUNK)];
+    let mut out = format!("size: {}\nbinary: {}\n", cover.len(), chunk.len()).into_bytes();
+    out.extend(chunk);
+    out.extend(b"\nOK\n");
+    Ok(out)
+}
+
+fn ack(code: u8, index: usize, command: &str, message: &str) -> String {
+    format!("ACK [{code}@{index}] {{{command}}} {message}\n")
+}
+
+fn serve(stream: TcpStream, state: Arc<Mutex<MockState>>) -> io::Result<()> {
+    let mut writer = stream.try_clone()?;
+    let reader = BufReader::new(stream);
+    writer.write_all(b"OK MPD 0.23.5\n")?;
+
+    // Pending command list, and whether `list_OK` is sent after each entry
+    let mut batch: Option<(Vec<Vec<String>>, bool)> = None;
+    for line in reader.lines() {
+        let line = line?;
+        let args = tokenize(&line);
+        let command = args.first().cloned().unwrap_or_default();
+        let mut state = state.lock().expect("Mock state poisoned");
+        state.commands.push(line.clone());
+
+        let response = match (command.as_str(), &mut batch) {
+            ("command_list_begin", None) => {
+                batch = Some((vec![], false));
+                continue;
+            }
+            ("command_list_ok_begin", None) => {
+                batch = Some((vec![], true));
+                continue;
+            }
+            ("command_list_end", Some(_)) => {
+                let (commands, list_ok) = batch.take().unwrap_or_default();
+                let mut response = String::new();
+                let mut failed = false;
+                for (index, args) in commands.iter().enumerate() {
+                    match handle(&mut state, args) {
+                        Ok(out) => {
+                            response.push_str(&out);
+                            if list_ok {
+                                response.push_str("list_OK\n");
+                            }
+                        }
+                        Err((code, message)) => {
+                            response.push_str(&ack(code, index, &args[0], &message));
+                            failed = true;
+                            break;
+                        }
+                    }
+                }
+                if !failed {
+                    response.push_str("OK\n");
+                }
+                response
+            }
+            (_, Some((commands, _))) => {
+                commands.push(args);
+                continue;
+            }
+            ("close", None) => return Ok(()),
+            ("albumart" | "readpicture", None) => {
+                let response = picture(&state, &args).unwrap_or_else(|(code, message)| {
+                    ack(code, 0, &command, &message).into_bytes()
+                });
+                drop(state);
+                writer.write_all(&response)?;
+                continue;
+            }
+            (_, None) => match handle(&mut state, &args) {
+                Ok(out) => out + "OK\n",
+                Err((code, message)) => ack(code, 0, &command, &message),
+            },
+        };
+        drop(state);
+        writer.write_all(response.as_bytes())?;
+    }
+    Ok(())
+}
+
+// A fake MPD daemon speaking the text protocol on a loopback socket
+pub struct MockServer {
+    addr: SocketAddr,
+    state: Arc<Mutex<MockState>>,
+    clients: Arc<Mutex<Vec<TcpStream>>>,
+    running: Arc<AtomicBool>,
+}
+
+impl MockServer {
+    pub fn start() -> io::Result<Self> {
+        Self::with_library(vec![])
+    }
+
+    pub fn with_library(library: Vec<Song>) -> io::Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let server = Self {
+            addr: listener.local_addr()?,
+            state: Arc::new(Mutex::new(MockState {
+                library,
+                ..Default::default()
+            })),
+            clients: Arc::default(),
+            running: Arc::new(AtomicBool::new(true)),
+        };
+
+        let state = server.state.clone();
+        let clients = server.clients.clone();
+        let running = server.running.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                if !running.load(Ordering::SeqCst) {
+                    break;
+                }
+                let Ok(stream) = stream else { continue };
+                if let Ok(clone) = stream.try_clone() {
+                    clients.lock().expect("Mock clients poisoned").push(clone);
+                }
+                let state = state.clone();
+                thread::spawn(move || serve(stream, state));
+            }
+        });
+        Ok(server)
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    pub fn state(&self) -> MutexGuard<'_, MockState> {
+        self.state.lock().expect("Mock state poisoned")
+    }
+
+    // Forcefully closes every open client connection, as a daemon restart would
+    pub fn disconnect_all(&self) {
+        for client in self
+            .clients
+            .lock()
+            .expect("Mock clients poisoned")
+            .drain(..)
+        {
+            let _ = client.shutdown(Shutdown::Both);
+        }
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::SeqCst);
+        self.disconnect_all();
+        // Wake the accept loop so the listener thread can exit
+        let _ = TcpStream::connect(self.addr);
+    }
+}
+
+pub fn song(file: &str, title: &str, artist: &str, secs: u64) -> Song {
+    Song {
+        file: file.to_owned(),
+        title: Some(title.to_owned()),
+        artist: Some(artist.to_owned()),
+        duration: Some(Duration::from_secs(secs)),
+        ..Default::default()
+    }
+}
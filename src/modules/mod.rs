@@ -2,3 +2,26 @@ pub mod mpd;
 pub mod ui;
 pub mod crossterm;
 pub mod config;
+#[cfg(any(test, feature = "mock"))]
+pub mod mock;
+pub mod queue;
+pub mod list;
+pub mod library;
+pub mod playlists;
+pub mod popup;
+pub mod history;
+pub mod command;
+pub mod app;
+pub mod keymap;
+pub mod overlay;
+pub mod lyrics;
+pub mod art;
+pub mod tags;
+pub mod modes;
+pub mod toast;
+pub mod signals;
+pub mod events;
+pub mod buffer;
+pub mod progress;
+pub mod tabs;
+pub mod paste;
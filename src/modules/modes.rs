@@ -0,0 +1,219 @@
+use super::{
+    mpd::{Backend, Mpd, MpdError, MpdResult, OneshotMode},
+    overlay::{Line, Overlay},
+    ui::{Rect, Render},
+};
+
+// Seconds each step changes the crossfade by, up to the longest one offered
+const CROSSFADE_STEP: u64 = 1;
+const MAX_CROSSFADE: u64 = 30;
+// Crossfades listed to pick from, other lengths are a few steps away
+const CROSSFADES: [u64; 10] = [0, 1, 2, 3, 5, 8, 10, 15, 20, 30];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Row {
+    Repeat,
+    Random,
+    Single,
+    Consume,
+    Crossfade,
+}
+
+impl Row {
+    pub const ALL: [Row; 5] = [
+        Row::Repeat,
+        Row::Random,
+        Row::Single,
+        Row::Consume,
+        Row::Crossfade,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Row::Repeat => "repeat",
+            Row::Random => "random",
+            Row::Single => "single",
+            Row::Consume => "consume",
+            Row::Crossfade => "crossfade",
+        }
+    }
+
+    // Everything the mode can be set to, in the order they are numbered
+    pub fn values(self) -> Vec<Value> {
+        match self {
+            Row::Repeat | Row::Random => vec![Value::Switch(false), Value::Switch(true)],
+            Row::Single | Row::Consume => OneshotMode::ALL.map(Value::Oneshot).to_vec(),
+            Row::Crossfade => CROSSFADES.map(Value::Seconds).to_vec(),
+        }
+    }
+
+    // The value as last reported, None while disconnected
+    pub fn value<B: Backend>(self, mpd: &Mpd<B>) -> Option<Value> {
+        match self {
+            Row::Repeat => mpd.get_repeat().map(Value::Switch),
+            Row::Random => mpd.get_random().map(Value::Switch),
+            Row::Single => mpd.get_single().map(Value::Oneshot),
+            Row::Consume => mpd.get_consume().map(Value::Oneshot),
+            Row::Crossfade => mpd.get_crossfade().map(Value::Seconds),
+        }
+    }
+
+    fn set<B: Backend>(self, mpd: &mut Mpd<B>, value: Value) -> MpdResult<()> {
+        match (self, value) {
+            (Row::Repeat, Value::Switch(on)) => mpd.set_repeat(on),
+            (Row::Random, Value::Switch(on)) => mpd.set_random(on),
+            (Row::Single, Value::Oneshot(mode)) => mpd.set_single(mode),
+            (Row::Consume, Value::Oneshot(mode)) => mpd.set_consume(mode),
+            (Row::Crossfade, Value::Seconds(seconds)) => mpd.set_crossfade(seconds),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Value {
+    Switch(bool),
+    Oneshot(OneshotMode),
+    Seconds(u64),
+}
+
+impl Value {
+    pub fn label(self) -> String {
+        match self {
+            Value::Switch(false) | Value::Seconds(0) => "off".to_owned(),
+            Value::Switch(true) => "on".to_owned(),
+            Value::Oneshot(mode) => mode.name().to_owned(),
+            Value::Seconds(seconds) => format!("{seconds} s"),
+        }
+    }
+}
+
+// Sets each playback mode to a value directly, where the keys in normal mode
+// only step through them one at a time
+pub struct ModesMenu {
+    pub sheet: Overlay,
+    // The mode whose values are listed instead of the modes, to pick one of them
+    pub choosing: Option<Row>,
+}
+
+impl ModesMenu {
+    pub fn new<B: Backend>(mpd: &Mpd<B>) -> Self {
+        let mut menu = Self {
+            sheet: Overlay::new("Playback modes", vec![]),
+            choosing: None,
+        };
+        menu.refresh(mpd);
+        menu
+    }
+
+    pub fn selected(&self) -> Row {
+        self.choosing
+            .unwrap_or(Row::ALL[self.sheet.list.cursor.min(Row::ALL.len() - 1)])
+    }
+
+    // Shows the modes as last reported, or dashes while disconnected. While
+    // choosing, the values of that mode are listed with their numbers instead
+    pub fn refresh<B: Backend>(&mut self, mpd: &Mpd<B>) {
+        if let Some(row) = self.choosing {
+            self.sheet.title = format!("Playback modes: {}", row.label());
+            self.sheet.lines = (1..)
+                .zip(row.values())
+                .map(|(nth, value)| Line::Entry(nth.to_string(), value.label()))
+                .collect();
+            return;
+        }
+        self.sheet.title = "Playback modes".to_owned();
+        self.sheet.lines = Row::ALL
+            .iter()
+            .map(|row| {
+                let value = row.value(mpd).map_or_else(|| "-".to_owned(), Value::label);
+                Line::Entry(row.label().to_owned(), value)
+            })
+            .collect();
+    }
+
+    // Lists the values of the selected mode, starting on the current one
+    pub fn open<B: Backend>(&mut self, mpd: &Mpd<B>) {
+        let row = self.selected();
+        let current = row.value(mpd);
+        self.choosing = Some(row);
+        self.sheet.list.cursor = row
+            .values()
+            .iter()
+            .position(|value| Some(*value) == current)
+            .unwrap_or_default();
+        self.refresh(mpd);
+    }
+
+    // Goes back from the values to the modes
+    pub fn close<B: Backend>(&mut self, mpd: &Mpd<B>) {
+        if let Some(row) = self.choosing.take() {
+            self.sheet.list.cursor = Row::ALL
+                .iter()
+                .position(|other| *other == row)
+                .unwrap_or_default();
+        }
+        self.refresh(mpd);
+    }
+
+    // Sets the `nth` value, counting from 1, of the selected mode, or the one under
+    // the cursor while its values are listed. Without either the values are listed
+    pub fn choose<B: Backend>(&mut self, mpd: &mut Mpd<B>, nth: Option<usize>) -> MpdResult<()> {
+        let row = self.selected();
+        let index = match (nth, self.choosing) {
+            (Some(nth), _) => nth.checked_sub(1),
+            (None, Some(_)) => Some(self.sheet.list.cursor),
+            (None, None) => {
+                self.open(mpd);
+                return Ok(());
+            }
+        };
+        let Some(value) = index.and_then(|index| row.values().get(index).copied()) else {
+            return Ok(());
+        };
+        if row.value(mpd).is_none() {
+            return Err(MpdError::NotConnected);
+        }
+        let result = row.set(mpd, value);
+        self.close(mpd);
+        result
+    }
+
+    // Moves the selected mode on to its next value, or back to the previous one
+    pub fn change<B: Backend>(&mut self, mpd: &mut Mpd<B>, forward: bool) -> MpdResult<()> {
+        let result = match self.selected() {
+            Row::Repeat => mpd.toggle_repeat(),
+            Row::Random => mpd.toggle_random(),
+            Row::Single => {
+                let single = mpd.get_single().ok_or(MpdError::NotConnected)?;
+                mpd.set_single(if forward {
+                    single.next()
+                } else {
+                    single.prev()
+                })
+            }
+            Row::Consume => {
+                let consume = mpd.get_consume().ok_or(MpdError::NotConnected)?;
+                mpd.set_consume(if forward {
+                    consume.next()
+                } else {
+                    consume.prev()
+                })
+            }
+            Row::Crossfade => {
+                let seconds = mpd.get_crossfade().ok_or(MpdError::NotConnected)?;
+                mpd.set_crossfade(if forward {
+                    (seconds + CROSSFADE_STEP).min(MAX_CROSSFADE)
+                } else {
+                    seconds.saturating_sub(CROSSFADE_STEP)
+                })
+            }
+        };
+        self.refresh(mpd);
+        result
+    }
+
+    pub fn draw<R: Render>(&mut self, ct: &mut R, screen: Rect) {
+        self.sheet.draw(ct, screen);
+    }
+}
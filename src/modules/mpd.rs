@@ -1,85 +1,758 @@
-use std::{net::ToSocketAddrs, time::Duration};
+use std::{
+    fmt::{self, Display, Formatter},
+    io::{self, BufRead, BufReader, Read, Write},
+    iter, mem,
+    net::{SocketAddr, TcpStream, ToSocketAddrs},
+    ops::Range,
+    time::{Duration, Instant},
+};
 
 use log::{error, info, warn};
-use mpd::{Client, Id, Song, Stats, Status};
+use mpd::{
+    error::{Error, ErrorCode},
+    reply::Reply,
+    song::QueuePlace,
+    Client, Id, Playlist, Song, State, Stats, Status,
+};
 
-pub struct Mpd {
-    connection: Client,
+use super::history::History;
+
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(2);
+const DEFAULT_HISTORY_DEPTH: usize = 50;
+
+// Why an operation on MPD did not happen
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MpdError {
+    // Still reconnecting
+    NotConnected,
+    // MPD refused the command with an ACK, the connection stays up
+    Server { context: String, message: String },
+    // The socket failed, so the connection was dropped
+    Connection { context: String, message: String },
+    NoMixer,
+    NotPlaying,
+}
+
+impl Display for MpdError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            MpdError::NotConnected => write!(f, "Not connected to MPD"),
+            MpdError::Server { context, message } => write!(f, "{context}: {message}"),
+            MpdError::Connection { context, message } => {
+                write!(f, "{context}: {message}, lost connection to MPD")
+            }
+            MpdError::NoMixer => write!(f, "No volume control, MPD has no mixer"),
+            MpdError::NotPlaying => write!(f, "Nothing is playing"),
+        }
+    }
+}
+
+impl std::error::Error for MpdError {}
+
+pub type MpdResult<T> = Result<T, MpdError>;
+
+// Single and consume can also be set for one song only, after which MPD turns
+// them off again
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OneshotMode {
+    #[default]
+    Off,
+    On,
+    Oneshot,
+}
+
+impl OneshotMode {
+    pub const ALL: [OneshotMode; 3] = [OneshotMode::Off, OneshotMode::On, OneshotMode::Oneshot];
+
+    // As MPD reports and takes it
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "0" => Some(OneshotMode::Off),
+            "1" => Some(OneshotMode::On),
+            "oneshot" => Some(OneshotMode::Oneshot),
+            _ => None,
+        }
+    }
+
+    pub fn arg(self) -> &'static str {
+        match self {
+            OneshotMode::Off => "0",
+            OneshotMode::On => "1",
+            OneshotMode::Oneshot => "oneshot",
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            OneshotMode::Off => "off",
+            OneshotMode::On => "on",
+            OneshotMode::Oneshot => "oneshot",
+        }
+    }
+
+    // Off, on, oneshot and around again
+    pub fn next(self) -> Self {
+        match self {
+            OneshotMode::Off => OneshotMode::On,
+            OneshotMode::On => OneshotMode::Oneshot,
+            OneshotMode::Oneshot => OneshotMode::Off,
+        }
+    }
+
+    pub fn prev(self) -> Self {
+        self.next().next()
+    }
+}
+
+// Single and consume as reported, which the crate's `Status` reads as booleans
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Modes {
+    pub single: OneshotMode,
+    pub consume: OneshotMode,
+}
+
+// Backend Trait contains every MPD operation the client relies on
+pub trait Backend {
+    fn connect(addr: SocketAddr) -> mpd::error::Result<Self>
+    where
+        Self: Sized;
+
+    // Playback options & status
+    fn status(&mut self) -> mpd::error::Result<(Status, Modes)>;
+    fn stats(&mut self) -> mpd::error::Result<Stats>;
+    fn volume(&mut self, volume: i8) -> mpd::error::Result<()>;
+    fn repeat(&mut self, value: bool) -> mpd::error::Result<()>;
+    fn random(&mut self, value: bool) -> mpd::error::Result<()>;
+    fn single(&mut self, mode: OneshotMode) -> mpd::error::Result<()>;
+    fn consume(&mut self, mode: OneshotMode) -> mpd::error::Result<()>;
+    fn crossfade(&mut self, seconds: u64) -> mpd::error::Result<()>;
+
+    // Playback control
+    fn play(&mut self) -> mpd::error::Result<()>;
+    fn toggle_pause(&mut self) -> mpd::error::Result<()>;
+    fn stop(&mut self) -> mpd::error::Result<()>;
+    fn next(&mut self) -> mpd::error::Result<()>;
+    fn prev(&mut self) -> mpd::error::Result<()>;
+    fn switch(&mut self, position: u32) -> mpd::error::Result<()>;
+    fn rewind(&mut self, position: Duration) -> mpd::error::Result<()>;
+
+    // Queue & Database
+    fn currentsong(&mut self) -> mpd::error::Result<Option<Song>>;
+    fn queue(&mut self) -> mpd::error::Result<Vec<Song>>;
+    fn listall(&mut self) -> mpd::error::Result<Vec<Song>>;
+    fn push(&mut self, path: &str) -> mpd::error::Result<Id>;
+    fn delete(&mut self, id: Id) -> mpd::error::Result<()>;
+    fn shuffle(&mut self, range: Option<Range<u32>>) -> mpd::error::Result<()>;
+    fn clear(&mut self) -> mpd::error::Result<()>;
+    fn listallinfo(&mut self) -> mpd::error::Result<Vec<Song>>;
+
+    // Stored Playlists
+    fn playlists(&mut self) -> mpd::error::Result<Vec<Playlist>>;
+    fn playlist(&mut self, name: &str) -> mpd::error::Result<Vec<Song>>;
+    fn load(&mut self, name: &str) -> mpd::error::Result<()>;
+    fn save(&mut self, name: &str) -> mpd::error::Result<()>;
+    fn pl_rename(&mut self, name: &str, new_name: &str) -> mpd::error::Result<()>;
+    fn pl_remove(&mut self, name: &str) -> mpd::error::Result<()>;
+
+    // Runs every command in one round trip, stopping at the first failure
+    fn command_list(&mut self, commands: &[Command]) -> mpd::error::Result<()>;
+    // Song counts of the stored playlists `names`, in one round trip
+    fn playlist_lengths(&mut self, names: &[String]) -> mpd::error::Result<Vec<usize>>;
+
+    // One chunk of a cover from `albumart` or `readpicture`, with the total size
+    fn picture(
+        &mut self,
+        command: &str,
+        uri: &str,
+        offset: usize,
+    ) -> mpd::error::Result<Option<(usize, Vec<u8>)>>;
+}
+
+// Commands that can be queued into a single MPD command list
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Add(String),
+    DeleteId(Id),
+    MoveId(Id, u32),
+    PrioId(Id, u8),
+    PlaylistAdd(String, String),
+    PlaylistDelete(String, u32),
+    PlaylistMove(String, u32, u32),
+    Update(String),
+}
+
+fn quote(arg: &str) -> String {
+    format!("\"{}\"", arg.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+impl Display for Command {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Command::Add(file) => write!(f, "add {}", quote(file)),
+            Command::DeleteId(id) => write!(f, "deleteid {}", id.0),
+            Command::MoveId(id, to) => write!(f, "moveid {} {to}", id.0),
+            Command::PrioId(id, prio) => write!(f, "prioid {prio} {}", id.0),
+            Command::PlaylistAdd(name, file) => {
+                write!(f, "playlistadd {} {}", quote(name), quote(file))
+            }
+            Command::PlaylistDelete(name, pos) => {
+                write!(f, "playlistdelete {} {pos}", quote(name))
+            }
+            Command::PlaylistMove(name, from, to) => {
+                write!(f, "playlistmove {} {from} {to}", quote(name))
+            }
+            Command::Update(path) => write!(f, "update {}", quote(path)),
+        }
+    }
+}
+
+// Builds song structs out of `key: value` pairs, one song per `file` key
+pub fn parse_songs(pairs: Vec<(String, String)>) -> Vec<Song> {
+    let mut songs: Vec<Song> = vec![];
+    for (key, value) in pairs {
+        if key == "file" {
+            songs.push(Song {
+                file: value,
+                ..Default::default()
+            });
+            continue;
+        }
+        let Some(song) = songs.last_mut() else {
+            continue;
+        };
+        match key.as_str() {
+            "Title" => song.title = Some(value),
+            "Artist" => song.artist = Some(value),
+            "Name" => song.name = Some(value),
+            "Last-Modified" => song.last_mod = Some(value),
+            "Time" => song.duration = value.parse().ok().map(Duration::from_secs),
+            "Pos" | "Id" | "Prio" => {
+                let place = song.place.get_or_insert_with(QueuePlace::default);
+                match key.as_str() {
+                    "Pos" => place.pos = value.parse().unwrap_or_default(),
+                    "Id" => place.id = Id(value.parse().unwrap_or_default()),
+                    _ => place.prio = value.parse().unwrap_or_default(),
+                }
+            }
+            "duration" | "directory" | "playlist" => {}
+            _ => song.tags.push((key, value)),
+        }
+    }
+    songs
+}
+
+// Builds the status out of the `key: value` pairs of `status`. The modes come from
+// the same pairs, since `Status` reads a oneshot single or consume as off
+pub fn parse_status(pairs: Vec<(String, String)>) -> (Status, Modes) {
+    // Newer MPD leaves the volume out when there is no mixer
+    let mut status = Status {
+        volume: -1,
+        ..Default::default()
+    };
+    let mut modes = Modes::default();
+    let seconds = |value: &str| {
+        value
+            .parse()
+            .ok()
+            .and_then(|secs| Duration::try_from_secs_f64(secs).ok())
+    };
+    for (key, value) in pairs {
+        match key.as_str() {
+            "volume" => status.volume = value.parse().unwrap_or(-1),
+            "repeat" => status.repeat = value == "1",
+            "random" => status.random = value == "1",
+            "single" => {
+                modes.single = OneshotMode::parse(&value).unwrap_or_default();
+                status.single = modes.single == OneshotMode::On;
+            }
+            "consume" => {
+                modes.consume = OneshotMode::parse(&value).unwrap_or_default();
+                status.consume = modes.consume == OneshotMode::On;
+            }
+            "playlist" => status.queue_version = value.parse().unwrap_or_default(),
+            "playlistlength" => status.queue_len = value.parse().unwrap_or_default(),
+            "state" => status.state = value.parse().unwrap_or(State::Stop),
+            "song" | "songid" | "nextsong" | "nextsongid" => {
+                let place = match key.as_str() {
+                    "song" | "songid" => &mut status.song,
+                    _ => &mut status.nextsong,
+                }
+                .get_or_insert_with(QueuePlace::default);
+                if key.ends_with("id") {
+                    place.id = Id(value.parse().unwrap_or_default());
+                } else {
+                    place.pos = value.parse().unwrap_or_default();
+                }
+            }
+            "time" => {
+                status.time = value.split_once(':').and_then(|(elapsed, total)| {
+                    Some((
+                        Duration::from_secs(elapsed.parse().ok()?),
+                        Duration::from_secs(total.parse().ok()?),
+                    ))
+                })
+            }
+            "elapsed" => status.elapsed = seconds(&value),
+            "duration" => status.duration = seconds(&value),
+            "bitrate" => status.bitrate = value.parse().ok(),
+            "xfade" => status.crossfade = value.parse().ok().map(Duration::from_secs),
+            "audio" => status.audio = value.parse().ok(),
+            "updating_db" => status.updating_db = value.parse().ok(),
+            "error" => status.error = Some(value),
+            _ => {}
+        }
+    }
+    (status, modes)
+}
+
+// Produces the `moveid` commands that turn the `current` order into `target`
+pub fn reorder_commands(current: &[Id], target: &[Id]) -> Vec<Command> {
+    let mut order = current.to_vec();
+    let mut commands = vec![];
+    for (pos, id) in target.iter().enumerate() {
+        if order.get(pos) == Some(id) {
+            continue;
+        }
+        if let Some(from) = order.iter().position(|other| other == id) {
+            order.remove(from);
+            order.insert(pos, *id);
+            commands.push(Command::MoveId(*id, pos as u32));
+        }
+    }
+    commands
+}
+
+type Pairs = Vec<(String, String)>;
+
+// Connection wraps the mpd crate client and keeps a second handle on the same
+// socket for protocol features the crate does not expose (command lists etc.).
+// The raw side keeps one reader, so bytes it buffered ahead are never lost
+pub struct Connection {
+    client: Client,
+    raw: BufReader<TcpStream>,
+}
+
+impl Connection {
+    // Sends a raw command line and collects the response pairs until `OK`
+    pub fn raw_command(&mut self, command: &str) -> mpd::error::Result<Vec<(String, String)>> {
+        self.raw.get_mut().write_all(command.as_bytes())?;
+        self.raw.get_mut().write_all(b"\n")?;
+        let reader = &mut self.raw;
+        let mut pairs = vec![];
+        loop {
+            let mut line = String::new();
+            if reader.read_line(&mut line)? == 0 {
+                return Err(Error::Io(io::ErrorKind::UnexpectedEof.into()));
+            }
+            match line.trim_end_matches('\n').parse::<Reply>()? {
+                Reply::Ok => return Ok(pairs),
+                Reply::Ack(err) => return Err(Error::Server(err)),
+                Reply::Pair(key, value) => pairs.push((key, value)),
+            }
+        }
+    }
+
+    // Runs `commands` as a command list and collects the response pairs of each
+    pub fn raw_list(&mut self, commands: &[String]) -> mpd::error::Result<Vec<Pairs>> {
+        let mut list = String::from("command_list_ok_begin\n");
+        for command in commands {
+            list.push_str(command);
+            list.push('\n');
+        }
+        list.push_str("command_list_end\n");
+        self.raw.get_mut().write_all(list.as_bytes())?;
+        let reader = &mut self.raw;
+        let mut replies = vec![];
+        let mut pairs = vec![];
+        loop {
+            let mut line = String::new();
+            if reader.read_line(&mut line)? == 0 {
+                return Err(Error::Io(io::ErrorKind::UnexpectedEof.into()));
+            }
+            let line = line.trim_end_matches('\n');
+            // The crate reads `list_OK` as `OK`, which would end the list early
+            if line == "list_OK" {
+                replies.push(mem::take(&mut pairs));
+                continue;
+            }
+            match line.parse::<Reply>()? {
+                Reply::Ok => return Ok(replies),
+                Reply::Ack(err) => return Err(Error::Server(err)),
+                Reply::Pair(key, value) => pairs.push((key, value)),
+            }
+        }
+    }
+
+    // Like `raw_command`, but also reads the bytes announced by a `binary` pair
+    pub fn raw_binary(&mut self, command: &str) -> mpd::error::Result<(Pairs, Vec<u8>)> {
+        self.raw.get_mut().write_all(command.as_bytes())?;
+        self.raw.get_mut().write_all(b"\n")?;
+        let reader = &mut self.raw;
+        let mut pairs = vec![];
+        let mut data = vec![];
+        loop {
+            let mut line = String::new();
+            if reader.read_line(&mut line)? == 0 {
+                return Err(Error::Io(io::ErrorKind::UnexpectedEof.into()));
+            }
+            match line.trim_end_matches('\n').parse::<Reply>()? {
+                Reply::Ok => return Ok((pairs, data)),
+                Reply::Ack(err) => return Err(Error::Server(err)),
+                Reply::Pair(key, value) if key == "binary" => {
+                    let len = value
+                        .parse()
+                        .map_err(|_| Error::Io(io::ErrorKind::InvalidData.into()))?;
+                    data = vec![0; len];
+                    reader.read_exact(&mut data)?;
+                    // The bytes are followed by a newline of their own
+                    reader.read_exact(&mut [0])?;
+                }
+                Reply::Pair(key, value) => pairs.push((key, value)),
+            }
+        }
+    }
+}
+
+impl Backend for Connection {
+    fn connect(addr: SocketAddr) -> mpd::error::Result<Self> {
+        let stream = TcpStream::connect(addr)?;
+        Ok(Self {
+            raw: BufReader::new(stream.try_clone()?),
+            client: Client::new(stream)?,
+        })
+    }
+
+    fn status(&mut self) -> mpd::error::Result<(Status, Modes)> {
+        self.raw_command("status").map(parse_status)
+    }
+
+    fn stats(&mut self) -> mpd::error::Result<Stats> {
+        self.client.stats()
+    }
+
+    fn volume(&mut self, volume: i8) -> mpd::error::Result<()> {
+        self.client.volume(volume)
+    }
+
+    fn repeat(&mut self, value: bool) -> mpd::error::Result<()> {
+        self.client.repeat(value)
+    }
+
+    fn random(&mut self, value: bool) -> mpd::error::Result<()> {
+        self.client.random(value)
+    }
+
+    fn single(&mut self, mode: OneshotMode) -> mpd::error::Result<()> {
+        self.raw_command(&format!("single {}", mode.arg()))
+            .map(drop)
+    }
+
+    fn consume(&mut self, mode: OneshotMode) -> mpd::error::Result<()> {
+        self.raw_command(&format!("consume {}", mode.arg()))
+            .map(drop)
+    }
+
+    fn crossfade(&mut self, seconds: u64) -> mpd::error::Result<()> {
+        self.raw_command(&format!("crossfade {seconds}")).map(drop)
+    }
+
+    fn play(&mut self) -> mpd::error::Result<()> {
+        self.client.play()
+    }
+
+    fn toggle_pause(&mut self) -> mpd::error::Result<()> {
+        self.client.toggle_pause()
+    }
+
+    fn stop(&mut self) -> mpd::error::Result<()> {
+        self.client.stop()
+    }
+
+    fn next(&mut self) -> mpd::error::Result<()> {
+        self.client.next()
+    }
+
+    fn prev(&mut self) -> mpd::error::Result<()> {
+        self.client.prev()
+    }
+
+    fn switch(&mut self, position: u32) -> mpd::error::Result<()> {
+        self.client.switch(position)
+    }
+
+    fn rewind(&mut self, position: Duration) -> mpd::error::Result<()> {
+        self.client.rewind(position)
+    }
+
+    fn currentsong(&mut self) -> mpd::error::Result<Option<Song>> {
+        self.client.currentsong()
+    }
+
+    fn queue(&mut self) -> mpd::error::Result<Vec<Song>> {
+        self.client.queue()
+    }
+
+    fn listall(&mut self) -> mpd::error::Result<Vec<Song>> {
+        self.client.listall()
+    }
+
+    fn push(&mut self, path: &str) -> mpd::error::Result<Id> {
+        self.client.push(Song {
+            file: path.to_owned(),
+            ..Default::default()
+        })
+    }
+
+    fn delete(&mut self, id: Id) -> mpd::error::Result<()> {
+        self.client.delete(id)
+    }
+
+    fn shuffle(&mut self, range: Option<Range<u32>>) -> mpd::error::Result<()> {
+        match range {
+            Some(range) => self.client.shuffle(range),
+            None => self.client.shuffle(..),
+        }
+    }
+
+    fn clear(&mut self) -> mpd::error::Result<()> {
+        self.client.clear()
+    }
+
+    fn listallinfo(&mut self) -> mpd::error::Result<Vec<Song>> {
+        self.raw_command("listallinfo").map(parse_songs)
+    }
+
+    fn playlists(&mut self) -> mpd::error::Result<Vec<Playlist>> {
+        self.client.playlists()
+    }
+
+    fn playlist(&mut self, name: &str) -> mpd::error::Result<Vec<Song>> {
+        self.client.playlist(name)
+    }
+
+    fn load(&mut self, name: &str) -> mpd::error::Result<()> {
+        self.client.load(name, ..)
+    }
+
+    fn save(&mut self, name: &str) -> mpd::error::Result<()> {
+        self.client.save(name)
+    }
+
+    fn pl_rename(&mut self, name: &str, new_name: &str) -> mpd::error::Result<()> {
+        self.client.pl_rename(name, new_name)
+    }
+
+    fn pl_remove(&mut self, name: &str) -> mpd::error::Result<()> {
+        self.client.pl_remove(name)
+    }
+
+    fn command_list(&mut self, commands: &[Command]) -> mpd::error::Result<()> {
+        if commands.is_empty() {
+            return Ok(());
+        }
+        let mut list = String::from("command_list_begin");
+        for command in commands {
+            list.push('\n');
+            list.push_str(&command.to_string());
+        }
+        list.push_str("\ncommand_list_end");
+        self.raw_command(&list).map(drop)
+    }
+
+    fn playlist_lengths(&mut self, names: &[String]) -> mpd::error::Result<Vec<usize>> {
+        if names.is_empty() {
+            return Ok(vec![]);
+        }
+        let commands: Vec<_> = names
+            .iter()
+            .map(|name| format!("listplaylist {}", quote(name)))
+            .collect();
+        let replies = self.raw_list(&commands)?;
+        Ok(replies
+            .iter()
+            .map(|pairs| pairs.iter().filter(|(key, _)| key == "file").count())
+            .collect())
+    }
+
+    fn picture(
+        &mut self,
+        command: &str,
+        uri: &str,
+        offset: usize,
+    ) -> mpd::error::Result<Option<(usize, Vec<u8>)>> {
+        let (pairs, data) = match self.raw_binary(&format!("{command} {} {offset}", quote(uri))) {
+            Ok(response) => response,
+            // No cover file next to the song
+            Err(Error::Server(err)) if err.code == ErrorCode::NoExist => return Ok(None),
+            Err(err) => return Err(err),
+        };
+        let size = pairs
+            .iter()
+            .find(|(key, _)| key == "size")
+            .and_then(|(_, size)| size.parse().ok());
+        Ok(size.map(|size| (size, data)))
+    }
+}
+
+pub struct Mpd<B: Backend = Connection> {
+    addr: SocketAddr,
+    connection: Option<B>,
+    last_attempt: Instant,
     status: Option<Status>,
+    // When the status came in, to tell how far playback has moved since
+    status_at: Instant,
     stats: Option<Stats>,
+    modes: Option<Modes>,
+    // Volume to go back to when unmuting
+    muted: Option<u8>,
+    // Queue contents, as file lists, from before each change
+    history: History<Vec<String>>,
+    // Files of the last fetched queue and the queue version they were fetched at
+    queue: Option<(u32, Vec<String>)>,
 }
 
-impl Mpd {
+impl<B: Backend> Mpd<B> {
     pub fn new<T: ToSocketAddrs>(addr: T) -> Self {
+        let addr = addr
+            .to_socket_addrs()
+            .ok()
+            .and_then(|mut addrs| addrs.next())
+            .unwrap_or_else(|| SocketAddr::new([127, 0, 0, 1].into(), 6600));
         Self {
-            connection: Client::connect(addr)
+            addr,
+            connection: B::connect(addr)
                 .map_err(|err| error!("Failed to Connect to MPD Server: {err}"))
-                .unwrap(),
+                .ok(),
+            last_attempt: Instant::now(),
             status: None,
+            status_at: Instant::now(),
             stats: None,
+            modes: None,
+            muted: None,
+            history: History::new(DEFAULT_HISTORY_DEPTH),
+            queue: None,
         }
     }
 
-    pub fn update_loop(&mut self) {
-        self.status = self.connection.status().map(Some).unwrap_or(None);
-        self.stats = self.connection.stats().map(Some).unwrap_or(None);
+    pub fn is_connected(&self) -> bool {
+        self.connection.is_some()
     }
 
-    pub fn set_volume(&mut self, volume: i8) {
-        let _ = self
-            .connection
-            .volume(volume)
-            .map_err(|err| warn!("Failed to set Volume: {err}"));
+    // Runs an operation on the connection, dropping it if the socket died
+    fn run<T>(
+        &mut self,
+        context: &str,
+        op: impl FnOnce(&mut B) -> mpd::error::Result<T>,
+    ) -> MpdResult<T> {
+        let connection = self.connection.as_mut().ok_or(MpdError::NotConnected)?;
+        op(connection).map_err(|err| {
+            warn!("{context}: {err}");
+            let context = context.to_owned();
+            match err {
+                Error::Server(err) => MpdError::Server {
+                    context,
+                    message: err.detail,
+                },
+                err => {
+                    warn!("Lost connection to MPD Server");
+                    self.connection = None;
+                    MpdError::Connection {
+                        context,
+                        message: err.to_string(),
+                    }
+                }
+            }
+        })
     }
 
-    pub fn increase_volume(&mut self) {
-        if let Some(vol) = self.get_volume() {
-            let _ = self
-                .connection
-                .volume(vol + 10)
-                .map_err(|err| warn!("Failed to set Volume: {err}"));
+    pub fn reconnect(&mut self) -> bool {
+        self.last_attempt = Instant::now();
+        match B::connect(self.addr) {
+            Ok(connection) => {
+                info!("Connected to MPD Server at {}", self.addr);
+                self.connection = Some(connection);
+                true
+            }
+            Err(err) => {
+                warn!("Failed to Connect to MPD Server: {err}");
+                false
+            }
         }
     }
-    pub fn decrease_volume(&mut self) {
-        if let Some(vol) = self.get_volume() {
-            let _ = self
-                .connection
-                .volume(vol - 10)
-                .map_err(|err| warn!("Failed to set Volume: {err}"));
+
+    pub fn update_loop(&mut self) {
+        if self.connection.is_none() {
+            self.status = None;
+            self.stats = None;
+            self.modes = None;
+            if self.last_attempt.elapsed() < RECONNECT_INTERVAL || !self.reconnect() {
+                return;
+            }
+        }
+        let status = self.run("Failed to get Status", |conn| conn.status()).ok();
+        (self.status, self.modes) = status.unzip();
+        self.status_at = Instant::now();
+        self.stats = self.run("Failed to get Stats", |conn| conn.stats()).ok();
+        // Someone else turned the volume back up
+        if self.get_volume().is_some_and(|volume| volume > 0) {
+            self.muted = None;
         }
     }
 
-    pub fn get_volume(&self) -> Option<i8> {
-        if let Some(status) = &self.status {
-            return Some(status.volume);
+    // MPD reports a volume of -1 when no mixer is configured for its outputs
+    pub fn has_mixer(&self) -> bool {
+        self.status
+            .as_ref()
+            .is_some_and(|status| status.volume >= 0)
+    }
+
+    pub fn set_volume(&mut self, volume: u8) -> MpdResult<()> {
+        if !self.has_mixer() {
+            return Err(MpdError::NoMixer);
         }
-        None
+        let volume = volume.min(100) as i8;
+        self.run("Failed to set Volume", |conn| conn.volume(volume))?;
+        self.muted = None;
+        // Keys held down step from here until the next status comes in
+        if let Some(status) = &mut self.status {
+            status.volume = volume;
+        }
+        Ok(())
     }
 
-    pub fn toggle_repeat(&mut self) {
-        let repeat_state = self.get_repeat().unwrap_or(false);
-        let single_state = self.get_single().unwrap_or(false);
-
-        if repeat_state && single_state {
-            let _ = self
-                .connection
-                .repeat(false)
-                .map_err(|err| warn!("Failed to toggle Repeat, Set Repeat: {err}"));
-            let _ = self
-                .connection
-                .single(false)
-                .map_err(|err| warn!("Failed to toggle Repeat, Set Single: {err}"));
-        } else if repeat_state {
-            let _ = self
-                .connection
-                .single(true)
-                .map_err(|err| warn!("Failed to toggle Repeat, Set Single: {err}"));
-        } else {
-            let _ = self
-                .connection
-                .repeat(true)
-                .map_err(|err| warn!("Failed to toggle Repeat, Set Repeat: {err}"));
+    // Moves the volume by `delta` points, staying within 0 and 100
+    pub fn change_volume(&mut self, delta: i16) -> MpdResult<()> {
+        let volume = self.get_volume().ok_or(MpdError::NoMixer)?;
+        self.set_volume((i16::from(volume) + delta).clamp(0, 100) as u8)
+    }
+
+    // Mutes, or brings back the volume from before muting
+    pub fn toggle_mute(&mut self) -> MpdResult<()> {
+        if let Some(volume) = self.muted {
+            return self.set_volume(volume);
+        }
+        let volume = self.get_volume().ok_or(MpdError::NoMixer)?;
+        if volume > 0 {
+            self.set_volume(0)?;
+            self.muted = Some(volume);
         }
+        Ok(())
+    }
+
+    pub fn is_muted(&self) -> bool {
+        self.muted.is_some()
+    }
+
+    // None while disconnected or without a mixer
+    pub fn get_volume(&self) -> Option<u8> {
+        self.status
+            .as_ref()
+            .and_then(|status| u8::try_from(status.volume).ok())
+    }
+
+    pub fn toggle_repeat(&mut self) -> MpdResult<()> {
+        let repeat = !self.get_repeat().unwrap_or(false);
+        self.set_repeat(repeat)
     }
 
     pub fn get_repeat(&self) -> Option<bool> {
@@ -89,134 +762,453 @@ impl Mpd {
         None
     }
 
-    pub fn get_single(&self) -> Option<bool> {
-        if let Some(status) = &self.status {
-            return Some(status.single);
-        }
-        None
+    pub fn get_single(&self) -> Option<OneshotMode> {
+        self.modes.map(|modes| modes.single)
+    }
+
+    pub fn toggle_random(&mut self) -> MpdResult<()> {
+        let random = !self.get_random().unwrap_or(false);
+        self.set_random(random)
     }
 
-    pub fn toggle_shuffle(&mut self) {
-        let _ = self
-            .connection
-            .shuffle(..)
-            .map_err(|err| warn!("Failed to toggle Shuffle: {err}"));
+    // Reorders the queue for good, or only the songs in `range`
+    pub fn shuffle_queue(&mut self, range: Option<Range<u32>>) -> MpdResult<()> {
+        self.change_queue(|mpd| mpd.run("Failed to Shuffle Queue", |conn| conn.shuffle(range)))
     }
 
-    pub fn get_shuffle(&self) -> Option<bool> {
+    pub fn get_random(&self) -> Option<bool> {
         if let Some(status) = &self.status {
             return Some(status.random);
         }
         None
     }
 
-    pub fn toggle_consume(&mut self) {
-        let _ = self
-            .connection
-            .consume(!self.get_consume().unwrap_or(false))
-            .map_err(|err| warn!("Failed to toggle Consume: {err}"));
+    pub fn cycle_single(&mut self) -> MpdResult<()> {
+        let single = self.get_single().unwrap_or_default().next();
+        self.set_single(single)
     }
 
-    pub fn get_consume(&self) -> Option<bool> {
-        if let Some(status) = &self.status {
-            return Some(status.consume);
+    pub fn cycle_consume(&mut self) -> MpdResult<()> {
+        let consume = self.get_consume().unwrap_or_default().next();
+        self.set_consume(consume)
+    }
+
+    // The setters also update the cached status, so menus show the new value
+    // before the next status comes in
+    pub fn set_repeat(&mut self, value: bool) -> MpdResult<()> {
+        self.run("Failed to set Repeat", |conn| conn.repeat(value))?;
+        if let Some(status) = &mut self.status {
+            status.repeat = value;
         }
-        None
+        Ok(())
     }
 
-    pub fn forward(&mut self, duration: Duration) {
-        if let Some((time, ..)) = self.get_time() {
-            self.seek(time + duration)
-        } else {
-            info!("A song must be playing")
-        };
+    pub fn set_random(&mut self, value: bool) -> MpdResult<()> {
+        self.run("Failed to set Random", |conn| conn.random(value))?;
+        if let Some(status) = &mut self.status {
+            status.random = value;
+        }
+        Ok(())
     }
 
-    pub fn rewind(&mut self, duration: Duration) {
-        if let Some((time, ..)) = self.get_time() {
-            self.seek(time - duration)
-        } else {
-            info!("A song must be playing")
-        };
+    pub fn set_single(&mut self, mode: OneshotMode) -> MpdResult<()> {
+        self.run("Failed to set Single", |conn| conn.single(mode))?;
+        if let Some(modes) = &mut self.modes {
+            modes.single = mode;
+        }
+        Ok(())
     }
 
-    pub fn seek(&mut self, seek_position: Duration) {
-        let _ = self
-            .connection
-            .rewind(seek_position)
-            .map_err(|err| error!("Failed to Seek: {err}"));
+    pub fn set_consume(&mut self, mode: OneshotMode) -> MpdResult<()> {
+        self.run("Failed to set Consume", |conn| conn.consume(mode))?;
+        if let Some(modes) = &mut self.modes {
+            modes.consume = mode;
+        }
+        Ok(())
     }
 
-    pub fn get_time(&self) -> Option<(Duration, Duration)> {
-        if let Some(status) = &self.status {
-            return status.time;
+    pub fn get_consume(&self) -> Option<OneshotMode> {
+        self.modes.map(|modes| modes.consume)
+    }
+
+    // MPD leaves crossfade out of the status while it is off
+    pub fn get_crossfade(&self) -> Option<u64> {
+        self.status
+            .as_ref()
+            .map(|status| status.crossfade.map_or(0, |crossfade| crossfade.as_secs()))
+    }
+
+    pub fn set_crossfade(&mut self, seconds: u64) -> MpdResult<()> {
+        self.run("Failed to set Crossfade", |conn| conn.crossfade(seconds))?;
+        if let Some(status) = &mut self.status {
+            status.crossfade = (seconds > 0).then(|| Duration::from_secs(seconds));
         }
-        None
+        Ok(())
     }
 
-    pub fn get_queue(&mut self) -> Vec<Song> {
-        if let Ok(queue) = self
-            .connection
-            .queue()
-            .map_err(|err| error!("Failed to Get Queue: {err}"))
-        {
-            queue
-        } else {
-            vec![]
+    pub fn forward(&mut self, duration: Duration) -> MpdResult<()> {
+        let (time, _) = self.get_time().ok_or(MpdError::NotPlaying)?;
+        self.seek(time + duration)
+    }
+
+    pub fn rewind(&mut self, duration: Duration) -> MpdResult<()> {
+        let (time, _) = self.get_time().ok_or(MpdError::NotPlaying)?;
+        self.seek(time.saturating_sub(duration))
+    }
+
+    pub fn seek(&mut self, seek_position: Duration) -> MpdResult<()> {
+        self.run("Failed to Seek", |conn| conn.rewind(seek_position))
+    }
+
+    // Cover of `uri`, embedded in the file or else a cover image in its directory.
+    // A failed `readpicture` still leaves the directory to look in
+    pub fn album_art(&mut self, uri: &str) -> Option<Vec<u8>> {
+        for command in ["readpicture", "albumart"] {
+            let mut data = vec![];
+            loop {
+                let chunk = self.run("Failed to read Album Art", |conn| {
+                    conn.picture(command, uri, data.len())
+                });
+                let Ok(Some((size, chunk))) = chunk else {
+                    break;
+                };
+                if chunk.is_empty() {
+                    break;
+                }
+                data.extend(chunk);
+                if data.len() >= size {
+                    return Some(data);
+                }
+            }
         }
+        None
     }
 
-    pub fn get_current_playing(&mut self) -> Option<Song> {
-        match self.connection.currentsong() {
-            Ok(song) => song,
-            Err(err) => {
-                error!("Failed to get current song: {err}");
-                None
+    // Elapsed and total time of the current song, to the millisecond where MPD
+    // reports it. While playing, the time since the status came in is added
+    pub fn get_time(&self) -> Option<(Duration, Duration)> {
+        let status = self.status.as_ref()?;
+        let (elapsed, total) = status.time?;
+        let mut elapsed = status.elapsed.unwrap_or(elapsed);
+        let total = status.duration.unwrap_or(total);
+        if status.state == State::Play {
+            elapsed += self.status_at.elapsed();
+            // Streams report no length
+            if !total.is_zero() {
+                elapsed = elapsed.min(total);
             }
         }
+        Some((elapsed, total))
+    }
+
+    pub fn get_status(&self) -> Option<&Status> {
+        self.status.as_ref()
+    }
+
+    pub fn get_stats(&self) -> Option<&Stats> {
+        self.stats.as_ref()
     }
+
+    pub fn get_queue(&mut self) -> Vec<Song> {
+        self.fetch_queue().unwrap_or_default()
+    }
+
+    fn fetch_queue(&mut self) -> MpdResult<Vec<Song>> {
+        let version = self.status.as_ref().map(|status| status.queue_version);
+        let queue = self.run("Failed to Get Queue", |conn| conn.queue())?;
+        self.queue = version.map(|version| {
+            let files = queue.iter().map(|song| song.file.clone()).collect();
+            (version, files)
+        });
+        Ok(queue)
+    }
+
+    pub fn get_current_playing(&mut self) -> Option<Song> {
+        self.run("Failed to get current song", |conn| conn.currentsong())
+            .ok()
+            .flatten()
+    }
+
+    pub fn get_library(&mut self) -> Vec<Song> {
+        self.run("Failed to Get Library", |conn| conn.listallinfo())
+            .unwrap_or_default()
+    }
+
     pub fn get_all_songs(&mut self) -> Vec<Song> {
-        if let Ok(list) = self
-            .connection
-            .listall()
-            .map_err(|err| error!("Failed to Get Queue: {err}"))
-        {
-            list
-        } else {
-            vec![]
+        self.run("Failed to Get Library", |conn| conn.listall())
+            .unwrap_or_default()
+    }
+
+    pub fn next_song(&mut self) -> MpdResult<()> {
+        self.run("Failed to skip to Next Song", |conn| conn.next())
+    }
+
+    pub fn prev_song(&mut self) -> MpdResult<()> {
+        self.run("Failed to skip to Previous Song", |conn| conn.prev())
+    }
+
+    pub fn toggle_play(&mut self) -> MpdResult<()> {
+        match self.status.as_ref().map(|status| status.state) {
+            Some(mpd::State::Play | mpd::State::Pause) => {
+                self.run("Failed to toggle Pause", |conn| conn.toggle_pause())
+            }
+            _ => self.run("Failed to Play", |conn| conn.play()),
         }
     }
 
-    pub fn next_song(&mut self) {
-        let _ = self.connection.next();
+    pub fn stop_playback(&mut self) -> MpdResult<()> {
+        self.run("Failed to Stop", |conn| conn.stop())
     }
 
-    pub fn prev_song(&mut self) {
-        let _ = self.connection.prev();
+    pub fn delete_from_queue(&mut self, song_id: Id) -> MpdResult<()> {
+        self.change_queue(|mpd| mpd.run("Failed to Delete from Queue", |conn| conn.delete(song_id)))
     }
 
-    pub fn toggle_play(&mut self) {
-        if let Some(status) = &self.status {
-            let _ = match status.state {
-                mpd::State::Stop => self.connection.play(),
-                mpd::State::Play | mpd::State::Pause => self.connection.toggle_pause(),
-            };
+    pub fn play_position(&mut self, position: u32) -> MpdResult<()> {
+        self.run("Failed to Play", |conn| conn.switch(position))
+    }
+
+    pub fn run_batch(&mut self, commands: Vec<Command>) -> MpdResult<()> {
+        self.run("Failed to run Command List", |conn| {
+            conn.command_list(&commands)
+        })
+    }
+
+    pub fn add_files(&mut self, files: impl IntoIterator<Item = String>) -> MpdResult<()> {
+        let commands = files.into_iter().map(Command::Add).collect();
+        self.change_queue(|mpd| mpd.run_batch(commands))
+    }
+
+    pub fn delete_ids(&mut self, ids: impl IntoIterator<Item = Id>) -> MpdResult<()> {
+        let commands = ids.into_iter().map(Command::DeleteId).collect();
+        self.change_queue(|mpd| mpd.run_batch(commands))
+    }
+
+    pub fn set_priority(&mut self, ids: impl IntoIterator<Item = Id>, prio: u8) -> MpdResult<()> {
+        self.run_batch(
+            ids.into_iter()
+                .map(|id| Command::PrioId(id, prio))
+                .collect(),
+        )
+    }
+
+    pub fn reorder_queue(&mut self, current: &[Id], target: &[Id]) -> MpdResult<()> {
+        self.change_queue(|mpd| mpd.run_batch(reorder_commands(current, target)))
+    }
+
+    // Stored playlists along with their track counts
+    pub fn get_playlists(&mut self) -> Vec<(Playlist, usize)> {
+        let playlists = self
+            .run("Failed to Get Playlists", |conn| conn.playlists())
+            .unwrap_or_default();
+        let names: Vec<_> = playlists
+            .iter()
+            .map(|playlist| playlist.name.clone())
+            .collect();
+        let counts = self
+            .run("Failed to Count Playlist Songs", |conn| {
+                conn.playlist_lengths(&names)
+            })
+            .unwrap_or_default();
+        playlists
+            .into_iter()
+            .zip(counts.into_iter().chain(iter::repeat(0)))
+            .collect()
+    }
+
+    pub fn get_playlist(&mut self, name: &str) -> Vec<Song> {
+        self.run("Failed to Get Playlist", |conn| conn.playlist(name))
+            .unwrap_or_default()
+    }
+
+    pub fn load_playlist(&mut self, name: &str) -> MpdResult<()> {
+        self.change_queue(|mpd| mpd.run("Failed to Load Playlist", |conn| conn.load(name)))
+    }
+
+    // Replaces the queue with the playlist's songs
+    pub fn replace_with_playlist(&mut self, name: &str) -> MpdResult<()> {
+        self.change_queue(|mpd| {
+            mpd.run("Failed to Clear Queue", |conn| conn.clear())?;
+            mpd.run("Failed to Load Playlist", |conn| conn.load(name))
+        })
+    }
+
+    pub fn save_playlist(&mut self, name: &str) -> MpdResult<()> {
+        self.run("Failed to Save Playlist", |conn| conn.save(name))
+    }
+
+    pub fn rename_playlist(&mut self, name: &str, new_name: &str) -> MpdResult<()> {
+        self.run("Failed to Rename Playlist", |conn| {
+            conn.pl_rename(name, new_name)
+        })
+    }
+
+    pub fn delete_playlist(&mut self, name: &str) -> MpdResult<()> {
+        self.run("Failed to Delete Playlist", |conn| conn.pl_remove(name))
+    }
+
+    pub fn add_to_playlist(
+        &mut self,
+        name: &str,
+        files: impl IntoIterator<Item = String>,
+    ) -> MpdResult<()> {
+        self.run_batch(
+            files
+                .into_iter()
+                .map(|file| Command::PlaylistAdd(name.to_owned(), file))
+                .collect(),
+        )
+    }
+
+    // Deletes tracks from a playlist, last position first so the others stay valid
+    pub fn delete_from_playlist(&mut self, name: &str, positions: &[u32]) -> MpdResult<()> {
+        let mut positions = positions.to_vec();
+        positions.sort_unstable_by(|a, b| b.cmp(a));
+        self.run_batch(
+            positions
+                .into_iter()
+                .map(|pos| Command::PlaylistDelete(name.to_owned(), pos))
+                .collect(),
+        )
+    }
+
+    pub fn move_in_playlist(&mut self, name: &str, moves: &[(u32, u32)]) -> MpdResult<()> {
+        self.run_batch(
+            moves
+                .iter()
+                .map(|(from, to)| Command::PlaylistMove(name.to_owned(), *from, *to))
+                .collect(),
+        )
+    }
+
+    // Has MPD rescan the given files or directories
+    pub fn update_paths(&mut self, paths: impl IntoIterator<Item = String>) -> MpdResult<()> {
+        self.run_batch(paths.into_iter().map(Command::Update).collect())
+    }
+
+    pub fn push_into_queue(&mut self, song: Song) -> MpdResult<()> {
+        self.change_queue(|mpd| {
+            mpd.run("Failed to Add to Queue", |conn| conn.push(&song.file))
+                .map(drop)
+        })
+    }
+
+    pub fn clear_queue(&mut self) -> MpdResult<()> {
+        self.change_queue(|mpd| mpd.run("Failed to Clear Queue", |conn| conn.clear()))
+    }
+
+    pub fn set_history_depth(&mut self, depth: usize) {
+        self.history.set_depth(depth);
+    }
+
+    // The queue as it was last fetched, unless the status has a newer version of it
+    fn queue_files(&mut self) -> MpdResult<Vec<String>> {
+        let version = self.status.as_ref().map(|status| status.queue_version);
+        match &self.queue {
+            Some((seen, files)) if Some(*seen) == version => Ok(files.clone()),
+            _ => self
+                .fetch_queue()
+                .map(|queue| queue.into_iter().map(|song| song.file).collect()),
+        }
+    }
+
+    // Runs a change to the queue, keeping the queue from before it so the change can
+    // be undone. A failed change is only kept if it got far enough to alter the queue,
+    // like a command list that stopped halfway, which moves the queue version on
+    fn change_queue<T>(&mut self, change: impl FnOnce(&mut Self) -> MpdResult<T>) -> MpdResult<T> {
+        let before = self.queue_files();
+        let version = self.queue.as_ref().map(|(version, _)| *version);
+        let result = change(self);
+        if let Ok(before) = before {
+            if result.is_ok() || self.queue_moved(version, &before) {
+                self.history.record(before);
+            }
+        }
+        // What was fetched is out of date until the next status shows the new version
+        if result.is_ok() {
+            self.queue = None;
+        }
+        result
+    }
+
+    // Whether the queue is no longer `before`, fetched again only when MPD's queue
+    // version moved on from `version`
+    fn queue_moved(&mut self, version: Option<u32>, before: &[String]) -> bool {
+        let now = self.run("Failed to get Status", |conn| conn.status());
+        match now {
+            Ok((status, _)) if Some(status.queue_version) == version => false,
+            Ok(_) => self
+                .fetch_queue()
+                .is_ok_and(|queue| queue.iter().map(|song| &song.file).ne(before)),
+            Err(_) => false,
         }
     }
 
-    pub fn stop_playback(&mut self) {
-        let _ = self.connection.stop();
+    // False when there was nothing to undo
+    pub fn undo(&mut self) -> MpdResult<bool> {
+        if !self.history.can_undo() {
+            return Ok(false);
+        }
+        let current = self.queue_files()?;
+        match self.history.undo(current) {
+            Some(previous) => self.restore_queue(&previous).map(|_| true),
+            None => Ok(false),
+        }
     }
 
-    pub fn delete_from_queue(&mut self, song_id: Id) {
-        let _ = self.connection.delete(song_id);
+    // False when there was nothing to redo
+    pub fn redo(&mut self) -> MpdResult<bool> {
+        if !self.history.can_redo() {
+            return Ok(false);
+        }
+        let current = self.queue_files()?;
+        match self.history.redo(current) {
+            Some(next) => self.restore_queue(&next).map(|_| true),
+            None => Ok(false),
+        }
     }
 
-    pub fn push_into_queue(&mut self, song: Song) {
-        let _ = self.connection.push(song);
+    // Turns the queue into `files` while keeping songs that are already queued
+    fn restore_queue(&mut self, files: &[String]) -> MpdResult<()> {
+        let result = self.rebuild_queue(files);
+        // What was fetched along the way is out of date now
+        self.queue = None;
+        result
     }
 
-    pub fn clear_queue(&mut self) {
-        let _ = self.connection.clear();
+    fn rebuild_queue(&mut self, files: &[String]) -> MpdResult<()> {
+        let queue = self.get_queue();
+        let mut used = vec![false; queue.len()];
+        let mut additions = vec![];
+        for file in files {
+            match (0..queue.len()).find(|idx| !used[*idx] && queue[*idx].file == *file) {
+                Some(idx) => used[idx] = true,
+                None => additions.push(Command::Add(file.clone())),
+            }
+        }
+        let mut commands = queue
+            .iter()
+            .zip(&used)
+            .filter(|(_, used)| !**used)
+            .filter_map(|(song, _)| song.place.map(|place| Command::DeleteId(place.id)))
+            .collect::<Vec<_>>();
+        commands.extend(additions);
+        self.run_batch(commands)?;
+
+        let queue = self.get_queue();
+        let mut used = vec![false; queue.len()];
+        let current = queue
+            .iter()
+            .filter_map(|song| song.place.map(|place| place.id))
+            .collect::<Vec<_>>();
+        let target = files
+            .iter()
+            .filter_map(|file| {
+                let idx = (0..queue.len()).find(|idx| !used[*idx] && queue[*idx].file == *file)?;
+                used[idx] = true;
+                queue[idx].place.map(|place| place.id)
+            })
+            .collect::<Vec<_>>();
+        self.run_batch(reorder_commands(&current, &target))
     }
 }
@@ -0,0 +1,239 @@
+use std::time::Duration;
+
+use crossterm::style::{Attribute, Color};
+use mpd::{song::QueuePlace, Song, Status};
+use unicode_width::UnicodeWidthStr;
+
+use super::{
+    command::Action,
+    config::Keymaps,
+    keymap::{key_name, keys_name},
+    list::ListState,
+    ui::{Overflow, Rect, Render},
+};
+
+pub enum Line {
+    Section(String),
+    // A name in the left column and what it stands for in the right one
+    Entry(String, String),
+}
+
+// A scrollable sheet drawn over the views, like the `?` help or song details
+pub struct Overlay {
+    pub title: String,
+    pub lines: Vec<Line>,
+    pub list: ListState,
+}
+
+fn title(name: &str) -> &str {
+    match name {
+        "normal" => "All views",
+        "queue" => "Queue",
+        "library" => "Library",
+        "playlists" => "Playlists",
+        "lyrics" => "Lyrics",
+        "tags" => "Tag editor",
+        "modes" => "Playback modes menu",
+        "search" => "Search prompt",
+        "command" => "Command line",
+        "popup" => "Popups",
+        name => name,
+    }
+}
+
+fn clock(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    match secs / 3600 {
+        0 => format!("{}:{:02}", secs / 60, secs % 60),
+        hours => format!("{hours}:{:02}:{:02}", secs / 60 % 60, secs % 60),
+    }
+}
+
+impl Overlay {
+    pub fn new(title: &str, lines: Vec<Line>) -> Self {
+        Self {
+            title: title.to_owned(),
+            lines,
+            list: ListState::default(),
+        }
+    }
+
+    // Every action with the keys bound to it in each keymap. Built from the keymaps
+    // as they are now, so bindings made with `:bind` show up
+    pub fn help(keymaps: &Keymaps) -> Self {
+        let mut lines = vec![Line::Section(format!(
+            "Leader is {}",
+            key_name(&keymaps.leader)
+        ))];
+        let mut bound = vec![];
+        for (name, map) in keymaps.named() {
+            // Keys of each action, in the order the actions were first bound
+            let mut actions: Vec<(Action, Vec<String>)> = vec![];
+            for binding in &map.0 {
+                let keys = keys_name(&binding.keys);
+                match actions
+                    .iter_mut()
+                    .find(|(action, _)| *action == binding.action)
+                {
+                    Some((_, all)) => all.push(keys),
+                    None => actions.push((binding.action, vec![keys])),
+                }
+            }
+            if actions.is_empty() {
+                continue;
+            }
+            lines.push(Line::Section(title(name).to_owned()));
+            for (action, keys) in actions {
+                bound.push(action);
+                lines.push(Line::Entry(keys.join(" "), action.name().to_owned()));
+            }
+        }
+
+        let unbound: Vec<_> = Action::ALL
+            .iter()
+            .filter(|(_, action)| !bound.contains(action))
+            .collect();
+        if !unbound.is_empty() {
+            lines.push(Line::Section("Unbound, run with :<name>".to_owned()));
+            for (name, _) in unbound {
+                lines.push(Line::Entry(String::new(), (*name).to_owned()));
+            }
+        }
+        Self::new("Help", lines)
+    }
+
+    // Everything MPD knows about `song`: its file, its place in the queue, and every
+    // tag as reported. `place` is where the song sits in the queue, if anywhere, and
+    // the status adds what is only known while it plays
+    pub fn song_info(song: &Song, place: Option<QueuePlace>, status: Option<&Status>) -> Self {
+        let entry = |name: &str, value: String| Line::Entry(name.to_owned(), value);
+        let mut lines = vec![Line::Section("File".to_owned())];
+        lines.push(entry("path", song.file.clone()));
+        if let Some(duration) = song.duration {
+            lines.push(entry("duration", clock(duration)));
+        }
+        if let Some(modified) = &song.last_mod {
+            lines.push(entry("last modified", modified.clone()));
+        }
+        if let Some((_, format)) = song.tags.iter().find(|(name, _)| name == "Format") {
+            lines.push(entry("format", format.clone()));
+        }
+
+        let playing = status.filter(|status| {
+            place.is_some_and(|place| status.song.is_some_and(|song| song.id == place.id))
+        });
+        if let Some(status) = playing {
+            lines.push(Line::Section("Playing".to_owned()));
+            if let Some(audio) = status.audio {
+                let bits = match audio.bits {
+                    0 => "float".to_owned(),
+                    bits => format!("{bits} bit"),
+                };
+                lines.push(entry(
+                    "output",
+                    format!("{} Hz, {bits}, {} channels", audio.rate, audio.chans),
+                ));
+            }
+            if let Some(bitrate) = status.bitrate {
+                lines.push(entry("bitrate", format!("{bitrate} kbps")));
+            }
+        }
+
+        if let Some(place) = place {
+            lines.push(Line::Section("Queue".to_owned()));
+            let position = match status {
+                Some(status) => format!("{} of {}", place.pos + 1, status.queue_len),
+                None => (place.pos + 1).to_string(),
+            };
+            lines.push(entry("position", position));
+            lines.push(entry("id", place.id.0.to_string()));
+            lines.push(entry("priority", place.prio.to_string()));
+        }
+
+        lines.push(Line::Section("Tags".to_owned()));
+        let known = [
+            ("Title", &song.title),
+            ("Artist", &song.artist),
+            ("Name", &song.name),
+        ];
+        for (name, value) in known {
+            if let Some(value) = value {
+                lines.push(entry(name, value.clone()));
+            }
+        }
+        for (name, value) in &song.tags {
+            if name != "Format" {
+                lines.push(entry(name, value.clone()));
+            }
+        }
+        Self::new("Song info", lines)
+    }
+
+    pub fn draw<R: Render>(&mut self, ct: &mut R, screen: Rect) {
+        let rect = screen.centered(screen.width.min(60), screen.height.saturating_sub(2));
+        let inner = rect.inner();
+
+        ct.set_background(rect, Color::Reset);
+        ct.set_foreground(rect, Color::Reset);
+        ct.set_attributes(rect, Attribute::NormalIntensity);
+        ct.set_text(rect, "", Overflow::Char);
+        ct.draw_border(rect);
+        let title = format!(" {} ", self.title);
+        let title_rect = Rect {
+            x: rect.x + 2,
+            width: (title.width() as u32).min(rect.width.saturating_sub(4)),
+            height: 1,
+            ..rect
+        };
+        ct.set_attributes(title_rect, Attribute::Bold);
+        ct.set_text(title_rect, title.as_str(), Overflow::Char);
+        ct.set_attributes(rect.inner(), Attribute::NormalIntensity);
+
+        let height = inner.height as usize;
+        let offset = self.list.scroll(height);
+        let width = self
+            .lines
+            .iter()
+            .map(|line| match line {
+                Line::Entry(name, _) => name.width(),
+                Line::Section(_) => 0,
+            })
+            .max()
+            .unwrap_or_default() as u32
+            + 2;
+        for (row, (pos, line)) in self
+            .lines
+            .iter()
+            .enumerate()
+            .skip(offset)
+            .take(height)
+            .enumerate()
+        {
+            let line_rect = Rect {
+                y: inner.y + row as u32,
+                height: 1,
+                ..inner
+            };
+            if pos == self.list.cursor {
+                ct.set_background(line_rect, Color::DarkGrey);
+            }
+            match line {
+                Line::Section(title) => {
+                    ct.set_attributes(line_rect, Attribute::Bold);
+                    ct.set_text(line_rect, title.as_str(), Overflow::Char);
+                }
+                Line::Entry(name, value) => {
+                    ct.set_foreground(line_rect, Color::Yellow);
+                    ct.set_text(line_rect, name.as_str(), Overflow::Char);
+                    let value_rect = Rect {
+                        x: line_rect.x + width.min(line_rect.width / 2),
+                        width: line_rect.width - width.min(line_rect.width / 2),
+                        ..line_rect
+                    };
+                    ct.set_foreground(value_rect, Color::Reset);
+                    ct.set_text(value_rect, value.as_str(), Overflow::Char);
+                }
+            }
+        }
+    }
+}
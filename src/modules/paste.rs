@@ -0,0 +1,83 @@
+use std::path::{Component, Path};
+
+use super::overlay::{Line, Overlay};
+
+// What a pasted line names for MPD: URLs as they are, and local files, given by
+// path or `file://` URL, relative to the music directory. None for anything else,
+// like prose or relative paths, which MPD would only fail to add
+pub fn uri(line: &str, music_dir: Option<&Path>) -> Option<String> {
+    let path = match line.split_once("://") {
+        Some(("file", path)) => decode(path)?,
+        Some((scheme, _)) if is_scheme(scheme) => return Some(line.to_owned()),
+        _ => line.to_owned(),
+    };
+    let path = Path::new(&path);
+    if !path.is_absolute() {
+        return None;
+    }
+    let relative = path.strip_prefix(music_dir?).ok()?;
+    let parts = relative
+        .components()
+        .map(|part| match part {
+            Component::Normal(part) => part.to_str(),
+            _ => None,
+        })
+        .collect::<Option<Vec<_>>>()?;
+    if parts.is_empty() {
+        return None;
+    }
+    Some(parts.join("/"))
+}
+
+// The URIs in pasted `text`, one per line, and the lines that were left out
+pub fn uris(text: &str, music_dir: Option<&Path>) -> (Vec<String>, Vec<String>) {
+    let mut uris = vec![];
+    let mut rejected = vec![];
+    for line in text.lines().map(str::trim).filter(|line| !line.is_empty()) {
+        match uri(line, music_dir) {
+            Some(uri) => uris.push(uri),
+            None => rejected.push(line.to_owned()),
+        }
+    }
+    (uris, rejected)
+}
+
+// Lists what a paste adds to the queue, and what it skips
+pub fn preview(uris: &[String], rejected: &[String]) -> Overlay {
+    let entry = |line: &String| Line::Entry(String::new(), line.clone());
+    let mut lines = vec![Line::Section("Adding".to_owned())];
+    lines.extend(uris.iter().map(entry));
+    if !rejected.is_empty() {
+        lines.push(Line::Section(
+            "Skipped, not a URL or a file in the music directory".to_owned(),
+        ));
+        lines.extend(rejected.iter().map(entry));
+    }
+    Overlay::new("Paste", lines)
+}
+
+// RFC 3986: a letter, then letters, digits, `+`, `-` or `.`
+fn is_scheme(scheme: &str) -> bool {
+    scheme.starts_with(|char: char| char.is_ascii_alphabetic())
+        && scheme
+            .chars()
+            .all(|char| char.is_ascii_alphanumeric() || "+-.".contains(char))
+}
+
+// Undoes the `%XX` escapes of a file URL. A `file://host/path` keeps its host,
+// which then fails to be under the music directory
+fn decode(path: &str) -> Option<String> {
+    let mut bytes = vec![];
+    let mut rest = path.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        if byte == b'%' {
+            let hex = std::str::from_utf8(tail.get(..2)?).ok()?;
+            bytes.push(u8::from_str_radix(hex, 16).ok()?);
+            rest = &tail[2..];
+        } else {
+            bytes.push(byte);
+            rest = tail;
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
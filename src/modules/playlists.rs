@@ -0,0 +1,186 @@
+use crossterm::style::{Attribute, Color};
+use mpd::{Playlist, Song};
+
+use super::{
+    list::{draw_songs, ListState},
+    mpd::{Backend, Mpd, MpdResult},
+    ui::{Overflow, Rect, Render},
+};
+
+// Contents of the playlist that is currently opened for editing
+pub struct Tracks {
+    pub name: String,
+    pub songs: Vec<Song>,
+    pub list: ListState,
+}
+
+#[derive(Default)]
+pub struct Playlists {
+    pub playlists: Vec<(Playlist, usize)>,
+    pub list: ListState,
+    pub tracks: Option<Tracks>,
+    loaded: bool,
+}
+
+impl Playlists {
+    pub fn sync<B: Backend>(&mut self, mpd: &mut Mpd<B>) {
+        if !mpd.is_connected() {
+            self.loaded = false;
+        } else if !self.loaded {
+            self.playlists = mpd.get_playlists();
+            self.playlists.sort_by(|a, b| a.0.name.cmp(&b.0.name));
+            self.list.clamp(self.playlists.len());
+            if let Some(tracks) = &mut self.tracks {
+                tracks.songs = mpd.get_playlist(&tracks.name);
+                tracks.list.clamp(tracks.songs.len());
+            }
+            self.loaded = true;
+        }
+    }
+
+    pub fn reload(&mut self) {
+        self.loaded = false;
+    }
+
+    pub fn selected_name(&self) -> Option<&str> {
+        match &self.tracks {
+            Some(tracks) => Some(tracks.name.as_str()),
+            None => self
+                .playlists
+                .get(self.list.cursor)
+                .map(|(playlist, _)| playlist.name.as_str()),
+        }
+    }
+
+    // The list that cursor movement and selection apply to
+    pub fn active_list(&mut self) -> (&mut ListState, usize) {
+        match &mut self.tracks {
+            Some(tracks) => (&mut tracks.list, tracks.songs.len()),
+            None => (&mut self.list, self.playlists.len()),
+        }
+    }
+
+    pub fn cursor_up(&mut self) {
+        self.active_list().0.up();
+    }
+
+    pub fn cursor_down(&mut self) {
+        let (list, len) = self.active_list();
+        list.down(len);
+    }
+
+    pub fn open_selected<B: Backend>(&mut self, mpd: &mut Mpd<B>) {
+        if self.tracks.is_some() {
+            return;
+        }
+        if let Some(name) = self.selected_name().map(str::to_owned) {
+            let songs = mpd.get_playlist(&name);
+            self.tracks = Some(Tracks {
+                name,
+                songs,
+                list: ListState::default(),
+            });
+        }
+    }
+
+    pub fn close(&mut self) {
+        self.tracks = None;
+    }
+
+    pub fn delete_tracks<B: Backend>(&mut self, mpd: &mut Mpd<B>) -> MpdResult<()> {
+        let Some(tracks) = &mut self.tracks else {
+            return Ok(());
+        };
+        let positions = tracks
+            .list
+            .targets(tracks.songs.len())
+            .into_iter()
+            .map(|idx| idx as u32)
+            .collect::<Vec<_>>();
+        mpd.delete_from_playlist(&tracks.name, &positions)?;
+        tracks.list.clear_selection();
+        self.reload();
+        Ok(())
+    }
+
+    // Shifts the targeted tracks one step, like the queue does
+    pub fn shift_tracks<B: Backend>(&mut self, mpd: &mut Mpd<B>, up: bool) -> MpdResult<()> {
+        let Some(tracks) = &mut self.tracks else {
+            return Ok(());
+        };
+        let mut targets = tracks.list.targets(tracks.songs.len());
+        let blocked = if up {
+            targets.first() == Some(&0)
+        } else {
+            targets.last() == Some(&tracks.songs.len().saturating_sub(1))
+        };
+        if targets.is_empty() || blocked {
+            return Ok(());
+        }
+        if !up {
+            targets.reverse();
+        }
+        let shift = |idx: usize| if up { idx - 1 } else { idx + 1 };
+        let moves = targets
+            .iter()
+            .map(|idx| (*idx as u32, shift(*idx) as u32))
+            .collect::<Vec<_>>();
+        mpd.move_in_playlist(&tracks.name, &moves)?;
+
+        if !tracks.list.selection().is_empty() {
+            tracks.list.set_selection(targets.into_iter().map(shift));
+        }
+        tracks.list.cursor = shift(tracks.list.cursor);
+        self.reload();
+        Ok(())
+    }
+
+    pub fn draw<R: Render>(&mut self, ct: &mut R, rect: Rect) {
+        if let Some(tracks) = &mut self.tracks {
+            draw_songs(ct, rect, &tracks.songs, &mut tracks.list, None);
+            return;
+        }
+
+        let height = rect.height as usize;
+        let offset = self.list.scroll(height);
+        ct.set_background(rect, Color::Reset);
+        ct.set_foreground(rect, Color::Reset);
+        ct.set_attributes(rect, Attribute::NormalIntensity);
+        ct.set_text(rect, "", Overflow::Char);
+        for (row, (pos, (playlist, count))) in self
+            .playlists
+            .iter()
+            .enumerate()
+            .skip(offset)
+            .take(height)
+            .enumerate()
+        {
+            let line = Rect {
+                y: rect.y + row as u32,
+                height: 1,
+                ..rect
+            };
+            if pos == self.list.cursor {
+                ct.set_background(line, Color::DarkGrey);
+            }
+            ct.set_text(
+                Rect {
+                    x: line.x + 1,
+                    width: line.width.saturating_sub(2),
+                    ..line
+                },
+                format!("{} ({count} tracks)", playlist.name).as_str(),
+                Overflow::Char,
+            );
+            ct.set_text(
+                Rect {
+                    x: (line.x + line.width).saturating_sub(playlist.last_mod.len() as u32 + 1),
+                    width: playlist.last_mod.len() as u32,
+                    ..line
+                },
+                playlist.last_mod.as_str(),
+                Overflow::Char,
+            );
+        }
+    }
+}
@@ -0,0 +1,125 @@
+use crossterm::style::{Attribute, Color};
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+use super::ui::{Overflow, Rect, Render};
+
+pub enum PopupKind {
+    Input(String),
+    Confirm,
+    Message(String),
+}
+
+// A modal box drawn over the frame that takes every key until it is answered,
+// carrying the action to run once it is submitted
+pub struct Popup<A> {
+    pub title: String,
+    pub kind: PopupKind,
+    pub action: A,
+}
+
+impl<A> Popup<A> {
+    pub fn input(title: impl Into<String>, text: impl Into<String>, action: A) -> Self {
+        Self {
+            title: title.into(),
+            kind: PopupKind::Input(text.into()),
+            action,
+        }
+    }
+
+    pub fn confirm(title: impl Into<String>, action: A) -> Self {
+        Self {
+            title: title.into(),
+            kind: PopupKind::Confirm,
+            action,
+        }
+    }
+
+    pub fn message(title: impl Into<String>, body: impl Into<String>, action: A) -> Self {
+        Self {
+            title: title.into(),
+            kind: PopupKind::Message(body.into()),
+            action,
+        }
+    }
+
+    // Typing only goes to input popups
+    pub fn insert(&mut self, char: char) -> bool {
+        match &mut self.kind {
+            PopupKind::Input(text) => {
+                text.push(char);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub fn delete_char(&mut self) -> bool {
+        match &mut self.kind {
+            PopupKind::Input(text) => {
+                text.pop();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub fn text(&self) -> &str {
+        match &self.kind {
+            PopupKind::Input(text) => text.trim(),
+            _ => "",
+        }
+    }
+
+    pub fn draw<R: Render>(&self, ct: &mut R, screen: Rect) {
+        let (body, hint) = match &self.kind {
+            PopupKind::Input(text) => (format!("{text}_"), "<Enter> confirm  <Esc> cancel"),
+            PopupKind::Confirm => (String::new(), "[y]es  [n]o"),
+            PopupKind::Message(body) => (body.clone(), "<Enter> close"),
+        };
+        let width = [self.title.width(), body.width(), hint.width()]
+            .into_iter()
+            .max()
+            .unwrap_or_default() as u32
+            + 4;
+        let rect = screen.centered(width.max(40), 5);
+        let inner = rect.inner();
+
+        ct.set_background(rect, Color::Reset);
+        ct.set_foreground(rect, Color::Reset);
+        ct.set_attributes(rect, Attribute::NormalIntensity);
+        ct.set_text(rect, "", Overflow::Char);
+        ct.draw_border(rect);
+
+        let line = |y: u32| Rect {
+            y: inner.y + y,
+            height: 1,
+            ..inner
+        };
+        ct.set_attributes(line(0), Attribute::Bold);
+        ct.set_text(line(0), self.title.as_str(), Overflow::Char);
+        // Input too long for the box keeps its end, where the typing is, in view
+        let body = match self.kind {
+            PopupKind::Input(_) => tail(&body, inner.width as usize),
+            _ => body.as_str(),
+        };
+        ct.set_text(line(1), body, Overflow::Char);
+        ct.set_foreground(line(2), Color::DarkGrey);
+        ct.set_text(line(2), hint, Overflow::Char);
+    }
+}
+
+// The end of `text` that fits in `width` cells
+fn tail(text: &str, width: usize) -> &str {
+    let mut used = 0;
+    let start = text
+        .grapheme_indices(true)
+        .rev()
+        .take_while(|(_, grapheme)| {
+            used += grapheme.width();
+            used <= width
+        })
+        .last()
+        .map_or(text.len(), |(idx, _)| idx);
+    &text[start..]
+}
@@ -0,0 +1,139 @@
+use std::{cmp::Ordering, ops::Range, time::Duration};
+
+use crossterm::style::{Attribute, Color};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    config::Progress,
+    ui::{Overflow, Rect, Render},
+};
+
+// Left blocks from one eighth of a cell up to seven, for the edge of the played part
+const EIGHTHS: [char; 7] = ['▏', '▎', '▍', '▌', '▋', '▊', '▉'];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TimeFormat {
+    Elapsed,
+    Remaining,
+    ElapsedTotal,
+    Percent,
+}
+
+// `m:ss`, or `h:mm:ss` when `long`
+fn clock(time: Duration, long: bool) -> String {
+    let secs = time.as_secs();
+    if long {
+        format!("{}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+    } else {
+        format!("{}:{:02}", secs / 60, secs % 60)
+    }
+}
+
+// Streams have no length, so they only ever show the elapsed time
+pub fn time_label(format: TimeFormat, elapsed: Duration, total: Option<Duration>) -> String {
+    let total = total.filter(|total| !total.is_zero());
+    // Both sides get hours once the track is that long, so they line up
+    let long = total.unwrap_or(elapsed) >= Duration::from_secs(3600);
+    match (format, total) {
+        (TimeFormat::Remaining, Some(total)) => {
+            format!("-{}", clock(total.saturating_sub(elapsed), long))
+        }
+        (TimeFormat::ElapsedTotal, Some(total)) => {
+            format!("{}/{}", clock(elapsed, long), clock(total, long))
+        }
+        (TimeFormat::Percent, Some(total)) => format!(
+            "{}%",
+            (elapsed.as_secs_f64() / total.as_secs_f64() * 100.0).min(100.0) as u32
+        ),
+        _ => clock(elapsed, long),
+    }
+}
+
+// The bar's glyphs for `width` cells, and which of them are the played part.
+// Without a length a short segment bounces along the bar instead
+pub fn bar(
+    conf: &Progress,
+    width: usize,
+    elapsed: Duration,
+    total: Option<Duration>,
+) -> (String, Range<usize>) {
+    let Some(total) = total.filter(|total| !total.is_zero()) else {
+        let len = (width / 6).max(1).min(width);
+        let travel = width - len;
+        let step = elapsed.as_secs() as usize % (travel * 2).max(1);
+        let start = if step > travel {
+            travel * 2 - step
+        } else {
+            step
+        };
+        let text = (0..width)
+            .map(|x| {
+                if (start..start + len).contains(&x) {
+                    conf.fill
+                } else {
+                    conf.empty
+                }
+            })
+            .collect();
+        return (text, start..start + len);
+    };
+    let ratio = (elapsed.as_secs_f64() / total.as_secs_f64()).clamp(0.0, 1.0);
+    let (full, partial) = if conf.smooth {
+        let eighths = (ratio * width as f64 * 8.0) as usize;
+        (eighths / 8, eighths % 8)
+    } else {
+        ((ratio * width as f64).round() as usize, 0)
+    };
+    let mut text = String::new();
+    for x in 0..width {
+        text.push(match x.cmp(&full) {
+            Ordering::Less => conf.fill,
+            Ordering::Equal if partial > 0 => EIGHTHS[partial - 1],
+            _ => conf.empty,
+        });
+    }
+    let played = full + usize::from(partial > 0);
+    (text, 0..played.min(width))
+}
+
+// The bar across `rect` with the time on its right
+pub fn draw<R: Render>(
+    ct: &mut R,
+    rect: Rect,
+    conf: &Progress,
+    elapsed: Duration,
+    total: Option<Duration>,
+) {
+    ct.set_background(rect, Color::Reset);
+    ct.set_foreground(rect, Color::Reset);
+    ct.set_attributes(rect, Attribute::NormalIntensity);
+    ct.set_text(rect, "", Overflow::Char);
+
+    let label = time_label(conf.time, elapsed, total);
+    let label_width = label.chars().count() as u32;
+    if label_width < rect.width {
+        let label_rect = Rect {
+            x: rect.x + rect.width - label_width,
+            width: label_width,
+            ..rect
+        };
+        ct.set_text(label_rect, label.as_str(), Overflow::Char);
+    }
+    let width = rect.width.saturating_sub(label_width + 2);
+    if width == 0 {
+        return;
+    }
+    let (text, played) = bar(conf, width as usize, elapsed, total);
+    let bar_rect = Rect { width, ..rect };
+    ct.set_foreground(bar_rect, conf.empty_color);
+    ct.set_foreground(
+        Rect {
+            x: rect.x + played.start as u32,
+            width: played.len() as u32,
+            ..rect
+        },
+        conf.fill_color,
+    );
+    ct.set_text(bar_rect, text.as_str(), Overflow::Char);
+}
@@ -0,0 +1,240 @@
+use mpd::{Id, Song};
+
+use super::{
+    list::{album, draw_songs, ListState},
+    mpd::{Backend, Mpd, MpdError, MpdResult},
+    ui::{Rect, Render},
+};
+
+#[derive(Default)]
+pub struct Queue {
+    pub songs: Vec<Song>,
+    pub list: ListState,
+    version: Option<u32>,
+}
+
+impl Queue {
+    // Refetches the queue whenever MPD reports a new queue version
+    pub fn sync<B: Backend>(&mut self, mpd: &mut Mpd<B>) {
+        let version = mpd.get_status().map(|status| status.queue_version);
+        if version != self.version {
+            let songs = if version.is_some() {
+                mpd.get_queue()
+            } else {
+                vec![]
+            };
+            // Other clients may have moved or deleted songs, so marks go by song id
+            self.list.remap(&song_ids(&self.songs), &song_ids(&songs));
+            self.songs = songs;
+            self.version = version;
+        }
+    }
+
+    pub fn selected(&self) -> Option<&Song> {
+        self.songs.get(self.list.cursor)
+    }
+
+    fn ids(&self) -> Vec<Id> {
+        self.songs
+            .iter()
+            .filter_map(|song| song.place.map(|place| place.id))
+            .collect()
+    }
+
+    fn target_ids(&self) -> Vec<Id> {
+        self.list
+            .targets(self.songs.len())
+            .into_iter()
+            .filter_map(|idx| self.songs[idx].place.map(|place| place.id))
+            .collect()
+    }
+
+    pub fn selected_files(&self) -> Vec<String> {
+        self.list
+            .targets(self.songs.len())
+            .into_iter()
+            .map(|idx| self.songs[idx].file.clone())
+            .collect()
+    }
+
+    pub fn cursor_up(&mut self) {
+        self.list.up();
+    }
+
+    pub fn cursor_down(&mut self) {
+        self.list.down(self.songs.len());
+    }
+
+    pub fn select_album(&mut self) {
+        let Some(current) = self.selected().and_then(album).map(str::to_owned) else {
+            return;
+        };
+        let songs = &self.songs;
+        self.list.select_where(songs.len(), |idx| {
+            album(&songs[idx]) == Some(current.as_str())
+        });
+    }
+
+    pub fn play_selected<B: Backend>(&mut self, mpd: &mut Mpd<B>) -> MpdResult<()> {
+        if self.selected().is_none() {
+            return Ok(());
+        }
+        mpd.play_position(self.list.cursor as u32)
+    }
+
+    pub fn delete_selected<B: Backend>(&mut self, mpd: &mut Mpd<B>) -> MpdResult<()> {
+        mpd.delete_ids(self.target_ids())?;
+        self.list.clear_selection();
+        Ok(())
+    }
+
+    // Moves the targeted songs as one block so that it starts at `to`
+    pub fn move_selected<B: Backend>(&mut self, mpd: &mut Mpd<B>, to: usize) -> MpdResult<()> {
+        let targets = self.list.targets(self.songs.len());
+        if targets.is_empty() {
+            return Ok(());
+        }
+        let ids = self.ids();
+        let (mut moved, mut rest): (Vec<_>, Vec<_>) = ids
+            .iter()
+            .enumerate()
+            .partition(|(idx, _)| targets.contains(idx));
+        let to = to.min(rest.len());
+        rest.splice(to..to, moved.drain(..));
+        let target = rest.into_iter().map(|(_, id)| *id).collect::<Vec<_>>();
+        mpd.reorder_queue(&ids, &target)?;
+
+        self.list.cursor = to;
+        // The marks go along with their songs once the queue is fetched again
+        self.list.set_selection(self.list.selection());
+        Ok(())
+    }
+
+    // Shifts every targeted song one step, keeping gaps between them
+    fn shift_selected<B: Backend>(&mut self, mpd: &mut Mpd<B>, up: bool) -> MpdResult<()> {
+        let targets = self.list.targets(self.songs.len());
+        let ids = self.ids();
+        let blocked = if up {
+            targets.first() == Some(&0)
+        } else {
+            targets.last() == Some(&(ids.len().saturating_sub(1)))
+        };
+        if targets.is_empty() || blocked {
+            return Ok(());
+        }
+        let mut target = ids.clone();
+        let mut order = targets.clone();
+        if !up {
+            order.reverse();
+        }
+        for idx in order {
+            let other = if up { idx - 1 } else { idx + 1 };
+            target.swap(idx, other);
+        }
+        mpd.reorder_queue(&ids, &target)?;
+
+        self.list.set_selection(self.list.selection());
+        self.list.cursor = if up {
+            self.list.cursor.saturating_sub(1)
+        } else {
+            self.list.cursor + 1
+        };
+        Ok(())
+    }
+
+    pub fn move_up<B: Backend>(&mut self, mpd: &mut Mpd<B>) -> MpdResult<()> {
+        self.shift_selected(mpd, true)
+    }
+
+    pub fn move_down<B: Backend>(&mut self, mpd: &mut Mpd<B>) -> MpdResult<()> {
+        self.shift_selected(mpd, false)
+    }
+
+    pub fn move_after_current<B: Backend>(&mut self, mpd: &mut Mpd<B>) -> MpdResult<()> {
+        let current = mpd
+            .get_status()
+            .and_then(|status| status.song)
+            .ok_or(MpdError::NotPlaying)?;
+        let current = current.pos as usize;
+        let targets = self.list.targets(self.songs.len());
+        if targets.contains(&current) {
+            return Ok(());
+        }
+        // Position of the playing song once the targets are taken out
+        let before = targets.iter().filter(|idx| **idx < current).count();
+        self.move_selected(mpd, current - before + 1)
+    }
+
+    // Shuffles the songs from the first to the last target, returning how many
+    pub fn shuffle_selected<B: Backend>(&mut self, mpd: &mut Mpd<B>) -> MpdResult<usize> {
+        let targets = self.list.targets(self.songs.len());
+        let (Some(first), Some(last)) = (targets.first(), targets.last()) else {
+            return Ok(0);
+        };
+        if first == last {
+            return Ok(0);
+        }
+        mpd.shuffle_queue(Some(*first as u32..*last as u32 + 1))?;
+        self.list.clear_selection();
+        Ok(last - first + 1)
+    }
+
+    // Shuffles what plays after the current song, or everything when nothing plays
+    pub fn shuffle_rest<B: Backend>(&mut self, mpd: &mut Mpd<B>) -> MpdResult<usize> {
+        let start = mpd
+            .get_status()
+            .and_then(|status| status.song)
+            .map_or(0, |place| place.pos as usize + 1);
+        let len = self.songs.len();
+        if start + 1 >= len {
+            return Ok(0);
+        }
+        mpd.shuffle_queue(Some(start as u32..len as u32))?;
+        Ok(len - start)
+    }
+
+    pub fn crop_to_selected<B: Backend>(&mut self, mpd: &mut Mpd<B>) -> MpdResult<()> {
+        let targets = self.list.targets(self.songs.len());
+        if targets.is_empty() {
+            return Ok(());
+        }
+        let others = self
+            .songs
+            .iter()
+            .enumerate()
+            .filter(|(idx, _)| !targets.contains(idx))
+            .filter_map(|(_, song)| song.place.map(|place| place.id));
+        mpd.delete_ids(others.collect::<Vec<_>>())?;
+        self.list.clear_selection();
+        self.list.cursor = 0;
+        Ok(())
+    }
+
+    // Gives the targeted songs the highest priority, or resets it if they all have it
+    pub fn toggle_priority<B: Backend>(&mut self, mpd: &mut Mpd<B>) -> MpdResult<()> {
+        let targets = self.list.targets(self.songs.len());
+        let prioritized = targets
+            .iter()
+            .all(|idx| self.songs[*idx].place.is_some_and(|place| place.prio > 0));
+        mpd.set_priority(self.target_ids(), if prioritized { 0 } else { u8::MAX })?;
+        self.list.clear_selection();
+        Ok(())
+    }
+
+    pub fn draw<R: Render>(&mut self, ct: &mut R, rect: Rect, playing: Option<u32>) {
+        draw_songs(
+            ct,
+            rect,
+            &self.songs,
+            &mut self.list,
+            playing.map(|pos| pos as usize),
+        );
+    }
+}
+
+fn song_ids(songs: &[Song]) -> Vec<Option<Id>> {
+    songs
+        .iter()
+        .map(|song| song.place.map(|place| place.id))
+        .collect()
+}
@@ -0,0 +1,53 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+use anyhow::Result;
+use signal_hook::{
+    consts::{SIGCONT, SIGHUP, SIGINT, SIGTERM, SIGTSTP},
+    flag, low_level,
+};
+
+// Signals caught for the main loop to act on between frames, since the terminal
+// can't be touched safely from the handlers themselves
+pub struct Signals {
+    terminate: Arc<AtomicBool>,
+    suspend: Arc<AtomicBool>,
+    resume: Arc<AtomicBool>,
+}
+
+impl Signals {
+    pub fn register() -> Result<Self> {
+        let signals = Self {
+            terminate: Arc::new(AtomicBool::new(false)),
+            suspend: Arc::new(AtomicBool::new(false)),
+            resume: Arc::new(AtomicBool::new(false)),
+        };
+        for signal in [SIGTERM, SIGINT, SIGHUP] {
+            flag::register(signal, Arc::clone(&signals.terminate))?;
+        }
+        flag::register(SIGTSTP, Arc::clone(&signals.suspend))?;
+        flag::register(SIGCONT, Arc::clone(&signals.resume))?;
+        Ok(signals)
+    }
+
+    pub fn terminated(&self) -> bool {
+        self.terminate.load(Ordering::Relaxed)
+    }
+
+    pub fn take_suspend(&self) -> bool {
+        self.suspend.swap(false, Ordering::Relaxed)
+    }
+
+    pub fn take_resume(&self) -> bool {
+        self.resume.swap(false, Ordering::Relaxed)
+    }
+}
+
+// Stops the process the way Ctrl-Z does in a shell, returning once it is continued.
+// The terminal should be restored first
+pub fn stop() -> Result<()> {
+    low_level::emulate_default_handler(SIGTSTP)?;
+    Ok(())
+}
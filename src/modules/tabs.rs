@@ -0,0 +1,85 @@
+use crossterm::style::{Attribute, Color};
+use unicode_width::UnicodeWidthStr;
+
+use super::{
+    app::View,
+    ui::{Overflow, Rect, Render},
+};
+
+// The views the tab bar starts out with, in order
+pub const DEFAULT: [View; 4] = [View::Queue, View::Library, View::Playlists, View::Lyrics];
+
+// Drops repeated views, and falls back to the defaults when none are left
+pub fn normalize(tabs: &[View]) -> Vec<View> {
+    let mut unique = vec![];
+    for view in tabs {
+        if !unique.contains(view) {
+            unique.push(*view);
+        }
+    }
+    if unique.is_empty() {
+        return DEFAULT.to_vec();
+    }
+    unique
+}
+
+// The tab `step` places after `current`, wrapping around. A view without a tab
+// of its own, like lyrics opened by their key, goes on from the first one
+pub fn cycle(tabs: &[View], current: View, step: isize) -> Option<View> {
+    let len = tabs.len() as isize;
+    if len == 0 {
+        return None;
+    }
+    let pos = match tabs.iter().position(|view| *view == current) {
+        Some(pos) => pos as isize + step,
+        None if step > 0 => 0,
+        None => len - 1,
+    };
+    Some(tabs[pos.rem_euclid(len) as usize])
+}
+
+// Labels along the bar from `x`, numbered for the Alt keys that reach them
+pub fn layout(tabs: &[View], x: u32) -> Vec<(View, String, u32)> {
+    let mut x = x;
+    tabs.iter()
+        .enumerate()
+        .map(|(idx, view)| {
+            let label = match idx {
+                0..=8 => format!(" {} {} ", idx + 1, view.name()),
+                _ => format!(" {} ", view.name()),
+            };
+            let start = x;
+            x += label.width() as u32;
+            (*view, label, start)
+        })
+        .collect()
+}
+
+// The tab under `column` of the bar starting at `x`
+pub fn at(tabs: &[View], x: u32, column: u32) -> Option<View> {
+    layout(tabs, x)
+        .into_iter()
+        .find(|(_, label, start)| (*start..*start + label.width() as u32).contains(&column))
+        .map(|(view, _, _)| view)
+}
+
+// Draws the tabs from the left of `rect`, returning where they end
+pub fn draw<R: Render>(ct: &mut R, rect: Rect, tabs: &[View], current: View) -> u32 {
+    let mut end = rect.x;
+    for (view, label, x) in layout(tabs, rect.x) {
+        let width = (label.width() as u32).min((rect.x + rect.width).saturating_sub(x));
+        if width == 0 {
+            break;
+        }
+        let tab = Rect { x, width, ..rect };
+        if view == current {
+            ct.set_background(tab, Color::DarkGrey);
+            ct.set_attributes(tab, Attribute::Bold);
+        } else {
+            ct.set_foreground(tab, Color::Grey);
+        }
+        ct.set_text(tab, label.as_str(), Overflow::Char);
+        end = x + width;
+    }
+    end
+}
@@ -0,0 +1,261 @@
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+use lofty::{
+    config::{ParseOptions, WriteOptions},
+    file::{AudioFile, TaggedFileExt},
+    probe::Probe,
+    tag::{ItemKey, Tag},
+};
+use mpd::Song;
+
+use super::{
+    overlay::{Line, Overlay},
+    ui::{Rect, Render},
+};
+
+// Tags the editor can change
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Field {
+    Title,
+    Artist,
+    Album,
+    AlbumArtist,
+    Track,
+    Genre,
+    Date,
+}
+
+impl Field {
+    pub const ALL: [Field; 7] = [
+        Field::Title,
+        Field::Artist,
+        Field::Album,
+        Field::AlbumArtist,
+        Field::Track,
+        Field::Genre,
+        Field::Date,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Field::Title => "title",
+            Field::Artist => "artist",
+            Field::Album => "album",
+            Field::AlbumArtist => "album artist",
+            Field::Track => "track",
+            Field::Genre => "genre",
+            Field::Date => "date",
+        }
+    }
+
+    // Lofty maps these onto the frame, comment or atom of each format
+    fn key(self) -> ItemKey {
+        match self {
+            Field::Title => ItemKey::TrackTitle,
+            Field::Artist => ItemKey::TrackArtist,
+            Field::Album => ItemKey::AlbumTitle,
+            Field::AlbumArtist => ItemKey::AlbumArtist,
+            Field::Track => ItemKey::TrackNumber,
+            Field::Genre => ItemKey::Genre,
+            Field::Date => ItemKey::RecordingDate,
+        }
+    }
+
+    // The value as MPD reports it
+    pub fn value(self, song: &Song) -> Option<&str> {
+        let name = match self {
+            Field::Title => return song.title.as_deref(),
+            Field::Artist => return song.artist.as_deref(),
+            Field::Album => "Album",
+            Field::AlbumArtist => "AlbumArtist",
+            Field::Track => "Track",
+            Field::Genre => "Genre",
+            Field::Date => "Date",
+        };
+        song.tags
+            .iter()
+            .find(|(tag, _)| tag == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+// One tag of one file to rewrite, `new` being `None` removes it
+#[derive(Debug, Clone, PartialEq)]
+pub struct Change {
+    pub file: String,
+    pub field: Field,
+    pub old: Option<String>,
+    pub new: Option<String>,
+}
+
+// Edits the tags of one or more songs at once. Fields that were not touched keep
+// whatever each song has, so songs can differ in them
+pub struct TagEditor {
+    pub songs: Vec<Song>,
+    // New value per field in `Field::ALL` order, `None` keeps the old ones
+    edits: [Option<String>; 7],
+    pub sheet: Overlay,
+}
+
+impl TagEditor {
+    pub fn new(songs: Vec<Song>) -> Self {
+        let title = match songs.as_slice() {
+            [song] => format!("Edit tags of {}", song.file),
+            songs => format!("Edit tags of {} songs", songs.len()),
+        };
+        let mut editor = Self {
+            songs,
+            edits: Default::default(),
+            sheet: Overlay::new(&title, vec![]),
+        };
+        editor.refresh();
+        editor
+    }
+
+    fn index(field: Field) -> usize {
+        Field::ALL
+            .iter()
+            .position(|other| *other == field)
+            .unwrap_or_default()
+    }
+
+    pub fn selected_field(&self) -> Field {
+        Field::ALL[self.sheet.list.cursor.min(Field::ALL.len() - 1)]
+    }
+
+    // The value all songs share, which a new edit starts from
+    pub fn shared(&self, field: Field) -> Option<&str> {
+        let mut values = self.songs.iter().map(|song| field.value(song));
+        let first = values.next()?;
+        values
+            .all(|value| value == first)
+            .then_some(first)
+            .flatten()
+    }
+
+    // An empty value removes the tag
+    pub fn set(&mut self, field: Field, value: &str) {
+        self.edits[Self::index(field)] = Some(value.to_owned());
+        self.refresh();
+    }
+
+    pub fn keep(&mut self, field: Field) {
+        self.edits[Self::index(field)] = None;
+        self.refresh();
+    }
+
+    fn refresh(&mut self) {
+        let lines = Field::ALL
+            .iter()
+            .map(|field| {
+                let value = match &self.edits[Self::index(*field)] {
+                    Some(value) if value.is_empty() => "(removed)".to_owned(),
+                    Some(value) => value.clone(),
+                    None => match self.shared(*field) {
+                        Some(value) => format!("{value} (keep)"),
+                        None if self.songs.len() > 1 => "(keep each)".to_owned(),
+                        None => "(keep)".to_owned(),
+                    },
+                };
+                Line::Entry(field.label().to_owned(), value)
+            })
+            .collect();
+        self.sheet.lines = lines;
+    }
+
+    // Every tag that saving would rewrite, file by file
+    pub fn changes(&self) -> Vec<Change> {
+        let mut changes = vec![];
+        for song in &self.songs {
+            for (field, edit) in Field::ALL.iter().zip(&self.edits) {
+                let Some(edit) = edit else {
+                    continue;
+                };
+                let old = field.value(song).map(str::to_owned);
+                let new = (!edit.is_empty()).then(|| edit.clone());
+                if old != new {
+                    changes.push(Change {
+                        file: song.file.clone(),
+                        field: *field,
+                        old,
+                        new,
+                    });
+                }
+            }
+        }
+        changes
+    }
+
+    pub fn draw<R: Render>(&mut self, ct: &mut R, screen: Rect) {
+        self.sheet.draw(ct, screen);
+    }
+}
+
+// The changes as `old → new` under each file, shown before anything is written
+pub fn preview(changes: &[Change]) -> Overlay {
+    let mut lines = vec![];
+    for (idx, change) in changes.iter().enumerate() {
+        if idx == 0 || changes[idx - 1].file != change.file {
+            lines.push(Line::Section(change.file.clone()));
+        }
+        let old = change.old.as_deref().unwrap_or("(none)");
+        let new = change.new.as_deref().unwrap_or("(removed)");
+        lines.push(Line::Entry(
+            change.field.label().to_owned(),
+            format!("{old} → {new}"),
+        ));
+    }
+    Overlay::new("Tag changes", lines)
+}
+
+// New values for the fields of one file, `None` removing the tag
+type Edits<'a> = Vec<(Field, Option<&'a str>)>;
+
+// Writes the changes into the files under `music_dir`, returning the files that
+// were written along with the errors of those that were not
+pub fn write(music_dir: &Path, changes: &[Change]) -> (Vec<String>, Vec<anyhow::Error>) {
+    let mut files: Vec<(&str, Edits)> = vec![];
+    for change in changes {
+        let edit = (change.field, change.new.as_deref());
+        match files.iter_mut().find(|(file, _)| *file == change.file) {
+            Some((_, edits)) => edits.push(edit),
+            None => files.push((&change.file, vec![edit])),
+        }
+    }
+    let mut written = vec![];
+    let mut errors = vec![];
+    for (file, edits) in files {
+        match write_file(&music_dir.join(file), &edits) {
+            Ok(()) => written.push(file.to_owned()),
+            Err(err) => errors.push(err.context(format!("Failed to tag {file}"))),
+        }
+    }
+    (written, errors)
+}
+
+// Rewrites the tags in place, so the file keeps its inode and with it its mode,
+// owner and extended attributes. Each format gets the tag it normally carries
+fn write_file(path: &Path, edits: &[(Field, Option<&str>)]) -> Result<()> {
+    let mut file = Probe::open(path)?
+        .options(ParseOptions::new().read_properties(false))
+        .guess_file_type()?
+        .read()?;
+    let kind = file.primary_tag_type();
+    if file.tag(kind).is_none() {
+        file.insert_tag(Tag::new(kind));
+    }
+    let tag = file.tag_mut(kind).context("No tag to write to")?;
+    for (field, value) in edits {
+        match value {
+            Some(value) => {
+                if !tag.insert_text(field.key(), (*value).to_owned()) {
+                    bail!("{kind:?} tags have no {} field", field.label());
+                }
+            }
+            None => tag.remove_key(field.key()),
+        }
+    }
+    file.save_to_path(path, WriteOptions::default())?;
+    Ok(())
+}
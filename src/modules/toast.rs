@@ -0,0 +1,162 @@
+use std::time::{Duration, Instant};
+
+use crossterm::style::{Attribute, Color};
+use unicode_width::UnicodeWidthStr;
+
+use super::{
+    overlay::{Line, Overlay},
+    ui::{Overflow, Rect, Render},
+};
+
+// Toasts on screen at once, the oldest go first
+const MAX_SHOWN: usize = 4;
+// Warnings and errors kept for the history panel
+const HISTORY_LEN: usize = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Info,
+    Warning,
+    Error,
+}
+
+impl Severity {
+    fn color(self) -> Color {
+        match self {
+            Severity::Info => Color::Green,
+            Severity::Warning => Color::Yellow,
+            Severity::Error => Color::Red,
+        }
+    }
+
+    // Errors stay up longer, so there is time to read them
+    fn duration(self) -> Duration {
+        match self {
+            Severity::Info => Duration::from_secs(3),
+            Severity::Warning => Duration::from_secs(5),
+            Severity::Error => Duration::from_secs(8),
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            Severity::Info => "info",
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Toast {
+    pub severity: Severity,
+    pub text: String,
+    pub at: Instant,
+}
+
+impl Toast {
+    fn expired(&self) -> bool {
+        self.at.elapsed() >= self.severity.duration()
+    }
+}
+
+// Short messages stacked in the corner for a few seconds each
+#[derive(Default)]
+pub struct Toasts {
+    shown: Vec<Toast>,
+    history: Vec<Toast>,
+}
+
+impl Toasts {
+    pub fn push(&mut self, severity: Severity, text: impl Into<String>) {
+        let toast = Toast {
+            severity,
+            text: text.into(),
+            at: Instant::now(),
+        };
+        if severity != Severity::Info {
+            if self.history.len() == HISTORY_LEN {
+                self.history.remove(0);
+            }
+            self.history.push(toast.clone());
+        }
+        // The same message again, like a held key failing, only restarts its timer
+        self.shown
+            .retain(|shown| shown.text != toast.text || shown.severity != toast.severity);
+        self.shown.push(toast);
+        if self.shown.len() > MAX_SHOWN {
+            self.shown.remove(0);
+        }
+    }
+
+    // Toasts still on screen, oldest first
+    pub fn shown(&self) -> impl Iterator<Item = &Toast> {
+        self.shown.iter().filter(|toast| !toast.expired())
+    }
+
+    // Drops the toasts whose time is up, returning whether any were on screen
+    pub fn expire(&mut self) -> bool {
+        let len = self.shown.len();
+        self.shown.retain(|toast| !toast.expired());
+        self.shown.len() != len
+    }
+
+    pub fn history(&self) -> &[Toast] {
+        &self.history
+    }
+
+    // Recent warnings and errors, newest first
+    pub fn history_sheet(&self) -> Overlay {
+        let lines = self
+            .history
+            .iter()
+            .rev()
+            .map(|toast| {
+                let secs = toast.at.elapsed().as_secs();
+                let ago = match secs {
+                    0..=59 => format!("{secs}s ago"),
+                    60..=3599 => format!("{}m ago", secs / 60),
+                    _ => format!("{}h ago", secs / 3600),
+                };
+                Line::Entry(
+                    format!("{ago} {}", toast.severity.label()),
+                    toast.text.clone(),
+                )
+            })
+            .collect::<Vec<_>>();
+        if lines.is_empty() {
+            return Overlay::new(
+                "Errors",
+                vec![Line::Section("Nothing went wrong so far".to_owned())],
+            );
+        }
+        Overlay::new("Errors", lines)
+    }
+
+    // Stacks the toasts up from the bottom right of `rect`, newest at the bottom
+    pub fn draw<R: Render>(&mut self, ct: &mut R, rect: Rect) {
+        self.expire();
+        let mut bottom = rect.y + rect.height;
+        for toast in self.shown.iter().rev() {
+            if bottom <= rect.y || rect.width < 4 {
+                break;
+            }
+            bottom -= 1;
+            let width = (toast.text.width() as u32 + 2).min(rect.width * 2 / 3);
+            let toast_rect = Rect {
+                x: rect.x + rect.width - width,
+                y: bottom,
+                width,
+                height: 1,
+            };
+            ct.set_background(toast_rect, toast.severity.color());
+            ct.set_foreground(toast_rect, Color::Black);
+            ct.set_attributes(toast_rect, Attribute::Bold);
+            ct.set_text(
+                toast_rect,
+                format!(" {}", toast.text).as_str(),
+                Overflow::Char,
+            );
+        }
+    }
+}
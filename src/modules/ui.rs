@@ -1,5 +1,5 @@
 use anyhow::Result;
-use crossterm::{event::{Event, KeyEvent}, style::{Attribute, Color}};
+use crossterm::style::{Attribute, Color};
 
 #[derive(Debug, Clone, Copy, Default, Hash, PartialEq, Eq, PartialOrd, Ord)]
 pub struct Rect {
@@ -9,18 +9,43 @@ pub struct Rect {
     pub height: u32,
 }
 
+impl Rect {
+    // A `width` x `height` rect in the middle of this one, shrunk to fit
+    pub fn centered(self, width: u32, height: u32) -> Rect {
+        let width = width.min(self.width);
+        let height = height.min(self.height);
+        Rect {
+            x: self.x + (self.width - width) / 2,
+            y: self.y + (self.height - height) / 2,
+            width,
+            height,
+        }
+    }
+
+    // The area inside a one cell border
+    pub fn inner(self) -> Rect {
+        Rect {
+            x: self.x + 1,
+            y: self.y + 1,
+            width: self.width.saturating_sub(2),
+            height: self.height.saturating_sub(2),
+        }
+    }
+}
+
 // UI Trait contains all mechanisms that platform needs to implement
 pub trait UI {
     fn init() -> Result<Self>
     where
         Self: Sized;
-    // Key/Mouse Events
-    fn is_event_ready(&self) -> bool;
-
-    //Read Event
-    fn read_event(&mut self) -> Result<Event>;
+    // The terminal was resized, events themselves come in through `events::Events`
+    fn resize(&mut self, width: u32, height: u32);
 
     fn destroy(&mut self) -> Result<()>;
+
+    // Takes the terminal back after `destroy`, like when continued after a suspend,
+    // and repaints the whole screen
+    fn resume(&mut self) -> Result<()>;
 }
 
 pub trait Render {
@@ -29,9 +54,41 @@ pub trait Render {
     fn set_attributes(&mut self, rect: Rect, attr: Attribute);
     fn set_text(&mut self, rect: Rect, text: &str, overflow: Overflow);
     fn render_frame(&mut self) -> Result<()>;
+
+    // Terminal graphics like Kitty or Sixel images over `rect`, sent after the cells
+    // of the frame. Backends that can't show them leave the cells as they are
+    fn set_graphic(&mut self, _rect: Rect, _escape: &str) {}
+
+    // Box-drawing frame along the edges of `rect`, one cell at a time
+    fn draw_border(&mut self, rect: Rect) {
+        if rect.width < 2 || rect.height < 2 {
+            return;
+        }
+        let (right, bottom) = (rect.x + rect.width - 1, rect.y + rect.height - 1);
+        for y in rect.y..=bottom {
+            for x in rect.x..=right {
+                let char = match (x == rect.x || x == right, y == rect.y || y == bottom) {
+                    (true, true) if x == rect.x && y == rect.y => "┌",
+                    (true, true) if y == rect.y => "┐",
+                    (true, true) if x == rect.x => "└",
+                    (true, true) => "┘",
+                    (true, false) => "│",
+                    (false, true) => "─",
+                    (false, false) => continue,
+                };
+                let cell = Rect {
+                    x,
+                    y,
+                    width: 1,
+                    height: 1,
+                };
+                self.set_text(cell, char, Overflow::Char);
+            }
+        }
+    }
 }
 
-pub enum Overflow{
+// How text longer than a row goes on. Only wrapping at any grapheme exists
+pub enum Overflow {
     Char,
-    Word,
 }
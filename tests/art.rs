@@ -0,0 +1,130 @@
+use std::{io::Cursor, time::Duration};
+
+use crossterm::style::Color;
+use image::{DynamicImage, ImageFormat, Rgb, RgbImage};
+use rustmpc::modules::{
+    art::{fit, sixel, ArtPanel, Picture, Protocol, Rendered},
+    events::{Events, Message},
+    mock::{song, MockServer},
+    mpd::Mpd,
+};
+
+fn png(width: u32, height: u32) -> Vec<u8> {
+    // Noise so the encoded file spans several protocol chunks
+    let image = RgbImage::from_fn(width, height, |x, y| {
+        Rgb([(x * 7 + y * 13) as u8, (x * y) as u8, (x ^ y) as u8])
+    });
+    let mut data = vec![];
+    DynamicImage::ImageRgb8(image)
+        .write_to(&mut Cursor::new(&mut data), ImageFormat::Png)
+        .unwrap();
+    data
+}
+
+#[test]
+fn scales_covers_to_the_panel() {
+    assert_eq!(fit((1000, 500), 40, 20, (1, 2)), (40, 20));
+    assert_eq!(fit((500, 1000), 40, 20, (1, 2)), (20, 40));
+    assert_eq!(fit((300, 300), 30, 10, (10, 20)), (200, 200));
+
+    // Red on top, blue below, in half blocks
+    let image = RgbImage::from_fn(4, 4, |_, y| {
+        if y < 2 {
+            Rgb([255, 0, 0])
+        } else {
+            Rgb([0, 0, 255])
+        }
+    });
+    let picture =
+        Picture::new(&DynamicImage::ImageRgb8(image), Protocol::HalfBlock, 4, 10).unwrap();
+    assert_eq!((picture.cols, picture.rows), (4, 2));
+    let Rendered::HalfBlock { width, cells } = picture.rendered else {
+        panic!("Expected half blocks");
+    };
+    let red = Color::Rgb { r: 255, g: 0, b: 0 };
+    let blue = Color::Rgb { r: 0, g: 0, b: 255 };
+    assert_eq!(width, 4);
+    assert_eq!(cells[0], (red, red));
+    assert_eq!(cells[4], (blue, blue));
+}
+
+#[test]
+fn encodes_sixel() {
+    let image = RgbImage::from_pixel(5, 1, Rgb([255, 0, 0]));
+    let out = sixel(&image);
+    assert!(out.starts_with("\x1bPq\"1;1;5;1"));
+    assert!(out.contains("#180;2;100;0;0"));
+    assert!(out.ends_with("#180!5@$-\x1b\\"));
+}
+
+#[test]
+fn reads_covers_in_chunks() {
+    let server = MockServer::with_library(vec![
+        song("a/one.flac", "One", "Alpha", 180),
+        song("b/two.flac", "Two", "Beta", 200),
+        song("c/three.flac", "Three", "Gamma", 200),
+    ])
+    .unwrap();
+    let embedded = png(120, 80);
+    assert!(embedded.len() > 8192);
+    let folder = png(16, 16);
+    {
+        let mut state = server.state();
+        state.pictures.insert("a/one.flac".into(), embedded.clone());
+        state.covers.insert("a/one.flac".into(), folder.clone());
+        state.covers.insert("b/two.flac".into(), folder.clone());
+    }
+    let mut mpd: Mpd = Mpd::new(server.addr());
+    mpd.update_loop();
+
+    assert_eq!(mpd.album_art("a/one.flac"), Some(embedded));
+    assert_eq!(mpd.album_art("b/two.flac"), Some(folder.clone()));
+    assert_eq!(mpd.album_art("c/three.flac"), None);
+    // Files MPD can't read a picture from may still have one in their folder
+    server
+        .state()
+        .covers
+        .insert("d/gone.flac".into(), folder.clone());
+    assert_eq!(mpd.album_art("d/gone.flac"), Some(folder));
+    assert!(mpd.is_connected());
+
+    let mut panel = ArtPanel::new(Protocol::HalfBlock);
+    let one = song("a/one.flac", "One", "Alpha", 180);
+    let mut events = Events::new(Duration::from_secs(60));
+    panel.fetch_with(events.watch_art(server.addr()));
+    panel.sync(Some(&one));
+    assert!(panel.image().is_none());
+    let Some(Message::Art(file, image)) = events.next(None) else {
+        panic!("no cover arrived");
+    };
+    assert!(panel.receive(file, image));
+    let image = panel.image().unwrap();
+    assert_eq!((image.width(), image.height()), (120, 80));
+
+    // The old cover stays up while the next one is on its way
+    let two = song("b/two.flac", "Two", "Beta", 200);
+    panel.sync(Some(&two));
+    assert_eq!(panel.image().unwrap().width(), 120);
+    let Some(Message::Art(file, image)) = events.next(None) else {
+        panic!("no cover arrived");
+    };
+    assert!(panel.receive(file, image));
+    assert_eq!(panel.image().unwrap().width(), 16);
+
+    // Covers already seen are not fetched again
+    let fetches = server.state().commands.len();
+    panel.sync(Some(&one));
+    assert_eq!(panel.image().unwrap().width(), 120);
+    assert_eq!(server.state().commands.len(), fetches);
+
+    // Covers of songs gone by the time they arrive are only kept
+    let three = song("c/three.flac", "Three", "Gamma", 200);
+    panel.sync(Some(&three));
+    panel.sync(Some(&two));
+    let Some(Message::Art(file, image)) = events.next(None) else {
+        panic!("no cover arrived");
+    };
+    assert_eq!(file, "c/three.flac");
+    assert!(!panel.receive(file, image));
+    assert_eq!(panel.image().unwrap().width(), 16);
+}
@@ -0,0 +1,124 @@
+use crossterm::style::Color;
+use rustmpc::modules::{
+    buffer::{Buffer, Patch, Symbol},
+    ui::Rect,
+};
+
+fn rect(width: u32, height: u32) -> Rect {
+    Rect {
+        x: 0,
+        y: 0,
+        width,
+        height,
+    }
+}
+
+// Draws into the back buffer, then shows it the way `render_frame` does
+fn frame(back: &mut Buffer, front: &mut Buffer, draw: impl FnOnce(&mut Buffer)) -> Vec<Patch> {
+    draw(back);
+    let mut patches = vec![];
+    back.diff(front, &mut patches);
+    std::mem::swap(back, front);
+    back.sync(front);
+    patches
+}
+
+#[test]
+fn only_changed_cells_are_sent() {
+    let (mut back, mut front) = (Buffer::new(rect(4, 3)), Buffer::new(rect(4, 3)));
+    assert!(frame(&mut back, &mut front, |_| {}).is_empty());
+
+    let patches = frame(&mut back, &mut front, |buffer| {
+        buffer.set_symbol(1, 2, Symbol::from('a'));
+    });
+    assert_eq!(patches.len(), 1);
+    assert_eq!(
+        (patches[0].x, patches[0].y, patches[0].cell.symbol.as_str()),
+        (1, 2, "a")
+    );
+
+    // The back buffer was a frame behind, and has caught up
+    assert!(frame(&mut back, &mut front, |buffer| {
+        buffer.set_symbol(1, 2, Symbol::from('a'));
+    })
+    .is_empty());
+    let patches = frame(&mut back, &mut front, |buffer| {
+        buffer.update(1, 2, |cell| cell.fg = Color::Red);
+        buffer.set_symbol(3, 0, Symbol::from('b'));
+    });
+    assert_eq!(patches.len(), 2);
+    assert_eq!(back.get(1, 2).unwrap().symbol.as_str(), "a");
+    assert_eq!(back.get(1, 2).unwrap().fg, Color::Red);
+
+    // Nothing outside the screen, and no wrapping into the next row
+    assert!(back.get(4, 0).is_none());
+    assert!(back.get(0, 3).is_none());
+}
+
+#[test]
+fn invalidated_and_resized_cells_are_sent_again() {
+    let (mut back, mut front) = (Buffer::new(rect(4, 3)), Buffer::new(rect(4, 3)));
+    frame(&mut back, &mut front, |buffer| {
+        buffer.set_symbol(0, 0, Symbol::from('a'));
+    });
+
+    front.invalidate(Rect {
+        x: 1,
+        y: 1,
+        width: 2,
+        height: 1,
+    });
+    let patches = frame(&mut back, &mut front, |_| {});
+    assert_eq!(
+        patches.iter().map(|p| (p.x, p.y)).collect::<Vec<_>>(),
+        [(1, 1), (2, 1)]
+    );
+    assert!(frame(&mut back, &mut front, |_| {}).is_empty());
+
+    let mut back = Buffer::new(rect(5, 2));
+    assert_eq!(frame(&mut back, &mut front, |_| {}).len(), 10);
+    assert!(frame(&mut back, &mut front, |_| {}).is_empty());
+}
+
+#[test]
+fn text_is_laid_out_by_grapheme() {
+    let mut buffer = Buffer::new(rect(4, 3));
+    // An e with a combining acute, a wide character and a flag
+    buffer.set_text(rect(4, 3), "e\u{301}世x\n🇳🇴");
+    let row = |buffer: &Buffer, y| {
+        (0..4)
+            .map(|x| buffer.get(x, y).unwrap().symbol.as_str().to_owned())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(row(&buffer, 0), ["e\u{301}", "世", "", "x"]);
+    assert_eq!(row(&buffer, 1), ["🇳🇴", "", " ", " "]);
+    assert_eq!(row(&buffer, 2), [" ", " ", " ", " "]);
+    assert_eq!(buffer.get(1, 0).unwrap().symbol.width(), 2);
+    assert!(buffer.get(2, 0).unwrap().symbol.is_continuation());
+
+    // A wide character that doesn't fit moves to the next row
+    buffer.set_text(rect(4, 2), "abc世");
+    assert_eq!(row(&buffer, 0), ["a", "b", "c", " "]);
+    assert_eq!(row(&buffer, 1), ["世", "", " ", " "]);
+
+    // Overwriting half of a wide character blanks the other half
+    buffer.set_symbol(2, 1, Symbol::from('y'));
+    assert_eq!(row(&buffer, 1), ["世", "", "y", " "]);
+    buffer.set_symbol(1, 1, Symbol::from('z'));
+    assert_eq!(row(&buffer, 1), [" ", "z", "y", " "]);
+}
+
+#[test]
+fn redrawing_the_same_frame_leaves_rows_clean() {
+    let (mut back, mut front) = (Buffer::new(rect(4, 3)), Buffer::new(rect(4, 3)));
+    let draw = |buffer: &mut Buffer| {
+        buffer.set_text(rect(4, 3), "one\ntwo");
+        buffer.update(0, 2, |cell| cell.bg = Color::Blue);
+    };
+    assert_eq!(frame(&mut back, &mut front, draw).len(), 7);
+    draw(&mut back);
+    assert!((0..3).all(|y| !back.is_dirty(y)));
+    back.update(1, 1, |cell| cell.fg = Color::Red);
+    assert!(back.is_dirty(1));
+    assert!(!back.is_dirty(0));
+}
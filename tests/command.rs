@@ -0,0 +1,140 @@
+use crossterm::event::{Event, KeyCode, KeyEvent, KeyModifiers};
+use rustmpc::modules::{
+    app::App,
+    command::{complete, parse, Action, Adjust, ExCommand, Setting},
+    config::Config,
+    keymap::Key,
+    mock::{song, MockServer},
+    mpd::{Mpd, OneshotMode},
+};
+
+fn key(code: KeyCode) -> Event {
+    Event::Key(KeyEvent::new(code, KeyModifiers::NONE))
+}
+
+fn app(server: &MockServer) -> App {
+    let mut app = App::new(Config::default(), Mpd::new(server.addr()));
+    app.update();
+    app
+}
+
+#[test]
+fn parses_commands() {
+    assert_eq!(
+        parse("add artist \"Radiohead\"").unwrap(),
+        ExCommand::Add {
+            tag: "artist".into(),
+            value: "Radiohead".into()
+        }
+    );
+    assert_eq!(parse("vol 40").unwrap(), ExCommand::Volume(Adjust::To(40)));
+    assert_eq!(parse("seek +30").unwrap(), ExCommand::Seek(Adjust::Up(30)));
+    assert_eq!(parse("seek 1:30").unwrap(), ExCommand::Seek(Adjust::To(90)));
+    assert_eq!(
+        parse("save road trip").unwrap(),
+        ExCommand::Save("road trip".into())
+    );
+    assert_eq!(
+        parse("set repeat off").unwrap(),
+        ExCommand::Set(Setting::Repeat, Some(OneshotMode::Off))
+    );
+    assert_eq!(
+        parse("set single oneshot").unwrap(),
+        ExCommand::Set(Setting::Single, Some(OneshotMode::Oneshot))
+    );
+    assert!(parse("set repeat oneshot").is_err());
+    assert_eq!(
+        parse("set random!").unwrap(),
+        ExCommand::Set(Setting::Random, None)
+    );
+    assert_eq!(
+        parse("bind <leader><C-x> clear_queue").unwrap(),
+        ExCommand::Bind(
+            vec![
+                Key::Leader,
+                Key::Event(Event::Key(KeyEvent::new(
+                    KeyCode::Char('x'),
+                    KeyModifiers::CONTROL
+                )))
+            ],
+            Action::ClearQueue
+        )
+    );
+    assert_eq!(parse("next").unwrap(), ExCommand::Run(Action::Next));
+    assert!(parse("vol loud").is_err());
+    assert!(parse("frobnicate").is_err());
+    assert!(parse("add artist \"Radiohead").is_err());
+}
+
+#[test]
+fn completes_names_and_arguments() {
+    assert_eq!(
+        complete("se"),
+        [
+            "seek",
+            "set",
+            "search",
+            "seek_forward",
+            "seek_back",
+            "select_all",
+            "select_album"
+        ]
+    );
+    assert_eq!(complete("set r"), ["set repeat", "set random"]);
+    assert_eq!(complete("bind x crop"), ["bind x crop_queue"]);
+}
+
+#[test]
+fn runs_commands_against_mpd() {
+    let server = MockServer::with_library(vec![
+        song("a/one.flac", "One", "Radiohead", 180),
+        song("b/two.flac", "Two", "Beta", 200),
+    ])
+    .unwrap();
+    let mut app = app(&server);
+
+    app.execute("add artist radiohead").unwrap();
+    assert_eq!(server.state().queue_files(), ["a/one.flac"]);
+    assert!(app.execute("add artist Nobody").is_err());
+
+    app.execute("vol 40").unwrap();
+    app.update();
+    app.execute("vol +70").unwrap();
+    assert_eq!(server.state().volume, 100);
+
+    app.execute("set consume on").unwrap();
+    assert_eq!(server.state().consume, OneshotMode::On);
+    app.update();
+    app.execute("set consume!").unwrap();
+    assert_eq!(server.state().consume, OneshotMode::Off);
+    app.execute("set single oneshot").unwrap();
+    assert_eq!(server.state().single, OneshotMode::Oneshot);
+}
+
+#[test]
+fn command_line_and_bindings_share_actions() {
+    let server = MockServer::with_library(vec![song("a/one.flac", "One", "Alpha", 180)]).unwrap();
+    let mut app = app(&server);
+    app.execute("add").unwrap_err();
+
+    app.handle_event(&key(KeyCode::Char(':')));
+    assert!(app.command_line.is_open());
+    for c in "bind z switch_view".chars() {
+        app.handle_event(&key(KeyCode::Char(c)));
+    }
+    app.handle_event(&key(KeyCode::Enter));
+    assert!(!app.command_line.is_open());
+    assert!(app.popup.is_none());
+    assert_eq!(app.command_line.history(), ["bind z switch_view"]);
+
+    app.handle_event(&key(KeyCode::Char('z')));
+    app.update();
+    app.execute("add").unwrap();
+    assert_eq!(server.state().queue_files(), ["a/one.flac"]);
+
+    // A failing command is reported in a popup
+    app.handle_event(&key(KeyCode::Char(':')));
+    app.handle_event(&key(KeyCode::Char('x')));
+    app.handle_event(&key(KeyCode::Enter));
+    assert!(app.popup.is_some());
+}
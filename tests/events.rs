@@ -0,0 +1,136 @@
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+use crossterm::event::{Event, KeyCode, KeyEvent, KeyModifiers};
+use mpd::idle::Subsystem;
+use rustmpc::modules::{
+    app::App,
+    config::Config,
+    events::{Events, Message},
+    mock::{song, MockServer},
+    mpd::Mpd,
+};
+
+#[test]
+fn messages_ticks_and_frame_deadlines() {
+    let mut events = Events::new(Duration::from_millis(50));
+    events
+        .sender()
+        .send(Message::Changed(vec![Subsystem::Player]))
+        .unwrap();
+    assert!(matches!(
+        events.next(None),
+        Some(Message::Changed(changed)) if changed == [Subsystem::Player]
+    ));
+
+    // A frame due before the tick ends the wait early
+    let start = Instant::now();
+    assert!(events
+        .next(Some(start + Duration::from_millis(10)))
+        .is_none());
+    assert!(start.elapsed() < Duration::from_millis(50));
+
+    assert!(matches!(events.next(None), Some(Message::Tick)));
+    assert!(start.elapsed() >= Duration::from_millis(40));
+}
+
+#[test]
+fn ticks_only_ask_for_a_redraw_after_changes() {
+    let library = (0..5)
+        .map(|idx| song(&format!("{idx}.flac"), "Song", "Artist", 60))
+        .collect();
+    let server = MockServer::with_library(library).unwrap();
+    let mut conf = Config::default();
+    conf.keymaps.timeout = 10;
+    let mut app: App = App::new(conf, Mpd::new(server.addr()));
+    app.update();
+    for song in app.mpd.get_all_songs() {
+        app.mpd.push_into_queue(song).unwrap();
+    }
+    assert!(app.update());
+    assert!(!app.update());
+    assert!(!app.tick());
+
+    // Someone else changed the volume
+    server.state().volume = 20;
+    assert!(app.update());
+
+    // A lone `g` is shown until the timeout drops it
+    app.handle_event(&Event::Key(KeyEvent::new(
+        KeyCode::Char('G'),
+        KeyModifiers::NONE,
+    )));
+    app.handle_event(&Event::Key(KeyEvent::new(
+        KeyCode::Char('g'),
+        KeyModifiers::NONE,
+    )));
+    thread::sleep(Duration::from_millis(20));
+    assert!(app.tick());
+    assert!(!app.tick());
+}
+
+#[test]
+fn playlists_changed_elsewhere_are_reloaded() {
+    let server = MockServer::with_library(vec![song("a/one.flac", "One", "Alpha", 180)]).unwrap();
+    let mut app: App = App::new(Config::default(), Mpd::new(server.addr()));
+    app.update();
+    assert!(app.playlists.playlists.is_empty());
+
+    // Another client saves a playlist, which its status doesn't show
+    let mut other: Mpd = Mpd::new(server.addr());
+    other.update_loop();
+    other
+        .push_into_queue(song("a/one.flac", "One", "Alpha", 180))
+        .unwrap();
+    other.save_playlist("mix").unwrap();
+    app.changed(&[Subsystem::Player]);
+    assert!(app.playlists.playlists.is_empty());
+
+    app.changed(&[Subsystem::Playlist]);
+    let names: Vec<_> = app
+        .playlists
+        .playlists
+        .iter()
+        .map(|(playlist, count)| (playlist.name.as_str(), *count))
+        .collect();
+    assert_eq!(names, [("mix", 1)]);
+}
+
+#[test]
+fn marks_follow_songs_changed_elsewhere() {
+    let server = MockServer::with_library(vec![
+        song("a/one.flac", "One", "Alpha", 180),
+        song("a/two.flac", "Two", "Alpha", 200),
+        song("b/three.flac", "Three", "Beta", 240),
+    ])
+    .unwrap();
+    let mut app: App = App::new(Config::default(), Mpd::new(server.addr()));
+    app.update();
+    app.execute("add artist Alpha").unwrap();
+    app.execute("add artist Beta").unwrap();
+    app.update();
+    app.queue.list.jump(1, 3);
+    app.queue.list.toggle_mark(3);
+
+    // Another client moves the marked song to the end and deletes the first
+    let mut other: Mpd = Mpd::new(server.addr());
+    other.update_loop();
+    let ids: Vec<_> = other
+        .get_queue()
+        .iter()
+        .map(|song| song.place.unwrap().id)
+        .collect();
+    other
+        .reorder_queue(&ids, &[ids[0], ids[2], ids[1]])
+        .unwrap();
+    other.delete_ids([ids[0]]).unwrap();
+    app.changed(&[Subsystem::Queue]);
+    assert_eq!(app.queue.selected_files(), ["a/two.flac"]);
+
+    // Marks of songs that are gone go with them
+    other.delete_ids([ids[1]]).unwrap();
+    app.changed(&[Subsystem::Queue]);
+    assert!(app.queue.list.selection().is_empty());
+}
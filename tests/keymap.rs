@@ -0,0 +1,360 @@
+use std::time::{Duration, Instant};
+
+use crossterm::event::{
+    Event, KeyCode, KeyEvent, KeyEventKind, KeyEventState, KeyModifiers, ModifierKeyCode,
+};
+use rustmpc::modules::{
+    app::App,
+    command::Action,
+    config::Config,
+    keymap::{keys_name, parse_key, parse_keys, Key, KeyState, Keymap, Step},
+    mock::{song, MockServer},
+    mpd::Mpd,
+    overlay::{Line, Overlay},
+};
+
+fn key(c: char) -> Event {
+    Event::Key(KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE))
+}
+
+fn keymap() -> Keymap {
+    Keymap::from([
+        ("g", Action::Top),
+        ("gg", Action::Bottom),
+        ("<leader>d", Action::ClearQueue),
+        ("j", Action::Down),
+    ])
+}
+
+fn run(step: Step) -> Option<(Vec<Action>, Option<usize>)> {
+    match step {
+        Step::Run(actions, count) => Some((actions, count)),
+        _ => None,
+    }
+}
+
+#[test]
+fn resolves_sequences_counts_and_leader() {
+    let map = keymap();
+    let maps = [&map];
+    let leader = key(',');
+    let now = Instant::now();
+    let mut keys = KeyState::default();
+
+    assert!(run(keys.feed(&key('g'), &maps, &leader, true, now)).is_none());
+    assert_eq!(
+        run(keys.feed(&key('g'), &maps, &leader, true, now)),
+        Some((vec![Action::Bottom], None))
+    );
+
+    keys.feed(&key('1'), &maps, &leader, true, now);
+    keys.feed(&key('2'), &maps, &leader, true, now);
+    assert_eq!(keys.pending(), "12");
+    assert_eq!(
+        run(keys.feed(&key('j'), &maps, &leader, true, now)),
+        Some((vec![Action::Down], Some(12)))
+    );
+
+    keys.feed(&key(','), &maps, &leader, true, now);
+    assert_eq!(
+        run(keys.feed(&key('d'), &maps, &leader, true, now)),
+        Some((vec![Action::ClearQueue], None))
+    );
+
+    // A prefix that is also a binding fires once the timeout passes
+    keys.feed(&key('g'), &maps, &leader, true, now);
+    let timeout = Duration::from_millis(500);
+    assert!(keys.tick(&maps, &leader, timeout, now).is_none());
+    let later = now + Duration::from_secs(1);
+    assert_eq!(
+        keys.tick(&maps, &leader, timeout, later).and_then(run),
+        Some((vec![Action::Top], None))
+    );
+
+    assert!(matches!(
+        keys.feed(&key('z'), &maps, &leader, true, now),
+        Step::Unbound(_)
+    ));
+}
+
+#[test]
+fn quit_keys_can_be_rebound() {
+    let server = MockServer::with_library(vec![song("a/one.flac", "One", "Alpha", 180)]).unwrap();
+    let mut app: App = App::new(Config::default(), Mpd::new(server.addr()));
+    app.update();
+
+    app.execute("unbind q").unwrap();
+    app.handle_event(&key('q'));
+    assert!(!app.quit);
+    // Gone from the sheets and popups as well
+    for (_, map) in app.conf.keymaps.named() {
+        assert!(map.0.iter().all(|binding| keys_name(&binding.keys) != "q"));
+    }
+    assert!(app.execute("unbind q").is_err());
+
+    app.execute("bind ZZ quit").unwrap();
+    app.handle_event(&key('Z'));
+    assert!(!app.quit);
+    app.handle_event(&key('Z'));
+    assert!(app.quit);
+}
+
+#[test]
+fn ctrl_z_asks_to_suspend() {
+    let server = MockServer::with_library(vec![song("a/one.flac", "One", "Alpha", 180)]).unwrap();
+    let mut app: App = App::new(Config::default(), Mpd::new(server.addr()));
+    app.update();
+
+    app.handle_event(&Event::Key(KeyEvent::new(
+        KeyCode::Char('z'),
+        KeyModifiers::CONTROL,
+    )));
+    assert!(app.suspend);
+    assert!(!app.quit);
+}
+
+#[test]
+fn counts_and_chords_move_the_cursor() {
+    let library = (0..10)
+        .map(|idx| song(&format!("{idx}.flac"), "Song", "Artist", 60))
+        .collect();
+    let server = MockServer::with_library(library).unwrap();
+    let mut app: App = App::new(Config::default(), Mpd::new(server.addr()));
+    app.update();
+    app.execute("add artist Artist").unwrap();
+    app.update();
+
+    for c in "4j".chars() {
+        app.handle_event(&key(c));
+    }
+    assert_eq!(app.queue.list.cursor, 4);
+    app.handle_event(&Event::Key(KeyEvent::new(
+        KeyCode::Char('G'),
+        KeyModifiers::SHIFT,
+    )));
+    assert_eq!(app.queue.list.cursor, 9);
+    for c in "gg".chars() {
+        app.handle_event(&key(c));
+    }
+    assert_eq!(app.queue.list.cursor, 0);
+
+    // Typing in the search prompt does not trigger normal mode bindings
+    for c in "/7.flac".chars() {
+        app.handle_event(&key(c));
+    }
+    app.handle_event(&Event::Key(KeyEvent::new(
+        KeyCode::Enter,
+        KeyModifiers::NONE,
+    )));
+    assert_eq!(app.queue.list.cursor, 7);
+}
+
+#[test]
+fn parses_key_notation() {
+    let ctrl_d = Event::Key(KeyEvent::new(KeyCode::Char('d'), KeyModifiers::CONTROL));
+    assert_eq!(parse_key("C-d").unwrap(), ctrl_d);
+    assert_eq!(parse_key("S-s").unwrap(), parse_key("S").unwrap());
+    assert_eq!(
+        parse_key("<Space>").unwrap(),
+        Event::Key(KeyEvent::new(KeyCode::Char(' '), KeyModifiers::NONE))
+    );
+    assert!(parse_key("<MediaPlay>").is_ok());
+    assert!(parse_key("X-d")
+        .unwrap_err()
+        .to_string()
+        .contains("modifier"));
+    assert!(parse_key("<Nope>").is_err());
+
+    for keys in [
+        "gg",
+        "<leader>d",
+        "<C-d>",
+        "<Space>",
+        "<F5>",
+        "G",
+        "<A-Enter>",
+    ] {
+        assert_eq!(keys_name(&parse_keys(keys).unwrap()), keys);
+    }
+    assert!(parse_keys("d<leader>").is_err());
+
+    // Names only count in brackets, bare they are letters
+    let letters = |keys: &str| keys.chars().map(|c| Key::Event(key(c))).collect::<Vec<_>>();
+    for keys in ["end", "up", "cr", "lt", "bs", "del", "tab", "esc"] {
+        assert_eq!(parse_keys(keys).unwrap(), letters(keys));
+    }
+    let end = Event::Key(KeyEvent::new(KeyCode::End, KeyModifiers::NONE));
+    assert_eq!(parse_keys("<End>").unwrap(), [Key::Event(end)]);
+    assert!(parse_key("end").is_err());
+    assert!(parse_key("C-Left").is_err());
+    assert!(parse_key("<C-Left>").is_ok());
+}
+
+#[test]
+fn reads_notation_and_legacy_configs() {
+    let mut conf = Config::default();
+    conf.keymaps.normal = Keymap::from([("<C-d>", Action::Down), ("gg", Action::Top)]);
+    let yaml = serde_yml::to_string(&conf).unwrap();
+    assert!(yaml.contains("<C-d>: down"));
+    let read = Config::from_yaml(&yaml).unwrap();
+    assert_eq!(read.keymaps.normal, conf.keymaps.normal);
+    assert_eq!(read.keymaps.leader, conf.keymaps.leader);
+
+    // Keys serialized as crossterm events still load
+    let event = Event::Key(KeyEvent::new(KeyCode::Char('x'), KeyModifiers::NONE));
+    let legacy = format!(
+        "keybinds:\n  quit:\n  - {}",
+        serde_yml::to_string(&event)
+            .unwrap()
+            .trim_end()
+            .replace('\n', "\n    ")
+    );
+    let read = Config::from_yaml(&legacy).unwrap();
+    let quit: Vec<_> = read
+        .keymaps
+        .normal
+        .0
+        .iter()
+        .filter(|binding| binding.action == Action::Quit)
+        .map(|binding| keys_name(&binding.keys))
+        .collect();
+    assert_eq!(quit, ["x"]);
+
+    assert!(Config::from_yaml("keymaps:\n  normal:\n    <Nope>: quit\n").is_err());
+    assert!(Config::from_yaml("keybinds:\n  frobnicate: []\n").is_err());
+}
+
+#[test]
+fn reports_conflicting_bindings() {
+    assert!(Config::default().keymaps.conflicts().is_empty());
+
+    let conf = Config::from_yaml("keymaps:\n  normal:\n    S: shuffle\n    S-s: stop\n").unwrap();
+    assert_eq!(
+        conf.keymaps.conflicts(),
+        ["\"S\" in normal is bound to shuffle, stop"]
+    );
+    let server = MockServer::with_library(vec![]).unwrap();
+    let app: App = App::new(conf, Mpd::new(server.addr()));
+    assert!(app.popup.is_some());
+}
+
+#[test]
+fn reports_bindings_that_start_longer_ones() {
+    let conf = Config::from_yaml(
+        "keymaps:\n  normal:\n    g: stop\n    gg: top\n    j: down\n  queue:\n    jj: stop\n",
+    )
+    .unwrap();
+    assert_eq!(
+        conf.keymaps.conflicts(),
+        [
+            "\"g\" in normal waits for more keys, as \"gg\" in normal starts with it",
+            "\"j\" in normal waits for more keys, as \"jj\" in queue starts with it",
+        ]
+    );
+
+    // A view may take over a key of normal, but not start one of its chords
+    let conf = Config::from_yaml("keymaps:\n  lyrics:\n    g: stop\n    x: stop\n").unwrap();
+    assert_eq!(
+        conf.keymaps.conflicts(),
+        ["\"g\" in lyrics waits for more keys, as \"gg\" in normal starts with it"]
+    );
+}
+
+#[test]
+fn help_lists_every_action() {
+    let mut conf = Config::default();
+    conf.keymaps.normal.unbind(&parse_keys("x").unwrap());
+    let help = Overlay::help(&conf.keymaps);
+    for (name, _) in Action::ALL {
+        assert!(
+            help.lines
+                .iter()
+                .any(|line| matches!(line, Line::Entry(_, action) if action == name)),
+            "{name} is missing"
+        );
+    }
+    assert!(help.lines.iter().any(|line| matches!(
+        line,
+        Line::Entry(keys, action) if keys == "q <C-c> <Esc>" && action == "quit"
+    )));
+
+    let server = MockServer::with_library(vec![]).unwrap();
+    let mut app: App = App::new(Config::default(), Mpd::new(server.addr()));
+    app.update();
+    app.handle_event(&key('?'));
+    assert!(app.overlay.is_some());
+    app.handle_event(&key('j'));
+    assert_eq!(app.overlay.as_ref().unwrap().list.cursor, 1);
+    app.handle_event(&key('q'));
+    assert!(app.overlay.is_none());
+    assert!(!app.quit);
+}
+
+#[test]
+fn normalizes_enhanced_key_events() {
+    let map = keymap();
+    let mut release = keymap();
+    release.bind(parse_keys("<Release-Space>").unwrap(), Action::PlayPause);
+    let maps = [&map];
+    let leader = key(',');
+    let now = Instant::now();
+    let mut keys = KeyState::default();
+    let with = |c: char, modifiers: KeyModifiers, kind: KeyEventKind| {
+        let mut event = KeyEvent::new_with_kind(KeyCode::Char(c), modifiers, kind);
+        event.state = KeyEventState::CAPS_LOCK;
+        Event::Key(event)
+    };
+
+    // Lone modifiers and releases do not break a sequence
+    keys.feed(&key('g'), &maps, &leader, true, now);
+    let shift = Event::Key(KeyEvent::new(
+        KeyCode::Modifier(ModifierKeyCode::LeftShift),
+        KeyModifiers::SHIFT,
+    ));
+    assert!(run(keys.feed(&shift, &maps, &leader, true, now)).is_none());
+    let released = with('g', KeyModifiers::NONE, KeyEventKind::Release);
+    assert!(run(keys.feed(&released, &maps, &leader, true, now)).is_none());
+    assert_eq!(
+        run(keys.feed(&key('g'), &maps, &leader, true, now)),
+        Some((vec![Action::Bottom], None))
+    );
+
+    // Holding a key repeats only actions meant for it
+    let held = with('j', KeyModifiers::NONE, KeyEventKind::Repeat);
+    assert_eq!(
+        run(keys.feed(&held, &maps, &leader, true, now)),
+        Some((vec![Action::Down], None))
+    );
+    let normal = Config::default().keymaps.normal;
+    let held = with('+', KeyModifiers::ALT, KeyEventKind::Repeat);
+    assert_eq!(
+        run(keys.feed(&held, &[&normal], &leader, true, now)),
+        Some((vec![Action::VolUpFine], None))
+    );
+    let held = with(',', KeyModifiers::NONE, KeyEventKind::Repeat);
+    assert!(run(keys.feed(&held, &maps, &leader, true, now)).is_none());
+    assert_eq!(keys.pending(), "");
+
+    let space = |kind| {
+        Event::Key(KeyEvent::new_with_kind(
+            KeyCode::Char(' '),
+            KeyModifiers::NONE,
+            kind,
+        ))
+    };
+    let maps = [&release];
+    assert!(matches!(
+        keys.feed(&space(KeyEventKind::Press), &maps, &leader, true, now),
+        Step::Unbound(_)
+    ));
+    assert_eq!(
+        run(keys.feed(&space(KeyEventKind::Release), &maps, &leader, true, now)),
+        Some((vec![Action::PlayPause], None))
+    );
+
+    // Terminals disagree on whether shifted symbols and letters carry Shift
+    let server = MockServer::with_library(vec![]).unwrap();
+    let mut app: App = App::new(Config::default(), Mpd::new(server.addr()));
+    app.handle_event(&with('?', KeyModifiers::SHIFT, KeyEventKind::Press));
+    assert!(app.overlay.is_some());
+}
@@ -0,0 +1,214 @@
+use std::{
+    fs,
+    path::PathBuf,
+    sync::Arc,
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use crossterm::event::{Event, KeyCode, KeyEvent, KeyModifiers};
+use mpd::Song;
+use rustmpc::modules::{
+    app::{App, View},
+    config::Config,
+    lyrics::{candidates, Lyrics, LyricsPanel, Provider},
+    mock::{song, MockServer},
+    mpd::Mpd,
+};
+
+fn scratch_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("rustmpc-{name}-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+const LRC: &str = "[ar:Alpha]
+[ti:One]
+[offset:+500]
+[00:01.50][00:20.00]Chorus
+[00:10.00]Verse
+";
+
+#[test]
+fn parses_lrc_and_plain_lyrics() {
+    let lyrics = Lyrics::parse(LRC);
+    let lines: Vec<_> = lyrics
+        .lines
+        .iter()
+        .map(|line| (line.time.unwrap().as_millis(), line.text.as_str()))
+        .collect();
+    assert_eq!(
+        lines,
+        [(1000, "Chorus"), (9500, "Verse"), (19500, "Chorus")]
+    );
+    assert_eq!(lyrics.current(Duration::ZERO), None);
+    assert_eq!(lyrics.current(Duration::from_secs(12)), Some(1));
+    assert_eq!(lyrics.current(Duration::from_secs(60)), Some(2));
+
+    let plain = Lyrics::parse("First line\n\nSecond line");
+    assert!(!plain.is_synced());
+    assert_eq!(plain.lines.len(), 3);
+    assert_eq!(plain.current(Duration::from_secs(5)), None);
+
+    // Stamps that are no time are read as plain text
+    let broken = Lyrics::parse("[00:-1]Before\n[00:nan]Never\n[00:inf]Always");
+    assert!(!broken.is_synced());
+    assert_eq!(broken.lines.len(), 3);
+}
+
+#[test]
+fn finds_lyrics_next_to_tracks() {
+    let music = scratch_dir("music");
+    fs::create_dir_all(music.join("a")).unwrap();
+    fs::write(music.join("a/one.lrc"), LRC).unwrap();
+    let one = song("a/one.flac", "One", "Alpha", 180);
+    assert_eq!(
+        candidates(&one, Some(&music), None),
+        [music.join("a/one.lrc"), music.join("a/one.txt")]
+    );
+
+    let mut panel = LyricsPanel::new(Some(music.clone()), None, None);
+    panel.sync(Some(&one), Some(Duration::from_secs(12)));
+    assert!(panel.lyrics().is_some());
+    assert_eq!(panel.list.cursor, 1);
+    panel.sync(Some(&one), Some(Duration::from_secs(25)));
+    assert_eq!(panel.list.cursor, 2);
+
+    // Scrolling by hand stops following until a line is picked
+    panel.cursor_up();
+    panel.sync(Some(&one), Some(Duration::from_secs(30)));
+    assert_eq!(panel.list.cursor, 1);
+    assert_eq!(panel.selected_time(), Some(Duration::from_millis(9500)));
+
+    let two = song("b/two.flac", "Two", "Beta", 200);
+    panel.sync(Some(&two), None);
+    assert!(panel.lyrics().is_none());
+    fs::remove_dir_all(music).unwrap();
+}
+
+struct Fixed;
+
+impl Provider for Fixed {
+    fn fetch(&self, song: &Song) -> Result<Option<String>> {
+        Ok(Some(format!(
+            "[00:01.00]{}",
+            song.title.as_deref().unwrap()
+        )))
+    }
+}
+
+#[test]
+fn fetches_and_caches_missing_lyrics() {
+    let cache = scratch_dir("lyrics-cache");
+    let one = song("a/one.flac", "One", "Alpha", 180);
+    let mut panel = LyricsPanel::new(None, Some(cache.clone()), Some(Arc::new(Fixed)));
+    let start = Instant::now();
+    while panel.lyrics().is_none() && start.elapsed() < Duration::from_secs(5) {
+        panel.sync(Some(&one), None);
+        thread::sleep(Duration::from_millis(10));
+    }
+    assert_eq!(panel.lyrics().unwrap().lines[0].text, "One");
+    assert_eq!(
+        fs::read_to_string(cache.join("Alpha - One.lrc")).unwrap(),
+        "[00:01.00]One"
+    );
+
+    // Without a provider the cache is still read
+    let mut panel = LyricsPanel::new(None, Some(cache.clone()), None);
+    panel.sync(Some(&one), None);
+    assert!(panel.lyrics().is_some());
+    fs::remove_dir_all(cache).unwrap();
+}
+
+#[test]
+fn lyrics_view_follows_the_playing_song() {
+    let server = MockServer::with_library(vec![song("a/one.flac", "One", "Alpha", 180)]).unwrap();
+    let music = scratch_dir("view-music");
+    fs::create_dir_all(music.join("a")).unwrap();
+    fs::write(music.join("a/one.txt"), "Plain words").unwrap();
+    let mut app: App = App::new(Config::default(), Mpd::new(server.addr()));
+    app.lyrics.music_dir = Some(music.clone());
+    app.lyrics.cache_dir = None;
+    app.update();
+    app.execute("add artist Alpha").unwrap();
+    app.update();
+    app.handle_event(&Event::Key(KeyEvent::new(
+        KeyCode::Enter,
+        KeyModifiers::NONE,
+    )));
+    app.handle_event(&Event::Key(KeyEvent::new(
+        KeyCode::Char('L'),
+        KeyModifiers::SHIFT,
+    )));
+    assert_eq!(app.view, View::Lyrics);
+    app.update();
+    assert_eq!(app.lyrics.lyrics().unwrap().lines[0].text, "Plain words");
+    fs::remove_dir_all(music).unwrap();
+
+    app.execute("lyrics").unwrap();
+    assert_eq!(app.view, View::Queue);
+}
+
+#[test]
+fn synced_lyrics_move_along_between_status_replies() {
+    let server = MockServer::with_library(vec![song("a/one.flac", "One", "Alpha", 180)]).unwrap();
+    let music = scratch_dir("tick-music");
+    fs::create_dir_all(music.join("a")).unwrap();
+    fs::write(
+        music.join("a/one.lrc"),
+        "[00:00.00]First\n[00:00.30]Second\n",
+    )
+    .unwrap();
+    let mut app: App = App::new(Config::default(), Mpd::new(server.addr()));
+    app.lyrics.music_dir = Some(music.clone());
+    app.lyrics.cache_dir = None;
+    app.update();
+    app.execute("add artist Alpha").unwrap();
+    app.mpd.play_position(0).unwrap();
+    app.execute("lyrics").unwrap();
+    app.update();
+    assert_eq!(app.lyrics.list.cursor, 0);
+    let commands = server.state().commands.len();
+
+    // MPD still says no time has passed, but the clock has
+    thread::sleep(Duration::from_millis(400));
+    assert!(app.tick());
+    assert_eq!(app.lyrics.list.cursor, 1);
+    assert!(app.mpd.get_time().unwrap().0 >= Duration::from_millis(400));
+    assert_eq!(server.state().commands.len(), commands);
+    fs::remove_dir_all(music).unwrap();
+}
+
+// Takes long enough to still be fetching once the view is shown
+struct Slow;
+
+impl Provider for Slow {
+    fn fetch(&self, song: &Song) -> Result<Option<String>> {
+        thread::sleep(Duration::from_millis(200));
+        Fixed.fetch(song)
+    }
+}
+
+#[test]
+fn fetched_lyrics_show_up_while_stopped() {
+    let server = MockServer::with_library(vec![song("a/one.flac", "One", "Alpha", 180)]).unwrap();
+    let mut app: App = App::new(Config::default(), Mpd::new(server.addr()));
+    app.lyrics.music_dir = None;
+    app.lyrics.cache_dir = None;
+    app.lyrics.provider = Some(Arc::new(Slow));
+    app.update();
+    app.execute("add artist Alpha").unwrap();
+    app.mpd.play_position(0).unwrap();
+    app.mpd.stop_playback().unwrap();
+    app.execute("lyrics").unwrap();
+    app.update();
+    assert!(app.lyrics.is_fetching());
+
+    let start = Instant::now();
+    while !app.tick() && start.elapsed() < Duration::from_secs(5) {
+        thread::sleep(Duration::from_millis(10));
+    }
+    assert_eq!(app.lyrics.lyrics().unwrap().lines[0].text, "One");
+}
@@ -0,0 +1,670 @@
+use std::{thread, time::Duration};
+
+use crossterm::event::{Event, KeyCode, KeyEvent, KeyModifiers};
+use mpd::State;
+use rustmpc::modules::{
+    app::{App, View},
+    config::Config,
+    mock::{song, MockServer},
+    modes::Row,
+    mpd::{parse_status, Mpd, MpdError, OneshotMode},
+    overlay::Line,
+    toast::Severity,
+};
+
+fn library() -> Vec<mpd::Song> {
+    vec![
+        song("a/one.flac", "One", "Alpha", 180),
+        song("a/two.flac", "Two", "Alpha", 200),
+        song("b/three.flac", "Three", "Beta", 240),
+    ]
+}
+
+fn connect(server: &MockServer) -> Mpd {
+    let mut mpd: Mpd = Mpd::new(server.addr());
+    assert!(mpd.is_connected());
+    mpd.update_loop();
+    mpd
+}
+
+fn fill_queue(mpd: &mut Mpd) {
+    for song in mpd.get_all_songs() {
+        mpd.push_into_queue(song).unwrap();
+    }
+    mpd.update_loop();
+}
+
+#[test]
+fn reads_status_and_library() {
+    let server = MockServer::with_library(library()).unwrap();
+    let mut mpd = connect(&server);
+
+    assert_eq!(mpd.get_volume(), Some(50));
+    assert_eq!(mpd.get_repeat(), Some(false));
+    let files = mpd
+        .get_all_songs()
+        .into_iter()
+        .map(|song| song.file)
+        .collect::<Vec<_>>();
+    assert_eq!(files, ["a/one.flac", "a/two.flac", "b/three.flac"]);
+}
+
+#[test]
+fn play_pause_and_skip() {
+    let server = MockServer::with_library(library()).unwrap();
+    let mut mpd = connect(&server);
+    fill_queue(&mut mpd);
+
+    mpd.toggle_play().unwrap();
+    mpd.update_loop();
+    assert_eq!(server.state().state, State::Play);
+    assert_eq!(mpd.get_current_playing().unwrap().file, "a/one.flac");
+
+    mpd.toggle_play().unwrap();
+    assert_eq!(server.state().state, State::Pause);
+
+    mpd.next_song().unwrap();
+    assert_eq!(mpd.get_current_playing().unwrap().file, "a/two.flac");
+    mpd.prev_song().unwrap();
+    assert_eq!(mpd.get_current_playing().unwrap().file, "a/one.flac");
+
+    mpd.stop_playback().unwrap();
+    assert_eq!(server.state().state, State::Stop);
+}
+
+#[test]
+fn queue_editing() {
+    let server = MockServer::with_library(library()).unwrap();
+    let mut mpd = connect(&server);
+    fill_queue(&mut mpd);
+    assert_eq!(
+        server.state().queue_files(),
+        ["a/one.flac", "a/two.flac", "b/three.flac"]
+    );
+
+    let id = mpd.get_queue()[1].place.unwrap().id;
+    mpd.delete_from_queue(id).unwrap();
+    assert_eq!(server.state().queue_files(), ["a/one.flac", "b/three.flac"]);
+
+    mpd.clear_queue().unwrap();
+    assert!(mpd.get_queue().is_empty());
+}
+
+#[test]
+fn server_errors_keep_the_connection() {
+    let server = MockServer::with_library(library()).unwrap();
+    let mut mpd = connect(&server);
+
+    let error = mpd.seek(Duration::from_secs(10)).unwrap_err();
+    assert!(matches!(error, MpdError::Server { .. }));
+    assert!(mpd.is_connected());
+    assert_eq!(server.state().elapsed, Duration::ZERO);
+}
+
+#[test]
+fn reconnects_after_disconnect() {
+    let server = MockServer::with_library(library()).unwrap();
+    let mut mpd = connect(&server);
+
+    server.disconnect_all();
+    thread::sleep(Duration::from_millis(50));
+    mpd.update_loop();
+    assert!(!mpd.is_connected());
+    assert_eq!(mpd.get_volume(), None);
+
+    assert!(mpd.reconnect());
+    mpd.update_loop();
+    assert_eq!(mpd.get_volume(), Some(50));
+}
+
+#[test]
+fn jumps_to_a_position() {
+    let server = MockServer::with_library(library()).unwrap();
+    let mut mpd = connect(&server);
+    fill_queue(&mut mpd);
+
+    mpd.play_position(1).unwrap();
+    assert_eq!(mpd.get_current_playing().unwrap().file, "a/two.flac");
+}
+
+#[test]
+fn batched_selection_commands() {
+    let server = MockServer::with_library(library()).unwrap();
+    let mut mpd = connect(&server);
+
+    let files = mpd
+        .get_library()
+        .into_iter()
+        .map(|song| song.file)
+        .collect::<Vec<_>>();
+    server.state().commands.clear();
+    mpd.add_files(files).unwrap();
+    assert_eq!(server.state().queue.len(), 3);
+    assert_eq!(
+        server.state().commands[1..],
+        [
+            "command_list_begin",
+            "add \"a/one.flac\"",
+            "add \"a/two.flac\"",
+            "add \"b/three.flac\"",
+            "command_list_end"
+        ]
+    );
+
+    let ids = mpd
+        .get_queue()
+        .into_iter()
+        .map(|song| song.place.unwrap().id)
+        .collect::<Vec<_>>();
+    mpd.reorder_queue(&ids, &[ids[2], ids[0], ids[1]]).unwrap();
+    assert_eq!(
+        server.state().queue_files(),
+        ["b/three.flac", "a/one.flac", "a/two.flac"]
+    );
+
+    mpd.set_priority([ids[0], ids[1]], 255).unwrap();
+    let prios = mpd
+        .get_queue()
+        .into_iter()
+        .map(|song| song.place.unwrap().prio)
+        .collect::<Vec<_>>();
+    assert_eq!(prios, [0, 255, 255]);
+
+    mpd.delete_ids([ids[0], ids[2]]).unwrap();
+    assert_eq!(server.state().queue_files(), ["a/two.flac"]);
+}
+
+#[test]
+fn stored_playlists() {
+    let server = MockServer::with_library(library()).unwrap();
+    let mut mpd = connect(&server);
+    fill_queue(&mut mpd);
+
+    mpd.save_playlist("mix").unwrap();
+    mpd.add_to_playlist("mix", ["a/one.flac".to_owned()])
+        .unwrap();
+    let playlists = mpd.get_playlists();
+    assert_eq!(playlists.len(), 1);
+    assert_eq!(playlists[0].0.name, "mix");
+    assert_eq!(playlists[0].1, 4);
+
+    // Counted in one round trip however many there are
+    mpd.save_playlist("again").unwrap();
+    let sent = server.state().commands.len();
+    let counts: Vec<_> = mpd
+        .get_playlists()
+        .into_iter()
+        .map(|(playlist, count)| (playlist.name, count))
+        .collect();
+    assert_eq!(counts, [("again".to_owned(), 3), ("mix".to_owned(), 4)]);
+    assert_eq!(
+        server.state().commands[sent..],
+        [
+            "listplaylists",
+            "command_list_ok_begin",
+            "listplaylist \"again\"",
+            "listplaylist \"mix\"",
+            "command_list_end"
+        ]
+    );
+    mpd.delete_playlist("again").unwrap();
+
+    mpd.delete_from_playlist("mix", &[0, 3]).unwrap();
+    mpd.move_in_playlist("mix", &[(1, 0)]).unwrap();
+    let files = mpd
+        .get_playlist("mix")
+        .into_iter()
+        .map(|song| song.file)
+        .collect::<Vec<_>>();
+    assert_eq!(files, ["b/three.flac", "a/two.flac"]);
+
+    mpd.rename_playlist("mix", "road trip").unwrap();
+    mpd.replace_with_playlist("road trip").unwrap();
+    assert_eq!(server.state().queue_files(), ["b/three.flac", "a/two.flac"]);
+
+    mpd.delete_playlist("road trip").unwrap();
+    assert!(mpd.get_playlists().is_empty());
+}
+
+#[test]
+fn deleting_playlist_songs_asks_first() {
+    let server = MockServer::with_library(library()).unwrap();
+    let mut app: App = App::new(Config::default(), Mpd::new(server.addr()));
+    app.update();
+    fill_queue(&mut app.mpd);
+    app.mpd.save_playlist("mix").unwrap();
+    app.playlists.reload();
+    app.update();
+    app.view = View::Playlists;
+    app.playlists.open_selected(&mut app.mpd);
+
+    keys(&mut app, "x");
+    assert_eq!(
+        app.popup.as_ref().unwrap().title,
+        "Delete 1 songs from \"mix\"?"
+    );
+    keys(&mut app, "n");
+    assert_eq!(server.state().playlists["mix"].len(), 3);
+
+    keys(&mut app, "xy");
+    assert_eq!(
+        server.state().playlists["mix"],
+        ["a/two.flac", "b/three.flac"]
+    );
+}
+
+#[test]
+fn clearing_the_queue_asks_without_fetching_it() {
+    let server = MockServer::with_library(library()).unwrap();
+    let mut app: App = App::new(Config::default(), Mpd::new(server.addr()));
+    fill_queue(&mut app.mpd);
+    app.update();
+    let sent = server.state().commands.len();
+
+    keys(&mut app, "d");
+    assert_eq!(
+        app.popup.as_ref().unwrap().title,
+        "Clear 3 songs from queue?"
+    );
+    assert_eq!(server.state().commands.len(), sent);
+    keys(&mut app, "y");
+    assert!(server.state().queue.is_empty());
+}
+
+#[test]
+fn undo_and_redo_queue_changes() {
+    let server = MockServer::with_library(library()).unwrap();
+    let mut mpd = connect(&server);
+    fill_queue(&mut mpd);
+    let original = ["a/one.flac", "a/two.flac", "b/three.flac"];
+
+    let ids = mpd
+        .get_queue()
+        .into_iter()
+        .map(|song| song.place.unwrap().id)
+        .collect::<Vec<_>>();
+    mpd.reorder_queue(&ids, &[ids[1], ids[2], ids[0]]).unwrap();
+    mpd.update_loop();
+    mpd.clear_queue().unwrap();
+    assert!(server.state().queue.is_empty());
+
+    mpd.undo().unwrap();
+    assert_eq!(
+        server.state().queue_files(),
+        ["a/two.flac", "b/three.flac", "a/one.flac"]
+    );
+    mpd.undo().unwrap();
+    assert_eq!(server.state().queue_files(), original);
+
+    mpd.redo().unwrap();
+    assert_eq!(
+        server.state().queue_files(),
+        ["a/two.flac", "b/three.flac", "a/one.flac"]
+    );
+
+    mpd.set_history_depth(1);
+    mpd.shuffle_queue(None).unwrap();
+    mpd.undo().unwrap();
+    mpd.undo().unwrap();
+    assert_eq!(
+        server.state().queue_files(),
+        ["a/two.flac", "b/three.flac", "a/one.flac"]
+    );
+}
+
+#[test]
+fn changes_reuse_the_fetched_queue() {
+    let server = MockServer::with_library(library()).unwrap();
+    let mut mpd = connect(&server);
+    fill_queue(&mut mpd);
+    let queue = mpd.get_queue();
+    let ids = queue
+        .iter()
+        .map(|song| song.place.unwrap().id)
+        .collect::<Vec<_>>();
+
+    // The queue is only fetched again once the status shows a new version of it
+    server.state().commands.clear();
+    mpd.delete_ids([ids[0]]).unwrap();
+    mpd.update_loop();
+    mpd.get_queue();
+    mpd.delete_ids([ids[1]]).unwrap();
+    let fetches = server
+        .state()
+        .commands
+        .iter()
+        .filter(|command| *command == "playlistinfo")
+        .count();
+    assert_eq!(fetches, 1);
+
+    mpd.undo().unwrap();
+    assert_eq!(server.state().queue_files(), ["a/two.flac", "b/three.flac"]);
+}
+
+#[test]
+fn failed_changes_are_not_undone() {
+    let server = MockServer::with_library(library()).unwrap();
+    let mut mpd = connect(&server);
+    fill_queue(&mut mpd);
+    mpd.clear_queue().unwrap();
+    mpd.undo().unwrap();
+    let original = ["a/one.flac", "a/two.flac", "b/three.flac"];
+    assert_eq!(server.state().queue_files(), original);
+
+    // Nothing was added, so the clear that was undone can still be redone
+    assert!(mpd.add_files(["missing.flac".to_owned()]).is_err());
+    assert!(mpd.redo().unwrap());
+    assert!(server.state().queue.is_empty());
+    mpd.undo().unwrap();
+
+    // This one stopped after adding the first file, which can be undone
+    assert!(mpd
+        .add_files(["a/one.flac".to_owned(), "missing.flac".to_owned()])
+        .is_err());
+    assert_eq!(server.state().queue.len(), 4);
+    assert!(mpd.undo().unwrap());
+    assert_eq!(server.state().queue_files(), original);
+}
+
+#[test]
+fn partial_queue_and_confirm_sections_keep_defaults() {
+    let conf = Config::from_yaml("queue: {}\nconfirm:\n  delete_songs: true\n").unwrap();
+    assert_eq!(conf.queue.history_depth, 50);
+    assert!(conf.confirm.delete_songs);
+    assert!(conf.confirm.clear_queue);
+    assert!(!conf.confirm.crop_queue);
+
+    let conf = Config::from_yaml("queue:\n  history_depth: 5\n").unwrap();
+    assert_eq!(conf.queue.history_depth, 5);
+    assert!(conf.confirm.delete_playlist);
+}
+
+fn queue(server: &MockServer) -> Vec<String> {
+    server
+        .state()
+        .queue_files()
+        .into_iter()
+        .map(str::to_owned)
+        .collect()
+}
+
+fn keys(app: &mut App, keys: &str) {
+    for char in keys.chars() {
+        app.handle_event(&Event::Key(KeyEvent::new(
+            KeyCode::Char(char),
+            KeyModifiers::NONE,
+        )));
+    }
+}
+
+#[test]
+fn random_mode_is_separate_from_shuffling() {
+    let songs = (0..8)
+        .map(|idx| song(&format!("{idx}.flac"), "Song", "Artist", 60))
+        .collect();
+    let server = MockServer::with_library(songs).unwrap();
+    let mut mpd = connect(&server);
+    fill_queue(&mut mpd);
+    let mut app: App = App::new(Config::default(), mpd);
+    app.update();
+    let original = queue(&server);
+
+    keys(&mut app, "z");
+    assert!(server.state().random);
+    assert_eq!(server.state().queue_files(), original);
+    app.update();
+    assert_eq!(app.mpd.get_random(), Some(true));
+
+    keys(&mut app, "Z");
+    assert_ne!(server.state().queue_files(), original);
+    assert_eq!(
+        app.toasts.shown().last().unwrap().text,
+        "Shuffled the queue"
+    );
+
+    // Only what comes after the playing song moves
+    app.mpd.play_position(2).unwrap();
+    app.update();
+    let before = queue(&server);
+    keys(&mut app, "\\Z");
+    let after = queue(&server);
+    assert_eq!(after[..3], before[..3]);
+    assert_ne!(after, before);
+    assert_eq!(
+        app.toasts.shown().last().unwrap().text,
+        "Shuffled 5 upcoming songs"
+    );
+
+    // And only the selected range
+    app.update();
+    keys(&mut app, "Vjjj\\z");
+    let shuffled = queue(&server);
+    assert_eq!(shuffled[4..], after[4..]);
+    assert_eq!(app.toasts.shown().last().unwrap().text, "Shuffled 4 songs");
+}
+
+#[test]
+fn volume_stays_in_range_and_mutes() {
+    let server = MockServer::with_library(library()).unwrap();
+    let mut mpd = connect(&server);
+
+    mpd.set_volume(98).unwrap();
+    mpd.change_volume(5).unwrap();
+    assert_eq!(server.state().volume, 100);
+    mpd.change_volume(-200).unwrap();
+    assert_eq!(server.state().volume, 0);
+
+    mpd.set_volume(40).unwrap();
+    mpd.toggle_mute().unwrap();
+    assert_eq!(server.state().volume, 0);
+    assert!(mpd.is_muted());
+    mpd.update_loop();
+    mpd.toggle_mute().unwrap();
+    assert_eq!(server.state().volume, 40);
+    assert!(!mpd.is_muted());
+
+    // Without a mixer MPD reports -1 and nothing is sent
+    server.state().volume = -1;
+    mpd.update_loop();
+    assert!(!mpd.has_mixer());
+    assert_eq!(mpd.get_volume(), None);
+    let sent = server.state().commands.len();
+    assert!(matches!(mpd.change_volume(10), Err(MpdError::NoMixer)));
+    assert!(matches!(mpd.toggle_mute(), Err(MpdError::NoMixer)));
+    assert_eq!(server.state().commands.len(), sent);
+}
+
+#[test]
+fn volume_keys_use_configured_steps() {
+    let server = MockServer::with_library(library()).unwrap();
+    let mut app: App = App::new(Config::default(), connect(&server));
+    app.update();
+
+    keys(&mut app, "+");
+    assert_eq!(server.state().volume, 55);
+    app.handle_event(&Event::Key(KeyEvent::new(
+        KeyCode::Char('-'),
+        KeyModifiers::ALT,
+    )));
+    assert_eq!(server.state().volume, 54);
+
+    // The slider takes the cursor keys until it is closed
+    keys(&mut app, "vk");
+    assert_eq!(server.state().volume, 59);
+    keys(&mut app, "G");
+    assert_eq!(server.state().volume, 0);
+    keys(&mut app, "gg");
+    assert_eq!(server.state().volume, 100);
+    keys(&mut app, "q");
+    assert!(!app.volume_slider);
+    assert!(!app.quit);
+
+    keys(&mut app, "M");
+    assert_eq!(server.state().volume, 0);
+    keys(&mut app, "M");
+    assert_eq!(server.state().volume, 100);
+}
+
+#[test]
+fn single_and_consume_cycle_through_oneshot() {
+    let server = MockServer::with_library(library()).unwrap();
+    let mut mpd = connect(&server);
+
+    mpd.cycle_single().unwrap();
+    assert_eq!(server.state().single, OneshotMode::On);
+    mpd.cycle_single().unwrap();
+    assert_eq!(server.state().single, OneshotMode::Oneshot);
+    assert_eq!(mpd.get_single(), Some(OneshotMode::Oneshot));
+    mpd.cycle_single().unwrap();
+    assert_eq!(server.state().single, OneshotMode::Off);
+
+    // Read back from the status, which the crate would report as off, without
+    // asking for it twice
+    server.state().consume = OneshotMode::Oneshot;
+    server.state().commands.clear();
+    mpd.update_loop();
+    assert_eq!(mpd.get_consume(), Some(OneshotMode::Oneshot));
+    assert_eq!(server.state().commands, ["status", "stats"]);
+    mpd.cycle_consume().unwrap();
+    assert_eq!(server.state().consume, OneshotMode::Off);
+
+    // Repeat no longer turns single on along the way
+    mpd.toggle_repeat().unwrap();
+    assert!(server.state().repeat);
+    assert_eq!(server.state().single, OneshotMode::Off);
+}
+
+#[test]
+fn parses_the_status_reply() {
+    let pairs = [
+        ("repeat", "1"),
+        ("single", "oneshot"),
+        ("consume", "1"),
+        ("state", "play"),
+        ("song", "2"),
+        ("songid", "7"),
+        ("elapsed", "61.250"),
+        ("duration", "nan"),
+        ("audio", "44100:24:2"),
+    ];
+    let (status, modes) = parse_status(
+        pairs
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect(),
+    );
+    assert_eq!(status.volume, -1);
+    assert!(status.repeat && !status.single && status.consume);
+    assert_eq!(modes.single, OneshotMode::Oneshot);
+    assert_eq!(modes.consume, OneshotMode::On);
+    assert_eq!(status.state, State::Play);
+    let song = status.song.unwrap();
+    assert_eq!((song.pos, song.id.0), (2, 7));
+    assert_eq!(status.elapsed, Some(Duration::from_millis(61250)));
+    assert_eq!(status.duration, None);
+    assert_eq!(status.audio.unwrap().bits, 24);
+}
+
+#[test]
+fn modes_menu_sets_values_directly() {
+    let server = MockServer::with_library(library()).unwrap();
+    let mut app: App = App::new(Config::default(), connect(&server));
+    app.update();
+
+    keys(&mut app, "O");
+    assert!(app.modes.is_some());
+    // Single is the third row
+    keys(&mut app, "jjhh");
+    assert_eq!(server.state().single, OneshotMode::On);
+    keys(&mut app, "jll");
+    assert_eq!(server.state().consume, OneshotMode::Oneshot);
+    keys(&mut app, "jlll");
+    assert_eq!(server.state().crossfade, 3);
+    app.update();
+    assert_eq!(app.mpd.get_crossfade(), Some(3));
+    let sheet = &app.modes.as_ref().unwrap().sheet;
+    assert!(
+        matches!(&sheet.lines[4], Line::Entry(name, value) if name == "crossfade" && value == "3 s")
+    );
+
+    keys(&mut app, "q");
+    assert!(app.modes.is_none());
+    assert!(!app.quit);
+}
+
+#[test]
+fn modes_menu_picks_values_from_a_list() {
+    let server = MockServer::with_library(library()).unwrap();
+    let mut app: App = App::new(Config::default(), connect(&server));
+    app.update();
+    let press = |app: &mut App, code| app.handle_event(&Event::Key(KeyEvent::from(code)));
+
+    // <Enter> on single lists its values, starting on the current one
+    keys(&mut app, "Ojj");
+    press(&mut app, KeyCode::Enter);
+    let menu = app.modes.as_ref().unwrap();
+    assert_eq!(menu.choosing, Some(Row::Single));
+    assert_eq!(menu.sheet.list.cursor, 0);
+    assert!(
+        matches!(&menu.sheet.lines[2], Line::Entry(nth, value) if nth == "3" && value == "oneshot")
+    );
+    keys(&mut app, "jj");
+    press(&mut app, KeyCode::Enter);
+    assert_eq!(server.state().single, OneshotMode::Oneshot);
+    let menu = app.modes.as_ref().unwrap();
+    assert_eq!(menu.choosing, None);
+    assert_eq!(menu.selected(), Row::Single);
+
+    // A count picks the value by its number without listing them
+    keys(&mut app, "j2");
+    press(&mut app, KeyCode::Enter);
+    assert_eq!(server.state().consume, OneshotMode::On);
+    keys(&mut app, "j5");
+    press(&mut app, KeyCode::Enter);
+    assert_eq!(server.state().crossfade, 5);
+    // Numbers past the last value do nothing
+    keys(&mut app, "99");
+    press(&mut app, KeyCode::Enter);
+    assert_eq!(server.state().crossfade, 5);
+
+    // <Esc> goes back from the values to the modes, then closes the menu
+    press(&mut app, KeyCode::Enter);
+    assert_eq!(app.modes.as_ref().unwrap().choosing, Some(Row::Crossfade));
+    press(&mut app, KeyCode::Esc);
+    assert_eq!(app.modes.as_ref().unwrap().selected(), Row::Crossfade);
+    press(&mut app, KeyCode::Esc);
+    assert!(app.modes.is_none());
+    assert!(!app.quit);
+}
+
+#[test]
+fn failed_commands_show_toasts_and_history() {
+    let server = MockServer::with_library(library()).unwrap();
+    let mut mpd = connect(&server);
+    fill_queue(&mut mpd);
+    let mut app: App = App::new(Config::default(), mpd);
+    app.update();
+
+    // Seeking needs a song, so it is only a warning
+    keys(&mut app, "]");
+    let toast = app.toasts.shown().last().unwrap();
+    assert_eq!(toast.severity, Severity::Warning);
+    assert_eq!(toast.text, "Nothing is playing");
+
+    // The queue changed behind our back, so MPD refuses the position
+    server.state().queue.clear();
+    app.handle_event(&Event::Key(KeyEvent::new(
+        KeyCode::Enter,
+        KeyModifiers::NONE,
+    )));
+    let toast = app.toasts.shown().last().unwrap();
+    assert_eq!(toast.severity, Severity::Error);
+    assert!(toast.text.contains("Bad song index"), "{}", toast.text);
+    assert!(app.mpd.is_connected());
+    assert_eq!(app.toasts.history().len(), 2);
+
+    keys(&mut app, "E");
+    let overlay = app.overlay.as_ref().unwrap();
+    assert_eq!(overlay.title, "Errors");
+    assert!(matches!(&overlay.lines[0], Line::Entry(_, text) if text.contains("Bad song index")));
+}
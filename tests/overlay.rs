@@ -0,0 +1,66 @@
+use crossterm::event::{Event, KeyCode, KeyEvent, KeyModifiers};
+use mpd::Song;
+use rustmpc::modules::{
+    app::{App, View},
+    config::Config,
+    mock::{song, MockServer},
+    mpd::Mpd,
+    overlay::{Line, Overlay},
+};
+
+fn key(code: KeyCode) -> Event {
+    Event::Key(KeyEvent::new(code, KeyModifiers::NONE))
+}
+
+fn value<'a>(overlay: &'a Overlay, name: &str) -> Option<&'a str> {
+    overlay.lines.iter().find_map(|line| match line {
+        Line::Entry(entry, value) if entry == name => Some(value.as_str()),
+        _ => None,
+    })
+}
+
+#[test]
+fn song_info_shows_tags_and_playback() {
+    let tagged = Song {
+        last_mod: Some("2024-03-01T12:00:00Z".to_owned()),
+        tags: vec![
+            ("Album".to_owned(), "First".to_owned()),
+            ("Genre".to_owned(), "Jazz".to_owned()),
+            ("Format".to_owned(), "96000:24:2".to_owned()),
+        ],
+        ..song("a/one.flac", "One", "Alpha", 3725)
+    };
+    let server =
+        MockServer::with_library(vec![tagged, song("b/two.flac", "Two", "Beta", 200)]).unwrap();
+    let mut app: App = App::new(Config::default(), Mpd::new(server.addr()));
+    app.update();
+    app.execute("add artist Alpha").unwrap();
+    app.update();
+    app.handle_event(&key(KeyCode::Enter));
+    app.update();
+
+    app.handle_event(&key(KeyCode::Char('i')));
+    let info = app.overlay.as_ref().unwrap();
+    assert_eq!(value(info, "path"), Some("a/one.flac"));
+    assert_eq!(value(info, "duration"), Some("1:02:05"));
+    assert_eq!(value(info, "last modified"), Some("2024-03-01T12:00:00Z"));
+    assert_eq!(value(info, "format"), Some("96000:24:2"));
+    assert_eq!(value(info, "output"), Some("44100 Hz, 16 bit, 2 channels"));
+    assert_eq!(value(info, "bitrate"), Some("320 kbps"));
+    assert_eq!(value(info, "position"), Some("1 of 1"));
+    assert_eq!(value(info, "priority"), Some("0"));
+    assert_eq!(value(info, "Title"), Some("One"));
+    assert_eq!(value(info, "Genre"), Some("Jazz"));
+    app.handle_event(&key(KeyCode::Char('i')));
+    assert!(app.overlay.is_none());
+
+    // A library song that is not queued has no queue place or playback details
+    app.handle_event(&key(KeyCode::Tab));
+    assert_eq!(app.view, View::Library);
+    app.handle_event(&key(KeyCode::Char('G')));
+    app.handle_event(&key(KeyCode::Char('i')));
+    let info = app.overlay.as_ref().unwrap();
+    assert_eq!(value(info, "path"), Some("b/two.flac"));
+    assert_eq!(value(info, "position"), None);
+    assert_eq!(value(info, "bitrate"), None);
+}
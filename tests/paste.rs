@@ -0,0 +1,143 @@
+use std::path::Path;
+
+use crossterm::event::{Event, KeyCode, KeyEvent, KeyModifiers};
+use rustmpc::modules::{
+    app::App,
+    config::Config,
+    mock::{song, MockServer},
+    mpd::Mpd,
+    paste,
+};
+
+fn key(code: KeyCode) -> Event {
+    Event::Key(KeyEvent::new(code, KeyModifiers::NONE))
+}
+
+fn app() -> (MockServer, App) {
+    app_in("  music_dir: /music\n")
+}
+
+fn app_in(music_dir: &str) -> (MockServer, App) {
+    let server = MockServer::with_library(vec![
+        song("a/one.flac", "One", "Alpha", 180),
+        song("a/two three.flac", "Two", "Beta", 200),
+    ])
+    .unwrap();
+    let conf = Config::from_yaml(&format!("mpd:\n  addr: {}\n{music_dir}", server.addr())).unwrap();
+    let mut app: App = App::new(conf, Mpd::new(server.addr()));
+    app.update();
+    (server, app)
+}
+
+#[test]
+fn lines_become_uris() {
+    let music = Some(Path::new("/music"));
+    assert_eq!(
+        paste::uri("http://radio.example/stream.mp3", music).as_deref(),
+        Some("http://radio.example/stream.mp3")
+    );
+    assert_eq!(
+        paste::uri("/music/a/one.flac", music).as_deref(),
+        Some("a/one.flac")
+    );
+    assert_eq!(
+        paste::uri("file:///music/a/two%20three.flac", music).as_deref(),
+        Some("a/two three.flac")
+    );
+    // Outside the music directory, or without knowing where it is
+    assert_eq!(paste::uri("/tmp/one.flac", music), None);
+    assert_eq!(paste::uri("/music/../etc/passwd", music), None);
+    assert_eq!(paste::uri("/music", music), None);
+    assert_eq!(paste::uri("/music/a/one.flac", None), None);
+    assert_eq!(paste::uri("file://host/music/a/one.flac", music), None);
+    assert_eq!(paste::uri("file:///music/%zz.flac", music), None);
+    // Nothing says what these would be relative to
+    assert_eq!(paste::uri("a/one.flac", music), None);
+    assert_eq!(paste::uri("file://a/one.flac", music), None);
+}
+
+#[test]
+fn prose_is_not_queued() {
+    let music = Some(Path::new("/music"));
+    assert_eq!(paste::uri("Have a listen to this one", music), None);
+    assert_eq!(paste::uri("see: https://example.com/live", music), None);
+    assert_eq!(paste::uri("foo/bar", music), None);
+    let (uris, rejected) = paste::uris("Tracks for tonight:\n/music/a/one.flac\nfoo/bar\n", music);
+    assert_eq!(uris, ["a/one.flac"]);
+    assert_eq!(rejected, ["Tracks for tonight:", "foo/bar"]);
+}
+
+#[test]
+fn pasted_lists_are_split_into_lines() {
+    let (uris, rejected) = paste::uris(
+        "  /music/a/one.flac\r\n\nhttps://example.com/live\n/home/me/song.flac\n",
+        Some(Path::new("/music")),
+    );
+    assert_eq!(uris, ["a/one.flac", "https://example.com/live"]);
+    assert_eq!(rejected, ["/home/me/song.flac"]);
+}
+
+#[test]
+fn pasting_queues_files_after_confirming() {
+    let (_server, mut app) = app();
+    app.handle_event(&Event::Paste(
+        "/music/a/one.flac\nfile:///music/a/two%20three.flac\n".to_owned(),
+    ));
+    assert!(app.overlay.is_some());
+    assert_eq!(
+        app.popup.as_ref().unwrap().title,
+        "Add 2 pasted items to the queue?"
+    );
+    app.handle_event(&key(KeyCode::Char('y')));
+    assert!(app.popup.is_none());
+    assert!(app.overlay.is_none());
+    app.update();
+    let files: Vec<_> = app.queue.songs.iter().map(|song| &song.file).collect();
+    assert_eq!(files, ["a/one.flac", "a/two three.flac"]);
+}
+
+#[test]
+fn denying_a_paste_queues_nothing() {
+    let (_server, mut app) = app();
+    app.handle_event(&Event::Paste("/music/a/one.flac".to_owned()));
+    app.handle_event(&key(KeyCode::Char('n')));
+    assert!(app.popup.is_none());
+    assert!(app.overlay.is_none());
+    app.update();
+    assert!(app.queue.songs.is_empty());
+}
+
+#[test]
+fn pastes_outside_the_music_directory_only_warn() {
+    let (_server, mut app) = app();
+    app.handle_event(&Event::Paste("/tmp/one.flac".to_owned()));
+    assert!(app.popup.is_none());
+    assert!(app.overlay.is_none());
+    assert_eq!(
+        app.toasts.history()[0].text,
+        "Nothing pasted is a URL or a file in the music directory"
+    );
+}
+
+#[test]
+fn pasting_paths_needs_the_music_directory() {
+    let (_server, mut app) = app_in("");
+    app.handle_event(&Event::Paste("/music/a/one.flac".to_owned()));
+    assert!(app.popup.is_none());
+    assert_eq!(
+        app.toasts.history()[0].text,
+        "Only URLs can be pasted while mpd.music_dir is not set"
+    );
+    app.handle_event(&Event::Paste("http://radio.example/live".to_owned()));
+    assert!(app.popup.is_some());
+}
+
+#[test]
+fn pasting_into_the_command_line_types_it() {
+    let (_server, mut app) = app();
+    app.handle_event(&key(KeyCode::Char(':')));
+    app.handle_event(&Event::Paste("bind z\nswitch_view\n".to_owned()));
+    assert!(app.popup.is_none());
+    app.handle_event(&key(KeyCode::Enter));
+    assert_eq!(app.command_line.history(), ["bind z switch_view"]);
+}
@@ -0,0 +1,55 @@
+use anyhow::Result;
+use crossterm::style::{Attribute, Color};
+use rustmpc::modules::{
+    popup::Popup,
+    ui::{Overflow, Rect, Render},
+};
+
+// Keeps the text drawn into each rect, leaving out the border's single cells
+#[derive(Default)]
+struct Texts(Vec<(Rect, String)>);
+
+impl Render for Texts {
+    fn set_background(&mut self, _rect: Rect, _color: Color) {}
+    fn set_foreground(&mut self, _rect: Rect, _color: Color) {}
+    fn set_attributes(&mut self, _rect: Rect, _attr: Attribute) {}
+    fn set_text(&mut self, rect: Rect, text: &str, _overflow: Overflow) {
+        if rect.width > 1 {
+            self.0.push((rect, text.to_owned()));
+        }
+    }
+    fn render_frame(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+const SCREEN: Rect = Rect {
+    x: 0,
+    y: 0,
+    width: 80,
+    height: 24,
+};
+
+#[test]
+fn popups_are_sized_by_display_width() {
+    // 30 wide characters take 60 cells, though they are 90 bytes
+    let title = "曲".repeat(30);
+    let popup = Popup::message(title.as_str(), "", ());
+    let mut texts = Texts::default();
+    popup.draw(&mut texts, SCREEN);
+    let (rect, _) = texts.0[0];
+    assert_eq!(rect.width, 64);
+    assert_eq!(texts.0[1].0.width, 62);
+    assert_eq!(texts.0[1].1, title);
+}
+
+#[test]
+fn long_input_shows_its_end() {
+    let popup = Popup::input("Rename", format!("{}é末", "a".repeat(100)), ());
+    let mut texts = Texts::default();
+    popup.draw(&mut texts, SCREEN);
+    let (rect, body) = &texts.0[2];
+    assert_eq!(rect.width, 78);
+    assert!(body.ends_with("aé末_"));
+    assert_eq!(body.chars().count(), 77);
+}
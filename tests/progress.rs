@@ -0,0 +1,72 @@
+use std::time::Duration;
+
+use rustmpc::modules::{
+    config::Progress,
+    progress::{bar, time_label, TimeFormat},
+};
+
+fn secs(secs: u64) -> Duration {
+    Duration::from_secs(secs)
+}
+
+#[test]
+fn smooth_bar_fills_eighths_of_a_cell() {
+    let conf = Progress::default();
+    let (text, played) = bar(&conf, 10, Duration::from_millis(2500), Some(secs(10)));
+    assert_eq!(text, "██▌───────");
+    assert_eq!(played, 0..3);
+
+    let (text, played) = bar(&conf, 4, secs(0), Some(secs(10)));
+    assert_eq!(text, "────");
+    assert!(played.is_empty());
+    let (text, _) = bar(&conf, 4, secs(12), Some(secs(10)));
+    assert_eq!(text, "████");
+
+    let conf = Progress {
+        smooth: false,
+        fill: '=',
+        empty: ' ',
+        ..Progress::default()
+    };
+    let (text, played) = bar(&conf, 10, Duration::from_millis(2500), Some(secs(10)));
+    assert_eq!(text, "===       ");
+    assert_eq!(played, 0..3);
+}
+
+#[test]
+fn streams_bounce_instead_of_dividing_by_zero() {
+    let conf = Progress::default();
+    for total in [None, Some(Duration::ZERO)] {
+        let starts: Vec<_> = (0..10)
+            .map(|elapsed| bar(&conf, 12, secs(elapsed), total).1.start)
+            .collect();
+        assert_eq!(starts, [0, 1, 2, 3, 4, 5, 6, 7, 8, 9]);
+        // Back from the far end
+        let (text, played) = bar(&conf, 12, secs(13), total);
+        assert_eq!(played, 7..9);
+        assert_eq!(text, "───────██───");
+    }
+}
+
+#[test]
+fn time_formats() {
+    let (elapsed, total) = (secs(75), Some(secs(200)));
+    assert_eq!(time_label(TimeFormat::Elapsed, elapsed, total), "1:15");
+    assert_eq!(time_label(TimeFormat::Remaining, elapsed, total), "-2:05");
+    assert_eq!(
+        time_label(TimeFormat::ElapsedTotal, elapsed, total),
+        "1:15/3:20"
+    );
+    assert_eq!(time_label(TimeFormat::Percent, elapsed, total), "37%");
+
+    // Hours on both sides once the track is that long
+    assert_eq!(
+        time_label(TimeFormat::ElapsedTotal, elapsed, Some(secs(3 * 3600 + 5))),
+        "0:01:15/3:00:05"
+    );
+
+    for format in [TimeFormat::Remaining, TimeFormat::Percent] {
+        assert_eq!(time_label(format, secs(3700), None), "1:01:40");
+        assert_eq!(time_label(format, elapsed, Some(Duration::ZERO)), "1:15");
+    }
+}
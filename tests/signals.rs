@@ -0,0 +1,23 @@
+use rustmpc::modules::signals::Signals;
+use signal_hook::{
+    consts::{SIGCONT, SIGTERM, SIGTSTP},
+    low_level::raise,
+};
+
+#[test]
+fn signals_are_left_for_the_main_loop() {
+    let signals = Signals::register().unwrap();
+    assert!(!signals.terminated());
+    assert!(!signals.take_suspend());
+
+    // Caught instead of stopping or killing the test process
+    raise(SIGTSTP).unwrap();
+    assert!(signals.take_suspend());
+    assert!(!signals.take_suspend());
+
+    raise(SIGCONT).unwrap();
+    assert!(signals.take_resume());
+
+    raise(SIGTERM).unwrap();
+    assert!(signals.terminated());
+}
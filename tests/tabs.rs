@@ -0,0 +1,98 @@
+use crossterm::event::{
+    Event, KeyCode, KeyEvent, KeyModifiers, MouseButton, MouseEvent, MouseEventKind,
+};
+use rustmpc::modules::{
+    app::{App, View},
+    config::Config,
+    mock::{song, MockServer},
+    mpd::Mpd,
+    tabs,
+};
+
+fn key(code: KeyCode, modifiers: KeyModifiers) -> Event {
+    Event::Key(KeyEvent::new(code, modifiers))
+}
+
+fn click(column: u16, row: u16) -> Event {
+    Event::Mouse(MouseEvent {
+        kind: MouseEventKind::Down(MouseButton::Left),
+        column,
+        row,
+        modifiers: KeyModifiers::NONE,
+    })
+}
+
+fn app(conf: Config) -> (MockServer, App) {
+    let library = (0..5)
+        .map(|idx| song(&format!("{idx}.flac"), "Song", "Artist", 60))
+        .collect();
+    let server = MockServer::with_library(library).unwrap();
+    let mut app: App = App::new(conf, Mpd::new(server.addr()));
+    for song in app.mpd.get_all_songs() {
+        app.mpd.push_into_queue(song).unwrap();
+    }
+    app.update();
+    (server, app)
+}
+
+#[test]
+fn keys_switch_between_tabs() {
+    let (_server, mut app) = app(Config::default());
+    assert_eq!(app.view, View::Queue);
+
+    app.handle_event(&key(KeyCode::Tab, KeyModifiers::NONE));
+    assert_eq!(app.view, View::Library);
+    app.handle_event(&key(KeyCode::BackTab, KeyModifiers::SHIFT));
+    app.handle_event(&key(KeyCode::BackTab, KeyModifiers::SHIFT));
+    assert_eq!(app.view, View::Lyrics);
+
+    app.handle_event(&key(KeyCode::Char('3'), KeyModifiers::ALT));
+    assert_eq!(app.view, View::Playlists);
+    // There is no ninth tab
+    app.handle_event(&key(KeyCode::Char('9'), KeyModifiers::ALT));
+    assert_eq!(app.view, View::Playlists);
+}
+
+#[test]
+fn views_keep_their_cursor() {
+    let (_server, mut app) = app(Config::default());
+    app.handle_event(&key(KeyCode::Char('j'), KeyModifiers::NONE));
+    app.handle_event(&key(KeyCode::Char('j'), KeyModifiers::NONE));
+    assert_eq!(app.queue.list.cursor, 2);
+
+    app.handle_event(&key(KeyCode::Char('2'), KeyModifiers::ALT));
+    app.handle_event(&key(KeyCode::Char('j'), KeyModifiers::NONE));
+    assert_eq!(app.library.list.cursor, 1);
+    app.handle_event(&key(KeyCode::Char('1'), KeyModifiers::ALT));
+    assert_eq!(app.queue.list.cursor, 2);
+}
+
+#[test]
+fn configured_tabs_and_clicks() {
+    let conf = Config::from_yaml("ui:\n  tabs: [lyrics, queue, lyrics]\n").unwrap();
+    let (_server, mut app) = app(conf);
+    assert_eq!(app.conf.ui.tabs, [View::Lyrics, View::Queue]);
+    assert_eq!(app.view, View::Lyrics);
+
+    app.handle_event(&key(KeyCode::Tab, KeyModifiers::NONE));
+    assert_eq!(app.view, View::Queue);
+    app.handle_event(&key(KeyCode::Tab, KeyModifiers::NONE));
+    assert_eq!(app.view, View::Lyrics);
+
+    // " 1 Lyrics " then " 2 Queue "
+    let layout = tabs::layout(&app.conf.ui.tabs, 0);
+    assert_eq!(layout[1].2, 10);
+    app.handle_event(&click(12, 0));
+    assert_eq!(app.view, View::Queue);
+    app.handle_event(&click(3, 5));
+    assert_eq!(app.view, View::Queue);
+    app.handle_event(&click(3, 0));
+    assert_eq!(app.view, View::Lyrics);
+
+    // Hidden views are left out of the cycle, and nothing empties the bar
+    assert_eq!(tabs::normalize(&[]), tabs::DEFAULT);
+    assert_eq!(
+        tabs::cycle(&[View::Queue, View::Lyrics], View::Library, 1),
+        Some(View::Queue)
+    );
+}
@@ -0,0 +1,255 @@
+use std::{
+    fs,
+    os::unix::fs::{MetadataExt, PermissionsExt},
+    path::PathBuf,
+};
+
+use crossterm::event::{Event, KeyCode, KeyEvent, KeyModifiers};
+use lofty::{file::TaggedFileExt, prelude::Accessor};
+use mpd::Song;
+use rustmpc::modules::{
+    app::{App, View},
+    config::Config,
+    mock::{song, MockServer},
+    mpd::Mpd,
+    overlay::Line,
+    tags::{write, Change, Field, TagEditor},
+};
+
+fn scratch_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("rustmpc-{name}-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn key(code: KeyCode) -> Event {
+    Event::Key(KeyEvent::new(code, KeyModifiers::NONE))
+}
+
+// STREAMINFO, then Vorbis comments as the last block, then a few bytes of "audio"
+fn flac(comments: &[&str]) -> Vec<u8> {
+    let mut block = vec![];
+    block.extend(3u32.to_le_bytes());
+    block.extend(b"ref");
+    block.extend((comments.len() as u32).to_le_bytes());
+    for comment in comments {
+        block.extend((comment.len() as u32).to_le_bytes());
+        block.extend(comment.as_bytes());
+    }
+    let mut data = b"fLaC\x00\x00\x00\x22".to_vec();
+    data.extend([0; 34]);
+    data.push(0x84);
+    data.extend(&(block.len() as u32).to_be_bytes()[1..]);
+    data.extend(block);
+    data.extend(b"AUDIO");
+    data
+}
+
+// Two silent frames of MPEG-1 layer III at 128 kbps and 44.1 kHz, 417 bytes each
+fn mp3() -> Vec<u8> {
+    let mut frame = vec![0xff, 0xfb, 0x90, 0x00];
+    frame.resize(417, 0);
+    frame.repeat(2)
+}
+
+// One Ogg page, with the CRC Ogg readers check
+fn ogg_page(kind: u8, seq: u32, packet: &[u8]) -> Vec<u8> {
+    let mut page = b"OggS\0".to_vec();
+    page.push(kind);
+    page.extend(0u64.to_le_bytes());
+    page.extend(1u32.to_le_bytes());
+    page.extend(seq.to_le_bytes());
+    page.extend([0; 4]);
+    let lacing: Vec<u8> = (0..=packet.len() / 255)
+        .map(|idx| (packet.len() - idx * 255).min(255) as u8)
+        .collect();
+    page.push(lacing.len() as u8);
+    page.extend(lacing);
+    page.extend(packet);
+    let crc = page.iter().fold(0u32, |crc, byte| {
+        (0..8).fold(crc ^ (u32::from(*byte) << 24), |crc, _| {
+            (crc << 1) ^ if crc & 1 << 31 != 0 { 0x04c1_1db7 } else { 0 }
+        })
+    });
+    page[22..26].copy_from_slice(&crc.to_le_bytes());
+    page
+}
+
+// The Opus header and comment pages, then one empty page to end the stream
+fn opus(comments: &[&str]) -> Vec<u8> {
+    let mut head = b"OpusHead\x01\x02".to_vec();
+    head.extend([0; 2]);
+    head.extend(48000u32.to_le_bytes());
+    head.extend([0; 3]);
+    let mut tags = b"OpusTags".to_vec();
+    tags.extend(3u32.to_le_bytes());
+    tags.extend(b"ref");
+    tags.extend((comments.len() as u32).to_le_bytes());
+    for comment in comments {
+        tags.extend((comment.len() as u32).to_le_bytes());
+        tags.extend(comment.as_bytes());
+    }
+    let mut data = ogg_page(0x02, 0, &head);
+    data.extend(ogg_page(0, 1, &tags));
+    data.extend(ogg_page(0x04, 2, &[]));
+    data
+}
+
+fn contains(data: &[u8], text: &str) -> bool {
+    data.windows(text.len())
+        .any(|window| window == text.as_bytes())
+}
+
+fn change(file: &str, field: Field, old: Option<&str>, new: Option<&str>) -> Change {
+    Change {
+        file: file.to_owned(),
+        field,
+        old: old.map(str::to_owned),
+        new: new.map(str::to_owned),
+    }
+}
+
+#[test]
+fn writes_tags_of_each_format() {
+    let music = scratch_dir("tags");
+    fs::write(
+        music.join("one.flac"),
+        flac(&["TITLE=One", "artist=Alpha", "COMMENT=kept"]),
+    )
+    .unwrap();
+    fs::write(music.join("two.mp3"), mp3()).unwrap();
+    fs::write(music.join("three.opus"), opus(&["TITLE=Three"])).unwrap();
+    fs::write(music.join("notes.txt"), b"not audio").unwrap();
+    let flac_path = music.join("one.flac");
+    fs::set_permissions(&flac_path, fs::Permissions::from_mode(0o640)).unwrap();
+    let inode = fs::metadata(&flac_path).unwrap().ino();
+
+    let (written, errors) = write(
+        &music,
+        &[
+            change("one.flac", Field::Artist, Some("Alpha"), Some("Beta")),
+            change("one.flac", Field::Title, Some("One"), None),
+            change("two.mp3", Field::Album, None, Some("First")),
+            change("two.mp3", Field::Track, None, Some("2")),
+            change("three.opus", Field::Genre, None, Some("Jazz")),
+            change("notes.txt", Field::Genre, None, Some("Jazz")),
+        ],
+    );
+    assert_eq!(written, ["one.flac", "two.mp3", "three.opus"]);
+    assert_eq!(errors.len(), 1);
+    assert!(format!("{:#}", errors[0]).contains("notes.txt"));
+
+    // Written in place, so the file is the same one with the same mode
+    let meta = fs::metadata(&flac_path).unwrap();
+    assert_eq!(meta.ino(), inode);
+    assert_eq!(meta.permissions().mode() & 0o777, 0o640);
+    assert_eq!(fs::read_dir(&music).unwrap().count(), 4);
+
+    let data = fs::read(&flac_path).unwrap();
+    assert!(data.starts_with(b"fLaC"));
+    assert!(data.ends_with(b"AUDIO"));
+    assert!(contains(&data, "ARTIST=Beta"));
+    assert!(contains(&data, "COMMENT=kept"));
+    assert!(!contains(&data, "Alpha"));
+    assert!(!contains(&data, "TITLE="));
+
+    let file = lofty::read_from_path(music.join("two.mp3")).unwrap();
+    let tag = file.primary_tag().unwrap();
+    assert_eq!(tag.album().as_deref(), Some("First"));
+    assert_eq!(tag.track(), Some(2));
+
+    let data = fs::read(music.join("three.opus")).unwrap();
+    assert!(contains(&data, "TITLE=Three"));
+    assert!(contains(&data, "GENRE=Jazz"));
+    fs::remove_dir_all(music).unwrap();
+}
+
+#[test]
+fn batch_edits_keep_untouched_fields() {
+    let one = Song {
+        tags: vec![("Album".to_owned(), "First".to_owned())],
+        ..song("a/one.flac", "One", "Alpha", 180)
+    };
+    let two = Song {
+        tags: vec![("Album".to_owned(), "First".to_owned())],
+        ..song("a/two.flac", "Two", "Beta", 200)
+    };
+    let mut editor = TagEditor::new(vec![one, two]);
+    assert_eq!(editor.shared(Field::Album), Some("First"));
+    assert_eq!(editor.shared(Field::Artist), None);
+    assert!(editor.changes().is_empty());
+
+    editor.set(Field::Artist, "Alpha");
+    editor.set(Field::Genre, "");
+    assert_eq!(
+        editor.changes(),
+        [change(
+            "a/two.flac",
+            Field::Artist,
+            Some("Beta"),
+            Some("Alpha")
+        )]
+    );
+    editor.keep(Field::Artist);
+    assert!(editor.changes().is_empty());
+}
+
+#[test]
+fn edits_selected_songs_after_a_preview() {
+    let music = scratch_dir("tag-editor");
+    fs::create_dir_all(music.join("a")).unwrap();
+    fs::write(music.join("a/one.flac"), flac(&["TITLE=One"])).unwrap();
+    fs::write(music.join("a/two.flac"), flac(&["TITLE=Two"])).unwrap();
+    let server = MockServer::with_library(vec![
+        song("a/one.flac", "One", "Alpha", 180),
+        song("a/two.flac", "Two", "Beta", 200),
+    ])
+    .unwrap();
+    let conf = Config::from_yaml(&format!(
+        "mpd:\n  addr: {}\n  music_dir: {}\n",
+        server.addr(),
+        music.display()
+    ))
+    .unwrap();
+    let mut app: App = App::new(conf, Mpd::new(server.addr()));
+    app.update();
+    app.handle_event(&key(KeyCode::Tab));
+    assert_eq!(app.view, View::Library);
+    app.handle_event(&Event::Key(KeyEvent::new(
+        KeyCode::Char('a'),
+        KeyModifiers::CONTROL,
+    )));
+    app.handle_event(&key(KeyCode::Char('e')));
+    assert_eq!(app.tags.as_ref().unwrap().songs.len(), 2);
+
+    // Album is the third field
+    app.handle_event(&key(KeyCode::Char('j')));
+    app.handle_event(&key(KeyCode::Char('j')));
+    app.handle_event(&key(KeyCode::Enter));
+    assert!(app.popup.is_some());
+    for char in "Rise".chars() {
+        app.handle_event(&key(KeyCode::Char(char)));
+    }
+    app.handle_event(&key(KeyCode::Enter));
+    assert!(app.popup.is_none());
+
+    app.handle_event(&key(KeyCode::Char('w')));
+    let preview = app.overlay.as_ref().unwrap();
+    assert!(matches!(&preview.lines[0], Line::Section(file) if file == "a/one.flac"));
+    assert!(
+        matches!(&preview.lines[1], Line::Entry(field, diff) if field == "album" && diff == "(none) → Rise")
+    );
+    assert_eq!(app.popup.as_ref().unwrap().title, "Write tags to 2 files?");
+    app.handle_event(&key(KeyCode::Char('y')));
+    assert!(app.tags.is_none());
+    assert!(app.overlay.is_none());
+
+    for file in ["a/one.flac", "a/two.flac"] {
+        assert!(contains(&fs::read(music.join(file)).unwrap(), "ALBUM=Rise"));
+    }
+    let commands = server.state().commands.clone();
+    assert!(commands.contains(&"update \"a/one.flac\"".to_owned()));
+    assert!(commands.contains(&"update \"a/two.flac\"".to_owned()));
+    fs::remove_dir_all(music).unwrap();
+}